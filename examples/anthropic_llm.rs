@@ -16,7 +16,8 @@ async fn main() {
             api_key: env::var("ANTHROPIC_API_KEY").expect("Must set ANTHROPIC_API_KEY env var"),
             model: "claude-3-5-haiku-latest".to_string(),
             max_tokens: 1024
-        }
+        },
+        usage: Default::default()
     };
     let mut buffer = String::new();
     std::io::stdin().read_line(&mut buffer).unwrap();
@@ -32,7 +33,9 @@ async fn main() {
             Message::User { parts: vec![
                 UserMessagePart { content: lliminal::llm::UserMessageContent::Text { text: buffer } }
             ] }
-        ]
+        ],
+        tools: vec![],
+        response_format: None
     };
 
     let mut response = client.complete(&request).await;
@@ -53,6 +56,9 @@ fn print_message(message: &Message) {
             for part in parts {
                 match part.content.clone() {
                     lliminal::llm::UserMessageContent::Text { text } => println!("User: {}", text),
+                    lliminal::llm::UserMessageContent::Image { media_type, .. } => println!("User image: {}", media_type),
+                    lliminal::llm::UserMessageContent::Document { media_type, .. } => println!("User document: {}", media_type),
+                    lliminal::llm::UserMessageContent::ToolResult { content, .. } => println!("Tool result: {}", content),
                 }
             }
         },
@@ -60,6 +66,7 @@ fn print_message(message: &Message) {
             for part in parts {
                 match part.content.clone() {
                     lliminal::llm::AssistantMessageContent::Text { text } => println!("Assistant: {}", text),
+                    lliminal::llm::AssistantMessageContent::ToolUse { name, input, .. } => println!("Tool call: {} {}", name, input),
                 }
             }
         }
@@ -0,0 +1,341 @@
+use std::{collections::BTreeMap, pin::pin, sync::Arc};
+
+use futures::{channel::mpsc, future::BoxFuture, FutureExt, StreamExt};
+use serde_json::Value;
+
+use super::{context::ContextManager, AssistantMessageContent, CompletionRequest, LlmClient, LlmError, Message, Result, SystemPrompt, ToolDefinition, Usage, UserMessageContent, UserMessagePart};
+
+/// The default number of completions an agent requests for a single run
+pub const DEFAULT_MAX_STEPS: usize = 10;
+
+/// The output of a tool call, either the result or an error message; both are shown to the model
+pub type ToolOutput = std::result::Result<String, String>;
+
+type ToolHandler = Arc<dyn Fn(Value) -> BoxFuture<'static, ToolOutput> + Send + Sync>;
+
+/// Decides whether a tool call is executed, given the name of the tool and the requested input
+pub type ToolApprover = Arc<dyn Fn(String, Value) -> BoxFuture<'static, ToolApproval> + Send + Sync>;
+
+/// The decision about a tool call which the model requested
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ToolApproval {
+    /// Execute the call with the given input, which may differ from the requested one
+    Approve { input: Value },
+    /// Do not execute the call; the model is told so, with the reason if there is one
+    Deny { reason: Option<String> }
+}
+
+/// A set of tools which can be called by their name
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: BTreeMap<String, RegisteredTool>
+}
+
+#[derive(Clone)]
+struct RegisteredTool {
+    definition: ToolDefinition,
+    handler: ToolHandler
+}
+
+impl ToolRegistry {
+    /// Register an async handler for a tool, replacing any tool with the same name
+    pub fn register<F, Fut>(&mut self, definition: ToolDefinition, handler: F)
+        where F: Fn(Value) -> Fut + Send + Sync + 'static, Fut: Future<Output = ToolOutput> + Send + 'static
+    {
+        let handler: ToolHandler = Arc::new(move |input| handler(input).boxed());
+        self.tools.insert(definition.name.clone(), RegisteredTool { definition, handler });
+    }
+
+    /// The definitions of all registered tools, ordered by name
+    pub fn definitions(&self) -> Vec<ToolDefinition> {
+        self.tools.values().map(|tool| tool.definition.clone()).collect()
+    }
+
+    /// Call the tool with the given name
+    pub async fn call(&self, name: &str, input: Value) -> ToolOutput {
+        match self.tools.get(name) {
+            Some(tool) => (tool.handler)(input).await,
+            None => Err(format!("Unknown tool: {name}"))
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.tools.contains_key(name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+}
+
+/// Progress of an agent run
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AgentEvent {
+    /// The whole conversation, including the response which is currently generated
+    Messages(Vec<Message>),
+    /// A tool call requested by the model is about to be executed
+    ToolCallStarted { id: String, name: String, input: Value },
+    /// A tool call has been executed
+    ToolCallFinished { id: String, name: String, output: ToolOutput },
+    /// The tokens used in this run so far, including requests to summarize the context
+    Usage(Usage),
+}
+
+/// Completes a conversation and executes the tool calls which the model requests
+///
+/// A run repeatedly sends the conversation to the model, executes all requested tool calls and
+/// sends back their results, until the model answers without tool calls or the step limit is
+/// reached.
+pub struct Agent<C: LlmClient> {
+    pub client: C,
+    pub system: Vec<SystemPrompt>,
+    pub tools: ToolRegistry,
+    /// The maximum number of completions requested in one run
+    pub max_steps: usize,
+    /// Keeps the requests within the context window, if set
+    pub context: Option<ContextManager>,
+    /// Asked before each tool call; all calls are executed if it is not set
+    pub approver: Option<ToolApprover>
+}
+
+impl<C: LlmClient> Agent<C> {
+    pub fn new(client: C) -> Self {
+        Self { client, system: vec![], tools: ToolRegistry::default(), max_steps: DEFAULT_MAX_STEPS, context: None, approver: None }
+    }
+
+    /// Continue the conversation until the model gives a final answer
+    ///
+    /// Progress is reported to `events`; a closed receiver is ignored. Returns the whole
+    /// conversation including all generated messages and tool results.
+    pub async fn run(&mut self, messages: Vec<Message>, events: mpsc::UnboundedSender<AgentEvent>) -> Result<Vec<Message>> {
+        let mut conversation = messages;
+        let initial_usage = self.client.usage();
+        let mut usage = Usage::default();
+        for _ in 0..self.max_steps {
+            let request = CompletionRequest {
+                system: self.system.clone(),
+                messages: conversation.clone(),
+                tools: self.tools.definitions(),
+                response_format: None
+            };
+            let request = match &self.context {
+                Some(context) => context.prepare(&mut self.client, request).await?,
+                None => request
+            };
+            let mut response = pin!(self.client.complete(&request).await);
+            let mut new_messages = Vec::new();
+            while let Some(partial_response) = response.next().await {
+                new_messages = partial_response?;
+                let _ = events.unbounded_send(AgentEvent::Messages([conversation.as_slice(), &new_messages].concat()));
+                self.report_usage(initial_usage, &mut usage, &events);
+            }
+            // The final usage is reported after the last content
+            self.report_usage(initial_usage, &mut usage, &events);
+
+            let tool_calls = tool_calls(&new_messages);
+            conversation.append(&mut new_messages);
+            if tool_calls.is_empty() {
+                return Ok(conversation);
+            }
+
+            let mut results = Vec::new();
+            for (id, name, input) in tool_calls {
+                let approval = match &self.approver {
+                    Some(approver) => approver(name.clone(), input.clone()).await,
+                    None => ToolApproval::Approve { input: input.clone() }
+                };
+                let output = match approval {
+                    ToolApproval::Approve { input: approved_input } => {
+                        // The conversation shows the input with which the tool was actually called
+                        if approved_input != input {
+                            replace_tool_input(&mut conversation, &id, approved_input.clone());
+                        }
+                        let _ = events.unbounded_send(AgentEvent::ToolCallStarted { id: id.clone(), name: name.clone(), input: approved_input.clone() });
+                        self.tools.call(&name, approved_input).await
+                    },
+                    ToolApproval::Deny { reason: Some(reason) } => Err(format!("The user denied the tool call: {reason}")),
+                    ToolApproval::Deny { reason: None } => Err("The user denied the tool call".to_string())
+                };
+                let _ = events.unbounded_send(AgentEvent::ToolCallFinished { id: id.clone(), name, output: output.clone() });
+                results.push(tool_result(id, output));
+            }
+            conversation.push(Message::User { parts: results });
+            let _ = events.unbounded_send(AgentEvent::Messages(conversation.clone()));
+        }
+        Err(LlmError::StepLimitReached)
+    }
+
+    /// Report the usage of this run if it has changed since the last report
+    fn report_usage(&self, initial_usage: Usage, usage: &mut Usage, events: &mpsc::UnboundedSender<AgentEvent>) {
+        let current_usage = self.client.usage() - initial_usage;
+        if current_usage != *usage {
+            *usage = current_usage;
+            let _ = events.unbounded_send(AgentEvent::Usage(current_usage));
+        }
+    }
+}
+
+fn tool_calls(messages: &[Message]) -> Vec<(String, String, Value)> {
+    messages.iter().flat_map(|message| match message {
+        Message::User { .. } => vec![],
+        Message::Assistant { parts } => parts.iter().filter_map(|part| match &part.content {
+            AssistantMessageContent::ToolUse { id, name, input } => Some((id.clone(), name.clone(), input.clone())),
+            AssistantMessageContent::Text { .. } => None
+        }).collect()
+    }).collect()
+}
+
+fn replace_tool_input(conversation: &mut [Message], tool_use_id: &str, new_input: Value) {
+    let parts = conversation.iter_mut().rev().flat_map(|message| match message {
+        Message::Assistant { parts } => parts.as_mut_slice(),
+        Message::User { .. } => &mut []
+    });
+    for part in parts {
+        if let AssistantMessageContent::ToolUse { id, input, .. } = &mut part.content && id == tool_use_id {
+            *input = new_input;
+            return;
+        }
+    }
+}
+
+fn tool_result(tool_use_id: String, output: ToolOutput) -> UserMessagePart {
+    let (content, is_error) = match output {
+        Ok(content) => (content, false),
+        Err(content) => (content, true)
+    };
+    UserMessagePart { content: UserMessageContent::ToolResult { tool_use_id, content, is_error } }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::{channel::mpsc, FutureExt, StreamExt};
+    use serde_json::json;
+
+    use crate::llm::{base::test::TestLlmClient, AssistantMessageContent, AssistantMessagePart, LlmError, Message, ToolDefinition, UserMessageContent, UserMessagePart};
+
+    use super::{Agent, AgentEvent, ToolApproval};
+
+    fn user_message(text: &str) -> Message {
+        Message::User { parts: vec![UserMessagePart { content: UserMessageContent::Text { text: text.to_string() } }] }
+    }
+
+    fn tool_use_response() -> Vec<crate::llm::Result<Vec<Message>>> {
+        vec![Ok(vec![Message::Assistant { parts: vec![
+            AssistantMessagePart {
+                complete: true,
+                content: AssistantMessageContent::ToolUse { id: "call_1".to_string(), name: "add".to_string(), input: json!({ "a": 1, "b": 2 }) }
+            }
+        ] }])]
+    }
+
+    fn agent() -> Agent<TestLlmClient> {
+        let mut agent = Agent::new(TestLlmClient::default());
+        agent.tools.register(
+            ToolDefinition { name: "add".to_string(), description: "Add two numbers".to_string(), input_schema: json!({ "type": "object" }) },
+            |input| async move {
+                match (input["a"].as_i64(), input["b"].as_i64()) {
+                    (Some(a), Some(b)) => Ok((a + b).to_string()),
+                    _ => Err("Invalid input".to_string())
+                }
+            }
+        );
+        agent
+    }
+
+    #[tokio::test]
+    async fn executes_tool_calls() {
+        let mut agent = agent();
+        agent.client.set_response_factory(|request| match request.messages.len() {
+            1 => tool_use_response(),
+            _ => vec![TestLlmClient::response_for_message("The sum is 3".to_string())]
+        });
+        let (sender, receiver) = mpsc::unbounded();
+
+        let conversation = agent.run(vec![user_message("What is 1 + 2?")], sender).await.unwrap();
+
+        assert_eq!(conversation.len(), 4);
+        assert_eq!(conversation[2], Message::User { parts: vec![
+            UserMessagePart { content: UserMessageContent::ToolResult { tool_use_id: "call_1".to_string(), content: "3".to_string(), is_error: false } }
+        ] });
+        assert_eq!(agent.client.requests.len(), 2);
+        assert_eq!(agent.client.requests[0].tools.len(), 1);
+
+        let events = receiver.collect::<Vec<_>>().await;
+        assert!(events.contains(&AgentEvent::ToolCallStarted { id: "call_1".to_string(), name: "add".to_string(), input: json!({ "a": 1, "b": 2 }) }));
+        assert!(events.contains(&AgentEvent::ToolCallFinished { id: "call_1".to_string(), name: "add".to_string(), output: Ok("3".to_string()) }));
+        assert_eq!(events.last(), Some(&AgentEvent::Messages(conversation)));
+    }
+
+    #[tokio::test]
+    async fn stops_at_step_limit() {
+        let mut agent = agent();
+        agent.max_steps = 3;
+        agent.client.set_response_factory(|_| tool_use_response());
+        let (sender, _receiver) = mpsc::unbounded();
+
+        let result = agent.run(vec![user_message("What is 1 + 2?")], sender).await;
+
+        assert_eq!(result, Err(LlmError::StepLimitReached));
+        assert_eq!(agent.client.requests.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn reports_denied_tool_calls_to_model() {
+        let mut agent = agent();
+        agent.approver = Some(Arc::new(|_, _| async { ToolApproval::Deny { reason: Some("Not now".to_string()) } }.boxed()));
+        agent.client.set_response_factory(|request| match request.messages.len() {
+            1 => tool_use_response(),
+            _ => vec![TestLlmClient::response_for_message("Okay".to_string())]
+        });
+        let (sender, receiver) = mpsc::unbounded();
+
+        let conversation = agent.run(vec![user_message("What is 1 + 2?")], sender).await.unwrap();
+
+        assert_eq!(conversation[2], Message::User { parts: vec![
+            UserMessagePart { content: UserMessageContent::ToolResult { tool_use_id: "call_1".to_string(), content: "The user denied the tool call: Not now".to_string(), is_error: true } }
+        ] });
+        let events = receiver.collect::<Vec<_>>().await;
+        assert!(!events.iter().any(|event| matches!(event, AgentEvent::ToolCallStarted { .. })));
+    }
+
+    #[tokio::test]
+    async fn calls_tools_with_approved_input() {
+        let mut agent = agent();
+        agent.approver = Some(Arc::new(|_, _| async { ToolApproval::Approve { input: json!({ "a": 2, "b": 2 }) } }.boxed()));
+        agent.client.set_response_factory(|request| match request.messages.len() {
+            1 => tool_use_response(),
+            _ => vec![TestLlmClient::response_for_message("The sum is 4".to_string())]
+        });
+        let (sender, _receiver) = mpsc::unbounded();
+
+        let conversation = agent.run(vec![user_message("What is 1 + 2?")], sender).await.unwrap();
+
+        assert_eq!(conversation[1], Message::Assistant { parts: vec![
+            AssistantMessagePart {
+                complete: true,
+                content: AssistantMessageContent::ToolUse { id: "call_1".to_string(), name: "add".to_string(), input: json!({ "a": 2, "b": 2 }) }
+            }
+        ] });
+        assert_eq!(conversation[2], Message::User { parts: vec![
+            UserMessagePart { content: UserMessageContent::ToolResult { tool_use_id: "call_1".to_string(), content: "4".to_string(), is_error: false } }
+        ] });
+    }
+
+    #[tokio::test]
+    async fn reports_unknown_tools_to_model() {
+        let mut agent = Agent::new(TestLlmClient::default());
+        agent.client.set_response_factory(|request| match request.messages.len() {
+            1 => tool_use_response(),
+            _ => vec![TestLlmClient::response_for_message("Sorry".to_string())]
+        });
+        let (sender, _receiver) = mpsc::unbounded();
+
+        let conversation = agent.run(vec![user_message("What is 1 + 2?")], sender).await.unwrap();
+
+        assert_eq!(conversation[2], Message::User { parts: vec![
+            UserMessagePart { content: UserMessageContent::ToolResult { tool_use_id: "call_1".to_string(), content: "Unknown tool: add".to_string(), is_error: true } }
+        ] });
+    }
+}
@@ -1,4 +1,6 @@
-use crate::llm::{AssistantMessageContent, LlmError};
+use std::sync::{Arc, Mutex};
+
+use crate::llm::{AssistantMessageContent, LlmError, ResponseFormat, ToolDefinition, Usage};
 
 use super::{AssistantMessagePart, CompletionRequest, LlmClient, Result, UserMessagePart};
 use eventsource_stream::{Event, EventStreamError, Eventsource};
@@ -9,8 +11,12 @@ use url::Url;
 
 const API_VERSION: &str = "2023-06-01";
 
+const RESPONSE_FORMAT_TOOL_DESCRIPTION: &str = "Respond with the requested JSON document as input of this tool";
+
 pub struct AnthropicLlmClient {
-    pub config: AnthropicLlmClientConfig
+    pub config: AnthropicLlmClientConfig,
+    /// The usage of all responses so far; updated while the responses are streamed
+    pub usage: Arc<Mutex<Usage>>
 }
 
 pub struct AnthropicLlmClientConfig {
@@ -20,16 +26,34 @@ pub struct AnthropicLlmClientConfig {
     pub max_tokens: u32
 }
 
+impl AnthropicLlmClient {
+    pub fn new(config: AnthropicLlmClientConfig) -> Self {
+        Self { config, usage: Arc::default() }
+    }
+}
+
 impl LlmClient for AnthropicLlmClient {
     type Response = IntoStream<mpsc::UnboundedReceiver<Result<Vec<super::Message>>>>;
 
     async fn complete(&mut self, request: &CompletionRequest) -> Self::Response {
         const PATH: &str = "/v1/messages";
+        // Anthropic has no dedicated response format, so the model is forced to call a tool whose
+        // input is the requested document
+        let response_format_tool = request.response_format.as_ref().map(|format| match format {
+            ResponseFormat::JsonSchema { name, schema } => Tool {
+                name: name.clone(),
+                description: Some(RESPONSE_FORMAT_TOOL_DESCRIPTION.to_string()),
+                input_schema: schema.clone()
+            }
+        });
+        let response_format_tool_name = response_format_tool.as_ref().map(|tool| tool.name.clone());
         let request = MessagesRequest {
             model: self.config.model.clone(),
             max_tokens: self.config.max_tokens,
             system: request.system.iter().map(Into::into).collect(),
             messages: request.messages.iter().map(Into::into).collect(),
+            tool_choice: response_format_tool_name.clone().map(|name| ToolChoice::Tool { name }),
+            tools: request.tools.iter().map(Into::into).chain(response_format_tool).collect(),
             stream: true
         };
         let client = reqwest::Client::new();
@@ -44,10 +68,16 @@ impl LlmClient for AnthropicLlmClient {
             .send()
             .await
         {
+            Ok(response) if !response.status().is_success() => {
+                let status = response.status().as_u16();
+                let body = response.text().await.unwrap_or_default();
+                sender.send(Err(api_error(Some(status), &body))).await.expect("Unable to send result");
+            },
             Ok(response) => {
+                let usage = self.usage.clone();
                 tokio::spawn(async move {
                     let response_eventsource = response.bytes_stream().eventsource();
-                    handle_response(response_eventsource, sender).await;
+                    handle_response(response_eventsource, sender, response_format_tool_name, usage).await;
                 });
             },
             Err(_) => {
@@ -57,16 +87,31 @@ impl LlmClient for AnthropicLlmClient {
 
         receiver.into_stream()
     }
+
+    fn usage(&self) -> Usage {
+        *self.usage.lock().expect("Usage lock is poisoned")
+    }
 }
 
-async fn handle_response<T>(mut eventsource: T, mut sender: mpsc::UnboundedSender<Result<Vec<super::Message>>>)
+async fn handle_response<T>(
+    mut eventsource: T,
+    mut sender: mpsc::UnboundedSender<Result<Vec<super::Message>>>,
+    response_format_tool_name: Option<String>,
+    usage: Arc<Mutex<Usage>>
+)
     where T: Stream<Item = std::result::Result<Event, EventStreamError<reqwest::Error>>> + Unpin
 {
-    let mut state_holder = StreamingResponseStateHolder::new();
+    let mut state_holder = StreamingResponseStateHolder::new(response_format_tool_name);
+    let mut failed = false;
+    let initial_usage = *usage.lock().expect("Usage lock is poisoned");
     while let Some(event) = eventsource.next().await {
         match event {
             Ok(event) => {
-                if let Some(current_result) = state_holder.handle_event(&event.event, &event.data) {
+                let current_result = state_holder.handle_event(&event.event, &event.data);
+                // The usage is updated before the result is sent, so that it is current for the receiver
+                *usage.lock().expect("Usage lock is poisoned") = initial_usage + state_holder.usage;
+                if let Some(current_result) = current_result {
+                    failed |= current_result.is_err();
                     sender.send(current_result).await.expect("Unable to send result");
                 }
                 if state_holder.is_completed() {
@@ -74,57 +119,156 @@ async fn handle_response<T>(mut eventsource: T, mut sender: mpsc::UnboundedSende
                 }
             },
             Err(_) => {
+                failed = true;
                 sender.send(Err(LlmError::UnexpectedResponse)).await.expect("Unable to send result");
             }
         }
     }
+    // The connection was closed before the response was complete
+    if !failed && !state_holder.is_completed() {
+        sender.send(Err(LlmError::UnexpectedResponse)).await.expect("Unable to send result");
+    }
+}
+
+/// The error described by an error response or error event
+fn api_error(status: Option<u16>, body: &str) -> LlmError {
+    match serde_json::from_str::<ErrorEvent>(body) {
+        Ok(ErrorEvent { error }) => LlmError::ApiError { status, error_type: error.error_type, message: error.message },
+        Err(_) => LlmError::ApiError { status, error_type: "unknown_error".to_string(), message: body.trim().to_string() }
+    }
 }
 
 struct StreamingResponseStateHolder {
     state: StreamingResponseState,
-    response_parts: Vec<AssistantMessageContent>
+    response_parts: Vec<AssistantMessageContent>,
+    /// The usage of this response; the output tokens grow while it is streamed
+    usage: Usage,
+    /// Name of the tool which is used to enforce the response format; its input is handled as text
+    response_format_tool_name: Option<String>
 }
 
 enum StreamingResponseState {
     Init,
     MessageTransferring,
-    ContentBlockStarted { current_content: AssistantMessageContent },
+    /// A content block is being transferred; tool inputs are collected as JSON text until the block stops
+    ContentBlockStarted { current_content: AssistantMessageContent, input_json: String },
     MessageCompleted,
     ResponseCompleted
 }
 
+#[derive(Deserialize)]
+struct MessageStartEvent {
+    message: MessageStart
+}
+
+#[derive(Deserialize)]
+struct MessageStart {
+    usage: Usage
+}
+
+#[derive(Deserialize)]
+struct MessageDeltaEvent {
+    usage: MessageDeltaUsage
+}
+
+/// The usage in a message delta; the output tokens are cumulative
+#[derive(Deserialize)]
+struct MessageDeltaUsage {
+    output_tokens: u64
+}
+
+#[derive(Deserialize)]
+struct ContentBlockStartEvent {
+    content_block: ContentBlock
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+enum ContentBlock {
+    Text { text: String },
+    ToolUse { id: String, name: String }
+}
+
+#[derive(Deserialize)]
+struct ErrorEvent {
+    error: ErrorDetails
+}
+
+#[derive(Deserialize)]
+struct ErrorDetails {
+    #[serde(rename = "type")]
+    error_type: String,
+    message: String
+}
+
 #[derive(Deserialize)]
 struct ContentBlockDeltaEvent {
     delta: ContentBlockDelta
 }
 
 #[derive(Deserialize)]
-struct ContentBlockDelta {
-    text: String
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+enum ContentBlockDelta {
+    TextDelta { text: String },
+    InputJsonDelta { partial_json: String }
 }
 
 impl StreamingResponseStateHolder {
-    fn new() -> Self {
-        Self { state: StreamingResponseState::Init, response_parts: vec![] }
+    fn new(response_format_tool_name: Option<String>) -> Self {
+        Self { state: StreamingResponseState::Init, response_parts: vec![], usage: Usage::default(), response_format_tool_name }
     }
 
     fn handle_event(&mut self, event: &str, data: &str) -> Option<Result<Vec<super::Message>>> {
         match (&self.state, event) {
             (StreamingResponseState::Init, "message_start") => {
+                if let Ok(event) = serde_json::from_str::<MessageStartEvent>(data) {
+                    self.usage = event.message.usage;
+                }
                 self.state = StreamingResponseState::MessageTransferring;
                 None
             },
             (StreamingResponseState::MessageTransferring, "content_block_start") => {
-                self.state = StreamingResponseState::ContentBlockStarted {
-                    current_content: AssistantMessageContent::Text { text: String::new() }
-                };
-                None
+                match serde_json::from_str::<ContentBlockStartEvent>(data).map(|event| event.content_block) {
+                    Ok(ContentBlock::Text { text }) => {
+                        self.state = StreamingResponseState::ContentBlockStarted {
+                            current_content: AssistantMessageContent::Text { text },
+                            input_json: String::new()
+                        };
+                        None
+                    },
+                    Ok(ContentBlock::ToolUse { name, .. }) if self.response_format_tool_name.as_ref() == Some(&name) => {
+                        self.state = StreamingResponseState::ContentBlockStarted {
+                            current_content: AssistantMessageContent::Text { text: String::new() },
+                            input_json: String::new()
+                        };
+                        None
+                    },
+                    Ok(ContentBlock::ToolUse { id, name }) => {
+                        self.state = StreamingResponseState::ContentBlockStarted {
+                            current_content: AssistantMessageContent::ToolUse { id, name, input: Value::Null },
+                            input_json: String::new()
+                        };
+                        None
+                    },
+                    _ => Some(Err(LlmError::UnexpectedResponse))
+                }
             },
-            (StreamingResponseState::ContentBlockStarted { current_content }, "content_block_delta") => {
+            (StreamingResponseState::ContentBlockStarted { current_content, input_json }, "content_block_delta") => {
                 if let Ok(delta_event) = serde_json::from_str::<ContentBlockDeltaEvent>(data) {
-                    self.state = StreamingResponseState::ContentBlockStarted {
-                        current_content: match current_content {
-                            AssistantMessageContent::Text { text } => AssistantMessageContent::Text { text: text.to_owned() + &delta_event.delta.text },
+                    let delta = match delta_event.delta {
+                        ContentBlockDelta::TextDelta { text } => text,
+                        ContentBlockDelta::InputJsonDelta { partial_json } => partial_json
+                    };
+                    self.state = match current_content {
+                        AssistantMessageContent::Text { text } => StreamingResponseState::ContentBlockStarted {
+                            current_content: AssistantMessageContent::Text { text: text.to_owned() + &delta },
+                            input_json: String::new()
+                        },
+                        AssistantMessageContent::ToolUse { .. } => StreamingResponseState::ContentBlockStarted {
+                            current_content: current_content.clone(),
+                            input_json: input_json.to_owned() + &delta
                         }
                     };
                     Some(Ok(self.current_response()))
@@ -132,8 +276,23 @@ impl StreamingResponseStateHolder {
                     Some(Err(LlmError::UnexpectedResponse))
                 }
             },
-            (StreamingResponseState::ContentBlockStarted { current_content }, "content_block_stop") => {
-                self.response_parts.push(current_content.clone());
+            (StreamingResponseState::ContentBlockStarted { current_content, input_json }, "content_block_stop") => {
+                let content = match current_content {
+                    AssistantMessageContent::Text { .. } => current_content.clone(),
+                    AssistantMessageContent::ToolUse { id, name, .. } => {
+                        // Tools without input parameters might not receive any input deltas
+                        let input = if input_json.is_empty() {
+                            Ok(Value::Object(Default::default()))
+                        } else {
+                            serde_json::from_str(input_json)
+                        };
+                        match input {
+                            Ok(input) => AssistantMessageContent::ToolUse { id: id.clone(), name: name.clone(), input },
+                            Err(_) => return Some(Err(LlmError::UnexpectedResponse))
+                        }
+                    }
+                };
+                self.response_parts.push(content);
                 self.state = StreamingResponseState::MessageTransferring;
                 Some(Ok(self.current_response()))
             },
@@ -142,10 +301,14 @@ impl StreamingResponseStateHolder {
                 None
             },
             (StreamingResponseState::MessageTransferring, "message_delta") => {
+                if let Ok(event) = serde_json::from_str::<MessageDeltaEvent>(data) {
+                    self.usage.output_tokens = event.usage.output_tokens;
+                }
                 self.state = StreamingResponseState::ResponseCompleted;
                 None
             },
             (_, "ping") => None,
+            (_, "error") => Some(Err(api_error(None, data))),
             _ => Some(Err(LlmError::UnexpectedResponse))
         }
     }
@@ -158,7 +321,7 @@ impl StreamingResponseStateHolder {
                 content: content.clone()
             });
         }
-        if let StreamingResponseState::ContentBlockStarted { current_content } = &self.state {
+        if let StreamingResponseState::ContentBlockStarted { current_content, .. } = &self.state {
             parts.push(AssistantMessagePart {
                 complete: false,
                 content: current_content.clone()
@@ -178,9 +341,28 @@ struct MessagesRequest {
     max_tokens: u32,
     system: Vec<SystemPrompt>,
     messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<Tool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<ToolChoice>,
     stream: bool
 }
 
+#[derive(Serialize)]
+struct Tool {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    input_schema: Value
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+enum ToolChoice {
+    Tool { name: String }
+}
+
 #[derive(Serialize)]
 struct Message {
     role: MessageRole,
@@ -230,7 +412,14 @@ impl Message {
         Message {
             role: MessageRole::User,
             content: parts.iter().cloned().map(|p| match p.content {
-                super::UserMessageContent::Text { text } => MessageContent::Text { text }
+                super::UserMessageContent::Text { text } => MessageContent::Text { text },
+                super::UserMessageContent::Image { media_type, data } => MessageContent::Image {
+                    source: ImageSource { data, media_type, encoding_type: "base64".to_string() }
+                },
+                super::UserMessageContent::Document { media_type, data } => MessageContent::Document {
+                    source: DocumentSource { data, media_type, encoding_type: "base64".to_string() }
+                },
+                super::UserMessageContent::ToolResult { tool_use_id, content, is_error } => MessageContent::ToolResult { tool_use_id, content, is_error }
             }).collect()
         }
     }
@@ -243,7 +432,11 @@ impl Message {
                 AssistantMessagePart {
                     complete: true,
                     content: AssistantMessageContent::Text { text }
-                } => Some(MessageContent::Text { text })
+                } => Some(MessageContent::Text { text }),
+                AssistantMessagePart {
+                    complete: true,
+                    content: AssistantMessageContent::ToolUse { id, name, input }
+                } => Some(MessageContent::ToolUse { id, input, name })
             }).collect()
         }
     }
@@ -258,6 +451,16 @@ impl From<&super::Message> for Message {
     }
 }
 
+impl From<&ToolDefinition> for Tool {
+    fn from(value: &ToolDefinition) -> Self {
+        Tool {
+            name: value.name.clone(),
+            description: Some(value.description.clone()),
+            input_schema: value.input_schema.clone()
+        }
+    }
+}
+
 impl From<&super::SystemPrompt> for SystemPrompt {
     fn from(value: &super::SystemPrompt) -> Self {
         SystemPrompt { text: value.content.clone(), encoding_type: "text".to_string() }
@@ -268,9 +471,10 @@ impl From<&super::SystemPrompt> for SystemPrompt {
 mod tests {
     use futures::StreamExt;
     use mockito::Matcher;
+    use serde_json::{json, Value};
     use url::Url;
 
-    use crate::llm::{AssistantMessageContent, AssistantMessagePart, LlmClient, Message, SystemPrompt, UserMessageContent, UserMessagePart};
+    use crate::llm::{AssistantMessageContent, AssistantMessagePart, LlmClient, LlmError, Message, ResponseFormat, SystemPrompt, ToolDefinition, Usage, UserMessageContent, UserMessagePart};
 
     use super::AnthropicLlmClient;
 
@@ -279,9 +483,9 @@ mod tests {
         let mut server = mockito::Server::new_async().await;
         let url = server.url();
 
-        let mut anthropic_client = AnthropicLlmClient {
-            config: super::AnthropicLlmClientConfig { base_url: Url::parse(&url).unwrap(), api_key: "test".to_string(), model: "model".to_string(), max_tokens: 1024 },
-        };
+        let mut anthropic_client = AnthropicLlmClient::new(
+            super::AnthropicLlmClientConfig { base_url: Url::parse(&url).unwrap(), api_key: "test".to_string(), model: "model".to_string(), max_tokens: 1024 }
+        );
         let request = crate::llm::CompletionRequest {
             system: vec![
                 SystemPrompt { content: "Answer in some way".to_string() }
@@ -299,7 +503,9 @@ mod tests {
                 Message::User { parts: vec![
                     UserMessagePart { content: UserMessageContent::Text { text: "Part 2".to_string() } },
                 ] }
-            ]
+            ],
+            tools: vec![],
+            response_format: None
         };
 
         let mock = server.mock("POST", "/v1/messages")
@@ -388,5 +594,299 @@ data: {"type": "message_stop"}
         assert_eq!(*result.next().await.unwrap().unwrap().first().unwrap(), Message::Assistant { parts: vec![
             AssistantMessagePart { complete: true, content: AssistantMessageContent::Text { text: "My response".to_string() } }
         ] });
+        assert_eq!(result.next().await, None);
+
+        assert_eq!(anthropic_client.usage(), Usage { input_tokens: 25, output_tokens: 15, ..Default::default() });
+    }
+
+    #[tokio::test]
+    async fn test_completion_with_response_format() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mut anthropic_client = AnthropicLlmClient::new(
+            super::AnthropicLlmClientConfig { base_url: Url::parse(&url).unwrap(), api_key: "test".to_string(), model: "model".to_string(), max_tokens: 1024 }
+        );
+        let request = crate::llm::CompletionRequest {
+            system: vec![],
+            messages: vec![
+                Message::User { parts: vec![
+                    UserMessagePart { content: UserMessageContent::Text { text: "Alice is 30".to_string() } },
+                ] }
+            ],
+            tools: vec![],
+            response_format: Some(ResponseFormat::JsonSchema {
+                name: "Person".to_string(),
+                schema: json!({ "type": "object", "properties": { "age": { "type": "integer" } } })
+            })
+        };
+
+        let mock = server.mock("POST", "/v1/messages")
+            .match_body(Matcher::PartialJsonString(r#"
+{
+  "tools": [
+    {
+      "name": "Person",
+      "description": "Respond with the requested JSON document as input of this tool",
+      "input_schema": { "type": "object", "properties": { "age": { "type": "integer" } } }
+    }
+  ],
+  "tool_choice": { "type": "tool", "name": "Person" }
+}
+            "#.to_string()))
+            .with_status(200)
+            .with_body(r#"
+event: message_start
+data: {"type": "message_start", "message": {"id": "msg_1", "type": "message", "role": "assistant", "content": [], "model": "model", "stop_reason": null, "stop_sequence": null, "usage": {"input_tokens": 25, "output_tokens": 1}}}
+
+event: content_block_start
+data: {"type": "content_block_start", "index": 0, "content_block": {"type": "tool_use", "id": "toolu_1", "name": "Person", "input": {}}}
+
+event: content_block_delta
+data: {"type": "content_block_delta", "index": 0, "delta": {"type": "input_json_delta", "partial_json": "{\"age\": "}}
+
+event: content_block_delta
+data: {"type": "content_block_delta", "index": 0, "delta": {"type": "input_json_delta", "partial_json": "30}"}}
+
+event: content_block_stop
+data: {"type": "content_block_stop", "index": 0}
+
+event: message_delta
+data: {"type": "message_delta", "delta": {"stop_reason": "tool_use", "stop_sequence":null}, "usage": {"output_tokens": 15}}
+
+event: message_stop
+data: {"type": "message_stop"}
+            "#)
+            .create();
+
+        let result = anthropic_client.complete(&request).await;
+        let responses = result.collect::<Vec<_>>().await;
+
+        mock.assert();
+
+        assert_eq!(*responses.last().unwrap().as_ref().unwrap().first().unwrap(), Message::Assistant { parts: vec![
+            AssistantMessagePart { complete: true, content: AssistantMessageContent::Text { text: "{\"age\": 30}".to_string() } }
+        ] });
+    }
+
+    #[tokio::test]
+    async fn test_completion_with_tools() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+
+        let mut anthropic_client = AnthropicLlmClient::new(
+            super::AnthropicLlmClientConfig { base_url: Url::parse(&url).unwrap(), api_key: "test".to_string(), model: "model".to_string(), max_tokens: 1024 }
+        );
+        let request = crate::llm::CompletionRequest {
+            system: vec![],
+            messages: vec![
+                Message::User { parts: vec![
+                    UserMessagePart { content: UserMessageContent::Text { text: "Weather in Berlin and Paris?".to_string() } },
+                ] },
+                Message::Assistant { parts: vec![
+                    AssistantMessagePart {
+                        complete: true,
+                        content: AssistantMessageContent::ToolUse { id: "toolu_1".to_string(), name: "weather".to_string(), input: json!({ "city": "Berlin" }) },
+                    }
+                ] },
+                Message::User { parts: vec![
+                    UserMessagePart { content: UserMessageContent::ToolResult { tool_use_id: "toolu_1".to_string(), content: "Sunny".to_string(), is_error: false } },
+                ] }
+            ],
+            tools: vec![
+                ToolDefinition { name: "weather".to_string(), description: "Get the weather".to_string(), input_schema: json!({ "type": "object" }) }
+            ],
+            response_format: None
+        };
+
+        let mock = server.mock("POST", "/v1/messages")
+            .match_body(Matcher::PartialJsonString(r#"
+{
+  "messages": [
+    {
+      "role": "user",
+      "content": [{ "type": "text", "text": "Weather in Berlin and Paris?" }]
+    },
+    {
+      "role": "assistant",
+      "content": [{ "type": "tool_use", "id": "toolu_1", "name": "weather", "input": { "city": "Berlin" } }]
+    },
+    {
+      "role": "user",
+      "content": [{ "type": "tool_result", "tool_use_id": "toolu_1", "content": "Sunny", "is_error": false }]
+    }
+  ],
+  "tools": [
+    { "name": "weather", "description": "Get the weather", "input_schema": { "type": "object" } }
+  ]
+}
+            "#.to_string()))
+            .with_status(200)
+            .with_body(r#"
+event: message_start
+data: {"type": "message_start", "message": {"id": "msg_1", "type": "message", "role": "assistant", "content": [], "model": "model", "stop_reason": null, "stop_sequence": null, "usage": {"input_tokens": 25, "output_tokens": 1}}}
+
+event: content_block_start
+data: {"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}
+
+event: content_block_delta
+data: {"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Checking Paris"}}
+
+event: content_block_stop
+data: {"type": "content_block_stop", "index": 0}
+
+event: content_block_start
+data: {"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "id": "toolu_2", "name": "weather", "input": {}}}
+
+event: content_block_delta
+data: {"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"city\": "}}
+
+event: content_block_delta
+data: {"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "\"Paris\"}"}}
+
+event: content_block_stop
+data: {"type": "content_block_stop", "index": 1}
+
+event: message_delta
+data: {"type": "message_delta", "delta": {"stop_reason": "tool_use", "stop_sequence":null}, "usage": {"output_tokens": 15}}
+
+event: message_stop
+data: {"type": "message_stop"}
+            "#)
+            .create();
+
+        let result = anthropic_client.complete(&request).await;
+        let responses = result.collect::<Vec<_>>().await;
+
+        mock.assert();
+
+        assert_eq!(*responses[3].as_ref().unwrap().first().unwrap(), Message::Assistant { parts: vec![
+            AssistantMessagePart { complete: true, content: AssistantMessageContent::Text { text: "Checking Paris".to_string() } },
+            AssistantMessagePart { complete: false, content: AssistantMessageContent::ToolUse { id: "toolu_2".to_string(), name: "weather".to_string(), input: Value::Null } }
+        ] });
+        assert_eq!(*responses.last().unwrap().as_ref().unwrap().first().unwrap(), Message::Assistant { parts: vec![
+            AssistantMessagePart { complete: true, content: AssistantMessageContent::Text { text: "Checking Paris".to_string() } },
+            AssistantMessagePart { complete: true, content: AssistantMessageContent::ToolUse { id: "toolu_2".to_string(), name: "weather".to_string(), input: json!({ "city": "Paris" }) } }
+        ] });
+    }
+
+    #[tokio::test]
+    async fn test_completion_with_attachments() {
+        let mut server = mockito::Server::new_async().await;
+        let mut anthropic_client = AnthropicLlmClient::new(
+            super::AnthropicLlmClientConfig { base_url: Url::parse(&server.url()).unwrap(), api_key: "test".to_string(), model: "model".to_string(), max_tokens: 1024 }
+        );
+        let request = crate::llm::CompletionRequest {
+            messages: vec![
+                Message::User { parts: vec![
+                    UserMessagePart { content: UserMessageContent::Text { text: "What is shown?".to_string() } },
+                    UserMessagePart { content: UserMessageContent::Image { media_type: "image/png".to_string(), data: "aW1hZ2U=".to_string() } },
+                    UserMessagePart { content: UserMessageContent::Document { media_type: "application/pdf".to_string(), data: "cGRm".to_string() } }
+                ] }
+            ],
+            ..Default::default()
+        };
+        let mock = server.mock("POST", "/v1/messages")
+            .match_body(Matcher::PartialJson(json!({
+                "messages": [
+                    {
+                        "role": "user",
+                        "content": [
+                            { "type": "text", "text": "What is shown?" },
+                            { "type": "image", "source": { "type": "base64", "media_type": "image/png", "data": "aW1hZ2U=" } },
+                            { "type": "document", "source": { "type": "base64", "media_type": "application/pdf", "data": "cGRm" } }
+                        ]
+                    }
+                ]
+            })))
+            .with_status(529)
+            .with_body(r#"{"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}"#)
+            .create();
+
+        anthropic_client.complete(&request).await.collect::<Vec<_>>().await;
+
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_completion_with_error_status() {
+        let mut server = mockito::Server::new_async().await;
+        let mut anthropic_client = AnthropicLlmClient::new(
+            super::AnthropicLlmClientConfig { base_url: Url::parse(&server.url()).unwrap(), api_key: "test".to_string(), model: "model".to_string(), max_tokens: 1024 }
+        );
+        let mock = server.mock("POST", "/v1/messages")
+            .with_status(529)
+            .with_body(r#"{"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}"#)
+            .create();
+
+        let responses = anthropic_client.complete(&crate::llm::CompletionRequest::default()).await.collect::<Vec<_>>().await;
+
+        mock.assert();
+        assert_eq!(responses, vec![Err(LlmError::ApiError {
+            status: Some(529),
+            error_type: "overloaded_error".to_string(),
+            message: "Overloaded".to_string()
+        })]);
+    }
+
+    #[tokio::test]
+    async fn test_completion_with_truncated_stream() {
+        let mut server = mockito::Server::new_async().await;
+        let mut anthropic_client = AnthropicLlmClient::new(
+            super::AnthropicLlmClientConfig { base_url: Url::parse(&server.url()).unwrap(), api_key: "test".to_string(), model: "model".to_string(), max_tokens: 1024 }
+        );
+        let mock = server.mock("POST", "/v1/messages")
+            .with_status(200)
+            .with_body(r#"
+event: message_start
+data: {"type": "message_start", "message": {"id": "msg_1", "type": "message", "role": "assistant", "content": [], "model": "model", "stop_reason": null, "stop_sequence": null, "usage": {"input_tokens": 25, "output_tokens": 1}}}
+
+event: content_block_start
+data: {"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}
+
+event: content_block_delta
+data: {"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "My"}}
+
+            "#)
+            .create();
+
+        let responses = anthropic_client.complete(&crate::llm::CompletionRequest::default()).await.collect::<Vec<_>>().await;
+
+        mock.assert();
+        assert_eq!(responses, vec![
+            Ok(vec![Message::Assistant { parts: vec![
+                AssistantMessagePart { complete: false, content: AssistantMessageContent::Text { text: "My".to_string() } }
+            ] }]),
+            Err(LlmError::UnexpectedResponse)
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_completion_with_error_event() {
+        let mut server = mockito::Server::new_async().await;
+        let mut anthropic_client = AnthropicLlmClient::new(
+            super::AnthropicLlmClientConfig { base_url: Url::parse(&server.url()).unwrap(), api_key: "test".to_string(), model: "model".to_string(), max_tokens: 1024 }
+        );
+        let mock = server.mock("POST", "/v1/messages")
+            .with_status(200)
+            .with_body(r#"
+event: message_start
+data: {"type": "message_start", "message": {"id": "msg_1", "type": "message", "role": "assistant", "content": [], "model": "model", "stop_reason": null, "stop_sequence": null, "usage": {"input_tokens": 25, "output_tokens": 1}}}
+
+event: error
+data: {"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}
+
+            "#)
+            .create();
+
+        let responses = anthropic_client.complete(&crate::llm::CompletionRequest::default()).await.collect::<Vec<_>>().await;
+
+        mock.assert();
+        // The error is reported once, not again as a truncated stream
+        assert_eq!(responses, vec![Err(LlmError::ApiError {
+            status: None,
+            error_type: "overloaded_error".to_string(),
+            message: "Overloaded".to_string()
+        })]);
     }
 }
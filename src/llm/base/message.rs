@@ -1,11 +1,16 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
 /// A system message, which the model should follow regardless of the other messages
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SystemPrompt {
     pub content: String
 }
 
 /// A message in a chat which should be completed
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "role")]
+#[serde(rename_all = "snake_case")]
 pub enum Message {
     /// A user message, which might contain of different parts to support multimodality
     User { parts: Vec<UserMessagePart> },
@@ -13,32 +18,63 @@ pub enum Message {
     Assistant { parts: Vec<AssistantMessagePart> },
 }
 
+impl Message {
+    /// The text parts of the message, joined by newlines
+    pub fn text(&self) -> String {
+        let texts: Vec<&str> = match self {
+            Message::User { parts } => parts.iter().filter_map(|part| match &part.content {
+                UserMessageContent::Text { text } => Some(text.as_str()),
+                UserMessageContent::ToolResult { .. } | UserMessageContent::Image { .. } | UserMessageContent::Document { .. } => None
+            }).collect(),
+            Message::Assistant { parts } => parts.iter().filter_map(|part| match &part.content {
+                AssistantMessageContent::Text { text } => Some(text.as_str()),
+                AssistantMessageContent::ToolUse { .. } => None
+            }).collect()
+        };
+        texts.join("\n")
+    }
+}
+
 /// A part of a user message
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct UserMessagePart {
     /// The content of the message part
+    #[serde(flatten)]
     pub content: UserMessageContent
 }
 
 /// The content of a user message part
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
 pub enum UserMessageContent {
-    Text { text: String }
+    Text { text: String },
+    /// An image such as "image/png", encoded as base64
+    Image { media_type: String, data: String },
+    /// A document such as "application/pdf", encoded as base64
+    Document { media_type: String, data: String },
+    /// The result of a tool call which the assistant requested in the previous message
+    ToolResult { tool_use_id: String, content: String, is_error: bool }
 }
 
 /// A part of an assistant message
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AssistantMessagePart {
     /// A flag which indicates whether the part is complete or being generated
     pub complete: bool,
 
     /// The content of an assistant message
+    #[serde(flatten)]
     pub content: AssistantMessageContent
 }
 
 /// The content of an assistant message part
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
 pub enum AssistantMessageContent {
-    Text { text: String }
+    Text { text: String },
+    /// A request to call a tool; the input is only available once the part is complete
+    ToolUse { id: String, name: String, input: Value }
 }
 
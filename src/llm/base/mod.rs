@@ -1,7 +1,11 @@
 mod message;
 
-use futures::Stream;
+use std::{ops::{Add, Sub}, pin::pin};
+
+use futures::{Stream, StreamExt};
 pub use message::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 pub type Result<T> = std::result::Result<T, LlmError>;
 
@@ -9,7 +13,18 @@ pub type Result<T> = std::result::Result<T, LlmError>;
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum LlmError {
     ConnectionError,
-    UnexpectedResponse
+    UnexpectedResponse,
+    /// The model kept requesting tool calls until the step limit was reached
+    StepLimitReached,
+    /// The response did not match the requested format, even after asking the model to repair it
+    InvalidStructuredOutput { reason: String },
+    /// The provider rejected the request or failed while answering it
+    ApiError {
+        /// The HTTP status, unless the error was reported within the response stream
+        status: Option<u16>,
+        error_type: String,
+        message: String
+    }
 }
 
 /// Generic trait to interact with an LLM
@@ -18,20 +33,112 @@ pub trait LlmClient {
 
     /// Send a prompt to the LLM and get a response
     fn complete(&mut self, request: &CompletionRequest) -> impl Future<Output = Self::Response>;
+
+    /// The tokens used by all requests of this client so far, if the provider reports them
+    fn usage(&self) -> Usage {
+        Usage::default()
+    }
+}
+
+/// The number of tokens which were processed, as reported by the provider
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Input tokens which were written to the prompt cache
+    pub cache_creation_input_tokens: u64,
+    /// Input tokens which were read from the prompt cache
+    pub cache_read_input_tokens: u64
+}
+
+impl Usage {
+    /// All input and output tokens
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens + self.cache_creation_input_tokens + self.cache_read_input_tokens
+    }
+}
+
+impl Add for Usage {
+    type Output = Usage;
+
+    fn add(self, other: Usage) -> Usage {
+        Usage {
+            input_tokens: self.input_tokens + other.input_tokens,
+            output_tokens: self.output_tokens + other.output_tokens,
+            cache_creation_input_tokens: self.cache_creation_input_tokens + other.cache_creation_input_tokens,
+            cache_read_input_tokens: self.cache_read_input_tokens + other.cache_read_input_tokens
+        }
+    }
+}
+
+impl Sub for Usage {
+    type Output = Usage;
+
+    fn sub(self, other: Usage) -> Usage {
+        Usage {
+            input_tokens: self.input_tokens.saturating_sub(other.input_tokens),
+            output_tokens: self.output_tokens.saturating_sub(other.output_tokens),
+            cache_creation_input_tokens: self.cache_creation_input_tokens.saturating_sub(other.cache_creation_input_tokens),
+            cache_read_input_tokens: self.cache_read_input_tokens.saturating_sub(other.cache_read_input_tokens)
+        }
+    }
+}
+
+/// Complete the request and wait for the final response
+pub(crate) async fn final_response<C: LlmClient>(client: &mut C, request: &CompletionRequest) -> Result<Vec<Message>> {
+    let mut response = pin!(client.complete(request).await);
+    let mut messages = Vec::new();
+    while let Some(partial_response) = response.next().await {
+        messages = partial_response?;
+    }
+    Ok(messages)
 }
 
 /// The request which contains all information to generate a text completion
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct CompletionRequest {
     /// The system prompts
     pub system: Vec<SystemPrompt>,
 
     /// The previous messages
     pub messages: Vec<Message>,
+
+    /// The tools which the model may call
+    pub tools: Vec<ToolDefinition>,
+
+    /// A format which the response has to follow, if any
+    pub response_format: Option<ResponseFormat>,
+}
+
+/// The description of a tool which the model may request to call
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    /// The unique name of the tool
+    pub name: String,
+
+    /// A description of what the tool does and when to use it
+    pub description: String,
+
+    /// The JSON schema of the tool input
+    pub input_schema: Value
+}
+
+/// A format which the text of the response has to follow
+///
+/// Each client decides how to enforce the format, e.g. with a forced tool call or a dedicated
+/// request parameter. In any case, the response contains the result as text.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseFormat {
+    /// A JSON document which conforms to the given JSON schema
+    JsonSchema { name: String, schema: Value }
 }
 
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
     use std::vec::IntoIter;
 
     use futures::stream::{self, Iter};
@@ -39,10 +146,12 @@ mod test {
 
     use super::{message::{AssistantMessageContent, AssistantMessagePart}, *};
 
+    type ResponseFactory = fn(CompletionRequest) -> Vec<Result<Vec<Message>>>;
+
     pub struct TestLlmClient {
         pub requests: Vec<CompletionRequest>,
         default_response: Result<Vec<Message>>,
-        response_factory: Option<fn(CompletionRequest) -> Vec<Result<Vec<Message>>>>
+        response_factory: Option<ResponseFactory>
     }
 
     #[cfg(test)]
@@ -71,15 +180,19 @@ mod test {
 
     #[cfg(test)]
     impl TestLlmClient {
-        fn set_response_message(&mut self, message: String) {
+        pub fn set_response_message(&mut self, message: String) {
             self.default_response = TestLlmClient::response_for_message(message);
         }
     
-        fn set_response_error(&mut self, error: LlmError) {
+        pub fn set_response_error(&mut self, error: LlmError) {
             self.default_response = Err(error);
         }
 
-        fn response_for_message(message: String) -> Result<Vec<Message>> {
+        pub fn set_response_factory(&mut self, factory: ResponseFactory) {
+            self.response_factory = Some(factory);
+        }
+
+        pub fn response_for_message(message: String) -> Result<Vec<Message>> {
             Ok(vec![Message::Assistant {
                 parts: vec![AssistantMessagePart {
                     complete: true, content: AssistantMessageContent::Text { text: message }
@@ -94,6 +207,8 @@ mod test {
         let completion_request = CompletionRequest {
             system: vec![],
             messages: vec![],
+            tools: vec![],
+            response_format: None,
         };
 
         let mut response = client.complete(&completion_request).await;
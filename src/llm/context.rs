@@ -0,0 +1,335 @@
+use std::{collections::BTreeSet, ops::Range, sync::{Arc, Mutex}};
+
+use serde::Deserialize;
+
+use super::{final_response, AssistantMessageContent, CompletionRequest, LlmClient, Message, Result, SystemPrompt, UserMessageContent, UserMessagePart};
+
+/// Context window sizes in tokens for model name prefixes; the first matching prefix is used
+const CONTEXT_WINDOWS: &[(&str, usize)] = &[
+    ("claude-", 200_000),
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4o", 128_000),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4", 8_192),
+    ("gpt-3.5-turbo", 16_385),
+    ("o1", 200_000),
+    ("o3", 200_000),
+    ("o4", 200_000),
+];
+
+/// The context window assumed for unknown models
+pub const DEFAULT_CONTEXT_WINDOW: usize = 8_192;
+
+/// The default number of tokens kept free for the response
+pub const DEFAULT_RESERVED_TOKENS: usize = 4_096;
+
+/// The number of tokens kept free for the summary of older turns
+const SUMMARY_TOKENS: usize = 1_024;
+
+const SUMMARY_PROMPT: &str = "Summarize the conversation between a user and an assistant which you are given. \
+    Keep all facts, decisions and open questions which are needed to continue the conversation. \
+    Answer with the summary only.";
+
+/// The context window of a model in tokens
+pub fn context_window(model: &str) -> usize {
+    CONTEXT_WINDOWS.iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map_or(DEFAULT_CONTEXT_WINDOW, |(_, size)| *size)
+}
+
+/// A rough estimate of the tokens of a message, assuming four bytes per token
+pub fn estimate_tokens(message: &Message) -> usize {
+    const MESSAGE_OVERHEAD: usize = 4;
+    // Images are scaled down by the provider, so their tokens do not depend on the size of the file
+    const IMAGE_BYTES: usize = 1600 * 4;
+    let bytes: usize = match message {
+        Message::User { parts } => parts.iter().map(|part| match &part.content {
+            UserMessageContent::Text { text } => text.len(),
+            UserMessageContent::Image { .. } => IMAGE_BYTES,
+            UserMessageContent::Document { data, .. } => data.len(),
+            UserMessageContent::ToolResult { tool_use_id, content, .. } => tool_use_id.len() + content.len()
+        }).sum(),
+        Message::Assistant { parts } => parts.iter().map(|part| match &part.content {
+            AssistantMessageContent::Text { text } => text.len(),
+            AssistantMessageContent::ToolUse { id, name, input } => id.len() + name.len() + input.to_string().len()
+        }).sum()
+    };
+    bytes.div_ceil(4) + MESSAGE_OVERHEAD
+}
+
+/// What to do with older turns when a conversation does not fit into the context window
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextStrategy {
+    /// Leave out the oldest turns
+    #[default]
+    DropOldest,
+    /// Replace the oldest turns with a summary generated by the model
+    Summarize
+}
+
+/// Keeps the messages of a request within the context window of the model
+///
+/// Messages are removed in whole turns, i.e. a user message with all following assistant
+/// messages and tool results, so tool calls are never separated from their results. The latest
+/// turn and turns with pinned messages are always kept. Clones share the generated summaries.
+#[derive(Clone, Debug)]
+pub struct ContextManager {
+    /// The context window of the model in tokens
+    pub context_window: usize,
+
+    /// The number of tokens kept free for the response
+    pub reserved_tokens: usize,
+
+    pub strategy: ContextStrategy,
+
+    /// Indices of messages which must not be removed from the conversation
+    pub pinned: BTreeSet<usize>,
+
+    summary: Arc<Mutex<Option<Summary>>>
+}
+
+#[derive(Clone, Debug)]
+struct Summary {
+    messages: Vec<Message>,
+    text: String
+}
+
+impl ContextManager {
+    pub fn new(context_window: usize, strategy: ContextStrategy) -> Self {
+        Self {
+            context_window,
+            reserved_tokens: DEFAULT_RESERVED_TOKENS,
+            strategy,
+            pinned: BTreeSet::new(),
+            summary: Arc::new(Mutex::new(None))
+        }
+    }
+
+    pub fn for_model(model: &str, strategy: ContextStrategy) -> Self {
+        Self::new(context_window(model), strategy)
+    }
+
+    /// Shorten the messages of the request if they exceed the context window
+    ///
+    /// With [`ContextStrategy::Summarize`], the client is used to summarize the removed turns.
+    pub async fn prepare<C: LlmClient>(&self, client: &mut C, request: CompletionRequest) -> Result<CompletionRequest> {
+        let removed = self.removed_messages(&request.system, &request.messages);
+        if !removed.iter().any(|removed| *removed) {
+            return Ok(request);
+        }
+        let (removed_messages, kept_messages): (Vec<_>, Vec<_>) = request.messages.iter().cloned()
+            .zip(removed)
+            .partition(|(_, removed)| *removed);
+        let removed_messages: Vec<Message> = removed_messages.into_iter().map(|(message, _)| message).collect();
+        let mut messages: Vec<Message> = kept_messages.into_iter().map(|(message, _)| message).collect();
+
+        if self.strategy == ContextStrategy::Summarize {
+            let summary = self.summarize(client, &removed_messages).await?;
+            let summary_part = UserMessagePart {
+                content: UserMessageContent::Text { text: format!("Summary of the earlier conversation:\n{summary}") }
+            };
+            match messages.first_mut() {
+                Some(Message::User { parts }) => parts.insert(0, summary_part),
+                _ => messages.insert(0, Message::User { parts: vec![summary_part] })
+            }
+        }
+        Ok(CompletionRequest { messages, ..request })
+    }
+
+    /// Flags for all messages which have to be removed to fit into the context window
+    fn removed_messages(&self, system: &[SystemPrompt], messages: &[Message]) -> Vec<bool> {
+        let summary_tokens = match self.strategy {
+            ContextStrategy::DropOldest => 0,
+            ContextStrategy::Summarize => SUMMARY_TOKENS
+        };
+        let system_tokens: usize = system.iter().map(|prompt| prompt.content.len().div_ceil(4)).sum();
+        let budget = self.context_window
+            .saturating_sub(self.reserved_tokens)
+            .saturating_sub(summary_tokens)
+            .saturating_sub(system_tokens);
+
+        let mut total: usize = messages.iter().map(estimate_tokens).sum();
+        let mut removed = vec![false; messages.len()];
+        let turns = turns(messages);
+        for turn in turns.iter().take(turns.len().saturating_sub(1)) {
+            if total <= budget {
+                break;
+            }
+            if turn.clone().any(|index| self.pinned.contains(&index)) {
+                continue;
+            }
+            for index in turn.clone() {
+                removed[index] = true;
+                total -= estimate_tokens(&messages[index]);
+            }
+        }
+        removed
+    }
+
+    /// Summarize the messages, extending the previous summary if it covers the first messages
+    async fn summarize<C: LlmClient>(&self, client: &mut C, messages: &[Message]) -> Result<String> {
+        let previous_summary = self.summary.lock().expect("Summary is poisoned").clone()
+            .filter(|summary| messages.starts_with(&summary.messages));
+        let (mut text, new_messages) = match &previous_summary {
+            Some(summary) if summary.messages.len() == messages.len() => return Ok(summary.text.clone()),
+            Some(summary) => (format!("Summary of the earlier conversation:\n{}\n\n", summary.text), &messages[summary.messages.len()..]),
+            None => (String::new(), messages)
+        };
+        text.push_str(&transcript(new_messages));
+
+        let request = CompletionRequest {
+            system: vec![SystemPrompt { content: SUMMARY_PROMPT.to_string() }],
+            messages: vec![Message::User { parts: vec![
+                UserMessagePart { content: UserMessageContent::Text { text } }
+            ] }],
+            ..Default::default()
+        };
+        let summary = final_response(client, &request).await?.iter()
+            .map(Message::text)
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.summary.lock().expect("Summary is poisoned").replace(Summary { messages: messages.to_vec(), text: summary.clone() });
+        Ok(summary)
+    }
+}
+
+/// Split the messages into turns, each starting with a user message which is not a tool result
+fn turns(messages: &[Message]) -> Vec<Range<usize>> {
+    let mut turns = Vec::new();
+    let mut start = 0;
+    for (index, message) in messages.iter().enumerate() {
+        if index > start && starts_turn(message) {
+            turns.push(start..index);
+            start = index;
+        }
+    }
+    if start < messages.len() {
+        turns.push(start..messages.len());
+    }
+    turns
+}
+
+fn starts_turn(message: &Message) -> bool {
+    match message {
+        Message::User { parts } => !parts.iter().any(|part| matches!(part.content, UserMessageContent::ToolResult { .. })),
+        Message::Assistant { .. } => false
+    }
+}
+
+fn transcript(messages: &[Message]) -> String {
+    messages.iter().flat_map(|message| match message {
+        Message::User { parts } => parts.iter().map(|part| match &part.content {
+            UserMessageContent::Text { text } => format!("User: {text}"),
+            UserMessageContent::Image { media_type, .. } | UserMessageContent::Document { media_type, .. } => format!("User attached a file of type {media_type}"),
+            UserMessageContent::ToolResult { content, .. } => format!("Tool result: {content}")
+        }).collect::<Vec<_>>(),
+        Message::Assistant { parts } => parts.iter().map(|part| match &part.content {
+            AssistantMessageContent::Text { text } => format!("Assistant: {text}"),
+            AssistantMessageContent::ToolUse { name, input, .. } => format!("Assistant called tool {name} with {input}")
+        }).collect()
+    }).collect::<Vec<_>>().join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::llm::{base::test::TestLlmClient, AssistantMessageContent, AssistantMessagePart, CompletionRequest, Message, UserMessageContent, UserMessagePart};
+
+    use super::{context_window, ContextManager, ContextStrategy};
+
+    fn user_message(text: &str) -> Message {
+        Message::User { parts: vec![UserMessagePart { content: UserMessageContent::Text { text: text.to_string() } }] }
+    }
+
+    fn assistant_message(text: &str) -> Message {
+        Message::Assistant { parts: vec![AssistantMessagePart { complete: true, content: AssistantMessageContent::Text { text: text.to_string() } }] }
+    }
+
+    /// Three turns of roughly 100 tokens each, the second one with a tool call
+    fn conversation() -> Vec<Message> {
+        let text = "x".repeat(200);
+        vec![
+            user_message(&text),
+            assistant_message(&text),
+            user_message(&text),
+            Message::Assistant { parts: vec![AssistantMessagePart {
+                complete: true,
+                content: AssistantMessageContent::ToolUse { id: "call_1".to_string(), name: "tool".to_string(), input: json!({}) }
+            }] },
+            Message::User { parts: vec![UserMessagePart {
+                content: UserMessageContent::ToolResult { tool_use_id: "call_1".to_string(), content: text.clone(), is_error: false }
+            }] },
+            assistant_message(&text),
+            user_message(&text),
+        ]
+    }
+
+    fn manager(strategy: ContextStrategy) -> ContextManager {
+        ContextManager { reserved_tokens: 0, ..ContextManager::new(100, strategy) }
+    }
+
+    fn request() -> CompletionRequest {
+        CompletionRequest { messages: conversation(), ..Default::default() }
+    }
+
+    #[test]
+    fn knows_context_windows() {
+        assert_eq!(context_window("claude-3-5-haiku-latest"), 200_000);
+        assert_eq!(context_window("gpt-4o-mini"), 128_000);
+        assert_eq!(context_window("unknown"), super::DEFAULT_CONTEXT_WINDOW);
+    }
+
+    #[tokio::test]
+    async fn keeps_fitting_conversation() {
+        let mut client = TestLlmClient::default();
+
+        let prepared = ContextManager::new(200_000, ContextStrategy::DropOldest).prepare(&mut client, request()).await.unwrap();
+
+        assert_eq!(prepared, request());
+    }
+
+    #[tokio::test]
+    async fn drops_oldest_turns() {
+        let mut client = TestLlmClient::default();
+
+        let prepared = manager(ContextStrategy::DropOldest).prepare(&mut client, request()).await.unwrap();
+
+        assert_eq!(prepared.messages, conversation()[6..]);
+        assert!(client.requests.is_empty());
+    }
+
+    #[tokio::test]
+    async fn keeps_pinned_turns() {
+        let mut client = TestLlmClient::default();
+        let mut manager = ContextManager { context_window: 200, ..manager(ContextStrategy::DropOldest) };
+        manager.pinned.insert(0);
+
+        let prepared = manager.prepare(&mut client, request()).await.unwrap();
+
+        assert_eq!(prepared.messages, [&conversation()[0..2], &conversation()[6..]].concat());
+    }
+
+    #[tokio::test]
+    async fn summarizes_oldest_turns() {
+        let mut client = TestLlmClient::default();
+        client.set_response_message("Summary".to_string());
+        let manager = ContextManager { context_window: 1_124, ..manager(ContextStrategy::Summarize) };
+
+        let prepared = manager.prepare(&mut client, request()).await.unwrap();
+        manager.prepare(&mut client, request()).await.unwrap();
+
+        assert_eq!(prepared.messages.len(), 1);
+        match &prepared.messages[0] {
+            Message::User { parts } => {
+                assert_eq!(parts.len(), 2);
+                assert_eq!(parts[0].content, UserMessageContent::Text { text: "Summary of the earlier conversation:\nSummary".to_string() });
+            },
+            _ => panic!("Not a user message")
+        }
+        // The summary is reused for the second request
+        assert_eq!(client.requests.len(), 1);
+        assert!(client.requests[0].messages[0].text().contains("Assistant called tool tool"));
+    }
+}
@@ -0,0 +1,467 @@
+use std::{collections::{BTreeMap, HashMap, HashSet}, process::Stdio, sync::{atomic::{AtomicU64, Ordering}, Arc, Weak}, time::Duration};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::{io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader}, process::{Child, Command}, sync::{oneshot, Mutex}, time::timeout};
+
+use super::{agent::{ToolOutput, ToolRegistry}, ToolDefinition};
+
+/// The MCP protocol version which is requested during initialization
+const PROTOCOL_VERSION: &str = "2025-06-18";
+
+/// JSON-RPC error code for requests with an unknown method
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// How long to wait for the response to a request, unless configured otherwise
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The longest tool name which Anthropic accepts
+pub const MAX_TOOL_NAME_LENGTH: usize = 64;
+
+pub type Result<T> = std::result::Result<T, McpError>;
+
+/// Type for error conditions when talking to an MCP server
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum McpError {
+    /// The server could not be launched or the connection to it failed
+    ConnectionError { reason: String },
+    /// The server answered a request with an error
+    ServerError { code: i64, message: String },
+    /// The server sent a message which does not follow the protocol
+    UnexpectedResponse,
+    /// The server did not answer a request in time
+    Timeout,
+    /// Tools of the server would be offered to the model under the same name
+    DuplicateToolName { name: String }
+}
+
+/// How to launch an MCP server with the stdio transport
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+pub struct McpServerConfig {
+    /// The executable of the server
+    pub command: String,
+
+    /// The arguments passed to the executable
+    #[serde(default)]
+    pub args: Vec<String>,
+
+    /// Additional environment variables for the server process
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+
+    /// How many seconds to wait for the response to a request, 60 by default
+    pub timeout_secs: Option<u64>
+}
+
+/// A client for a Model Context Protocol server
+///
+/// The client speaks JSON-RPC 2.0 with newline-delimited messages. Clones share the same
+/// connection; a launched server process is killed once the last clone is dropped.
+#[derive(Clone)]
+pub struct McpClient {
+    connection: Arc<Connection>,
+    timeout: Duration
+}
+
+struct Connection {
+    writer: Mutex<Box<dyn AsyncWrite + Send + Unpin>>,
+    pending_requests: std::sync::Mutex<HashMap<u64, oneshot::Sender<Result<Value>>>>,
+    next_id: AtomicU64,
+    _process: Option<Child>
+}
+
+#[derive(Deserialize)]
+struct IncomingMessage {
+    id: Option<Value>,
+    method: Option<String>,
+    result: Option<Value>,
+    error: Option<RpcError>
+}
+
+#[derive(Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListToolsResult {
+    tools: Vec<McpTool>,
+    next_cursor: Option<String>
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct McpTool {
+    name: String,
+    #[serde(default)]
+    description: String,
+    input_schema: Value
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CallToolResult {
+    #[serde(default)]
+    content: Vec<ToolResultContent>,
+    #[serde(default)]
+    is_error: bool
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+enum ToolResultContent {
+    Text { text: String },
+    #[serde(other)]
+    Other
+}
+
+impl McpClient {
+    /// Launch the server process and initialize the connection over its stdin and stdout
+    pub async fn spawn(config: &McpServerConfig) -> Result<Self> {
+        let mut process = Command::new(&config.command)
+            .args(&config.args)
+            .envs(&config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|err| McpError::ConnectionError { reason: format!("Cannot launch {}: {err}", config.command) })?;
+        let stdin = process.stdin.take().expect("Stdin of MCP server is piped");
+        let stdout = process.stdout.take().expect("Stdout of MCP server is piped");
+        let timeout = config.timeout_secs.map_or(DEFAULT_TIMEOUT, Duration::from_secs);
+        Self::initialize(stdout, stdin, Some(process), timeout).await
+    }
+
+    /// Initialize a connection over an existing transport, e.g. the stdio of a running server
+    pub async fn connect<R, W>(reader: R, writer: W) -> Result<Self>
+        where R: AsyncRead + Send + Unpin + 'static, W: AsyncWrite + Send + Unpin + 'static
+    {
+        Self::initialize(reader, writer, None, DEFAULT_TIMEOUT).await
+    }
+
+    /// Wait at most `timeout` for the responses to further requests
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    async fn initialize<R, W>(reader: R, writer: W, process: Option<Child>, timeout: Duration) -> Result<Self>
+        where R: AsyncRead + Send + Unpin + 'static, W: AsyncWrite + Send + Unpin + 'static
+    {
+        let connection = Arc::new(Connection {
+            writer: Mutex::new(Box::new(writer)),
+            pending_requests: std::sync::Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            _process: process
+        });
+        tokio::spawn(receive_messages(reader, Arc::downgrade(&connection)));
+
+        let client = Self { connection, timeout };
+        client.request("initialize", json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": {},
+            "clientInfo": { "name": "lliminal", "version": env!("CARGO_PKG_VERSION") }
+        })).await?;
+        client.connection.send(json!({ "jsonrpc": "2.0", "method": "notifications/initialized" })).await?;
+        Ok(client)
+    }
+
+    /// List all tools which the server offers
+    pub async fn list_tools(&self) -> Result<Vec<ToolDefinition>> {
+        let mut tools = Vec::new();
+        let mut cursor = None;
+        loop {
+            let params = match cursor {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({})
+            };
+            let result: ListToolsResult = serde_json::from_value(self.request("tools/list", params).await?)
+                .map_err(|_| McpError::UnexpectedResponse)?;
+            tools.extend(result.tools.into_iter().map(|tool| ToolDefinition {
+                name: tool.name,
+                description: tool.description,
+                input_schema: tool.input_schema
+            }));
+            match result.next_cursor {
+                Some(next_cursor) => cursor = Some(next_cursor),
+                None => return Ok(tools)
+            }
+        }
+    }
+
+    /// Call a tool of the server; text content of the result is concatenated
+    pub async fn call_tool(&self, name: &str, arguments: Value) -> Result<ToolOutput> {
+        let result: CallToolResult = serde_json::from_value(
+            self.request("tools/call", json!({ "name": name, "arguments": arguments })).await?
+        ).map_err(|_| McpError::UnexpectedResponse)?;
+        let text = result.content.into_iter().map(|content| match content {
+            ToolResultContent::Text { text } => text,
+            ToolResultContent::Other => "[unsupported content]".to_string()
+        }).collect::<Vec<_>>().join("\n");
+        Ok(if result.is_error { Err(text) } else { Ok(text) })
+    }
+
+    /// Register all tools of the server, so that calls are routed to this client
+    ///
+    /// The tools are named after the server, see [`tool_name`], so that tools of different servers do not clash.
+    /// Fails without registering any tool if two tools would get the same name, or a tool the name of one which is
+    /// registered already.
+    pub async fn register_tools(&self, server: &str, registry: &mut ToolRegistry) -> Result<()> {
+        let tools = self.list_tools().await?;
+        let mut names = HashSet::new();
+        for definition in &tools {
+            let name = tool_name(server, &definition.name);
+            if registry.contains(&name) || !names.insert(name.clone()) {
+                return Err(McpError::DuplicateToolName { name });
+            }
+        }
+        for mut definition in tools {
+            let client = self.clone();
+            let name = definition.name.clone();
+            definition.name = tool_name(server, &name);
+            registry.register(definition, move |input| {
+                let client = client.clone();
+                let name = name.clone();
+                async move {
+                    client.call_tool(&name, input).await.unwrap_or_else(|err| Err(format!("MCP request failed: {err:?}")))
+                }
+            });
+        }
+        Ok(())
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.connection.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.connection.pending_requests.lock().expect("Pending requests are poisoned").insert(id, sender);
+        self.connection.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })).await?;
+        match timeout(self.timeout, receiver).await {
+            Ok(response) => response.unwrap_or_else(|_| Err(McpError::ConnectionError { reason: "Connection closed".to_string() })),
+            Err(_) => {
+                self.connection.pending_requests.lock().expect("Pending requests are poisoned").remove(&id);
+                Err(McpError::Timeout)
+            }
+        }
+    }
+}
+
+/// The name under which a tool of an MCP server is offered to the model, e.g. `files__read`
+///
+/// Characters which model providers do not accept in tool names are replaced by underscores, and names are cut to
+/// [`MAX_TOOL_NAME_LENGTH`]. Names which are changed that way, or whose server name contains `__`, end with a hash of
+/// the server and tool names instead, so that different tools keep different names.
+pub fn tool_name(server: &str, tool: &str) -> String {
+    let name = format!("{server}__{tool}");
+    let cleaned: String = name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .collect();
+    if cleaned == name && !server.contains("__") && name.len() <= MAX_TOOL_NAME_LENGTH {
+        return name;
+    }
+    let suffix = format!("_{:08x}", name_hash(server, tool));
+    let mut shortened = cleaned;
+    // Only ASCII characters are left, so that any length is a character boundary
+    shortened.truncate(MAX_TOOL_NAME_LENGTH - suffix.len());
+    shortened + &suffix
+}
+
+/// An FNV-1a hash of the server and tool names, which is the same on every run and platform
+fn name_hash(server: &str, tool: &str) -> u32 {
+    server.bytes().chain([0]).chain(tool.bytes())
+        .fold(0x811c_9dc5, |hash, byte| (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193))
+}
+
+impl Connection {
+    async fn send(&self, message: Value) -> Result<()> {
+        let mut line = message.to_string();
+        line.push('\n');
+        let mut writer = self.writer.lock().await;
+        let result = match writer.write_all(line.as_bytes()).await {
+            Ok(()) => writer.flush().await,
+            Err(err) => Err(err)
+        };
+        result.map_err(|err| McpError::ConnectionError { reason: err.to_string() })
+    }
+
+    fn resolve(&self, id: u64, result: Result<Value>) {
+        let sender = self.pending_requests.lock().expect("Pending requests are poisoned").remove(&id);
+        if let Some(sender) = sender {
+            let _ = sender.send(result);
+        }
+    }
+}
+
+/// Dispatch incoming messages until the server closes the connection or the client is dropped
+async fn receive_messages<R: AsyncRead + Unpin>(reader: R, connection: Weak<Connection>) {
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let Some(connection) = connection.upgrade() else {
+            return;
+        };
+        let Ok(message) = serde_json::from_str::<IncomingMessage>(&line) else {
+            continue;
+        };
+        match message {
+            // Requests from the server; only pings are supported
+            IncomingMessage { id: Some(id), method: Some(method), .. } => {
+                let response = if method == "ping" {
+                    json!({ "jsonrpc": "2.0", "id": id, "result": {} })
+                } else {
+                    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": METHOD_NOT_FOUND, "message": "Method not found" } })
+                };
+                let _ = connection.send(response).await;
+            },
+            // Notifications from the server are not needed
+            IncomingMessage { method: Some(_), .. } => {},
+            IncomingMessage { id: Some(id), result, error, .. } => {
+                let Some(id) = id.as_u64() else {
+                    continue;
+                };
+                let result = match (result, error) {
+                    (_, Some(error)) => Err(McpError::ServerError { code: error.code, message: error.message }),
+                    (Some(result), None) => Ok(result),
+                    (None, None) => Err(McpError::UnexpectedResponse)
+                };
+                connection.resolve(id, result);
+            },
+            IncomingMessage { .. } => {}
+        }
+    }
+    // Fail all requests which are still waiting for a response
+    if let Some(connection) = connection.upgrade() {
+        connection.pending_requests.lock().expect("Pending requests are poisoned").clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use tokio::io::{duplex, AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream};
+
+    use crate::llm::{agent::ToolRegistry, ToolDefinition};
+
+    use std::time::Duration;
+
+    use super::{tool_name, McpClient, McpError, MAX_TOOL_NAME_LENGTH};
+
+    /// A minimal MCP server with an echo tool and a failing tool
+    async fn run_test_server(stream: DuplexStream) {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut lines = BufReader::new(reader).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let request: Value = serde_json::from_str(&line).unwrap();
+            let result = match request["method"].as_str().unwrap() {
+                "initialize" => json!({ "protocolVersion": "2025-06-18", "capabilities": { "tools": {} }, "serverInfo": { "name": "test", "version": "1.0" } }),
+                "tools/list" if request["params"]["cursor"].is_null() => json!({
+                    "tools": [{ "name": "echo", "description": "Echo the text", "inputSchema": { "type": "object" } }],
+                    "nextCursor": "2"
+                }),
+                "tools/list" => json!({
+                    "tools": [{ "name": "fail", "inputSchema": { "type": "object" } }]
+                }),
+                "tools/call" if request["params"]["name"] == "echo" => json!({
+                    "content": [{ "type": "text", "text": request["params"]["arguments"]["text"] }]
+                }),
+                "tools/call" => json!({ "content": [{ "type": "text", "text": "Failed" }], "isError": true }),
+                _ => continue
+            };
+            let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }).to_string() + "\n";
+            writer.write_all(response.as_bytes()).await.unwrap();
+        }
+    }
+
+    async fn connect() -> McpClient {
+        let (client_stream, server_stream) = duplex(4096);
+        tokio::spawn(run_test_server(server_stream));
+        let (reader, writer) = tokio::io::split(client_stream);
+        McpClient::connect(reader, writer).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn lists_tools() {
+        let client = connect().await;
+
+        let tools = client.list_tools().await.unwrap();
+
+        assert_eq!(tools, vec![
+            ToolDefinition { name: "echo".to_string(), description: "Echo the text".to_string(), input_schema: json!({ "type": "object" }) },
+            ToolDefinition { name: "fail".to_string(), description: "".to_string(), input_schema: json!({ "type": "object" }) }
+        ]);
+    }
+
+    #[tokio::test]
+    async fn routes_registered_tools_to_server() {
+        let client = connect().await;
+        let mut registry = ToolRegistry::default();
+
+        client.register_tools("test", &mut registry).await.unwrap();
+
+        assert_eq!(registry.call("test__echo", json!({ "text": "Hello" })).await, Ok("Hello".to_string()));
+        assert_eq!(registry.call("test__fail", json!({})).await, Err("Failed".to_string()));
+    }
+
+    #[tokio::test]
+    async fn times_out_unanswered_requests() {
+        let client = connect().await.with_timeout(Duration::from_millis(10));
+
+        let result = client.request("unknown", json!({})).await;
+
+        assert_eq!(result, Err(McpError::Timeout));
+        assert!(client.connection.pending_requests.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn names_tools_after_server() {
+        assert_eq!(tool_name("files", "read"), "files__read");
+        assert_eq!(tool_name("my-files", "read_all"), "my-files__read_all");
+        assert_eq!(tool_name("my files", "read.all"), tool_name("my files", "read.all"));
+        assert!(tool_name("my files", "read.all").starts_with("my_files__read_all_"));
+    }
+
+    #[test]
+    fn keeps_names_of_different_tools_apart() {
+        assert_ne!(tool_name("srv", "my.tool"), tool_name("srv", "my_tool"));
+        assert_ne!(tool_name("a__b", "c"), tool_name("a", "b__c"));
+        assert_eq!(tool_name("a", "b__c"), "a__b__c");
+    }
+
+    #[test]
+    fn shortens_long_names_with_a_hash() {
+        let server = "s".repeat(40);
+        let first = tool_name(&server, &"t".repeat(40));
+        let second = tool_name(&server, &format!("{}u", "t".repeat(39)));
+
+        assert_eq!(first.len(), MAX_TOOL_NAME_LENGTH);
+        assert_eq!(second.len(), MAX_TOOL_NAME_LENGTH);
+        assert_ne!(first, second);
+        assert!(first.starts_with(&format!("{server}__t")));
+        assert_eq!(tool_name(&"s".repeat(30), &"t".repeat(32)).len(), MAX_TOOL_NAME_LENGTH);
+    }
+
+    #[tokio::test]
+    async fn rejects_tools_whose_names_are_taken() {
+        let client = connect().await;
+        let mut registry = ToolRegistry::default();
+        client.register_tools("test", &mut registry).await.unwrap();
+        let mut other = ToolRegistry::default();
+        other.register(ToolDefinition { name: "test__echo".to_string(), description: String::new(), input_schema: json!({}) }, |_| async { Ok(String::new()) });
+
+        let result = client.register_tools("test", &mut other).await;
+
+        assert_eq!(result, Err(McpError::DuplicateToolName { name: "test__echo".to_string() }));
+        assert!(!other.contains("test__fail"));
+        assert!(registry.contains("test__fail"));
+    }
+
+    #[tokio::test]
+    async fn fails_to_launch_missing_server() {
+        let config = super::McpServerConfig { command: "/nonexistent/mcp-server".to_string(), ..Default::default() };
+
+        let result = McpClient::spawn(&config).await;
+
+        assert!(matches!(result, Err(McpError::ConnectionError { .. })));
+    }
+}
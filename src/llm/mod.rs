@@ -1,5 +1,10 @@
 mod base;
 
+pub mod agent;
 pub mod anthropic;
+pub mod context;
+pub mod mcp;
+pub mod schema;
+pub mod structured;
 
 pub use base::*;
@@ -0,0 +1,171 @@
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use super::CompletionRequest;
+
+/// The name of the field which holds the format version of a document
+const VERSION_FIELD: &str = "version";
+
+/// Type for error conditions on reading a versioned document
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SchemaError {
+    /// The document was written by a newer version, or its version cannot be migrated
+    UnsupportedVersion { version: u64 },
+    /// The document is no valid JSON or does not match its format
+    InvalidDocument { reason: String }
+}
+
+/// A type which is stored as a JSON object with a format version
+///
+/// Whenever the JSON format of a type changes incompatibly, its `VERSION` is increased and
+/// [`Versioned::migrate`] learns to convert documents of the previous version, so that old
+/// documents can still be read.
+pub trait Versioned: Serialize + DeserializeOwned {
+    /// The current format version
+    const VERSION: u64;
+
+    /// Convert a document of version `version` into version `version + 1`
+    fn migrate(version: u64, _document: Value) -> Result<Value, SchemaError> {
+        Err(SchemaError::UnsupportedVersion { version })
+    }
+}
+
+/// The provider-neutral format of a conversation: system prompts, messages, tools and response format
+///
+/// Version 1 stores messages with a `role` and a list of `parts`, each tagged with a `type`.
+impl Versioned for CompletionRequest {
+    const VERSION: u64 = 1;
+}
+
+/// Serialize the value together with its format version
+pub fn to_json<T: Versioned>(value: &T) -> Result<String, SchemaError> {
+    serde_json::to_string_pretty(&to_value(value)?).map_err(invalid_document)
+}
+
+/// Serialize the value into a JSON object together with its format version
+pub fn to_value<T: Versioned>(value: &T) -> Result<Value, SchemaError> {
+    let mut document = match serde_json::to_value(value).map_err(invalid_document)? {
+        Value::Object(document) => document,
+        _ => return Err(SchemaError::InvalidDocument { reason: "Versioned values must be JSON objects".to_string() })
+    };
+    document.insert(VERSION_FIELD.to_string(), T::VERSION.into());
+    Ok(Value::Object(document))
+}
+
+/// Deserialize a versioned document, migrating it from older versions if necessary
+pub fn from_json<T: Versioned>(json: &str) -> Result<T, SchemaError> {
+    from_value(serde_json::from_str(json).map_err(invalid_document)?)
+}
+
+/// Deserialize a versioned JSON object, migrating it from older versions if necessary
+pub fn from_value<T: Versioned>(document: Value) -> Result<T, SchemaError> {
+    let mut document = match document {
+        Value::Object(document) => document,
+        _ => return Err(SchemaError::InvalidDocument { reason: "Versioned documents must be JSON objects".to_string() })
+    };
+    let mut version = document.remove(VERSION_FIELD)
+        .and_then(|version| version.as_u64())
+        .ok_or_else(|| SchemaError::InvalidDocument { reason: "Missing format version".to_string() })?;
+    if version > T::VERSION {
+        return Err(SchemaError::UnsupportedVersion { version });
+    }
+    while version < T::VERSION {
+        document = match T::migrate(version, Value::Object(document))? {
+            Value::Object(document) => document,
+            _ => return Err(SchemaError::InvalidDocument { reason: "Migration did not return a JSON object".to_string() })
+        };
+        version += 1;
+    }
+    serde_json::from_value(Value::Object(document)).map_err(invalid_document)
+}
+
+fn invalid_document(err: serde_json::Error) -> SchemaError {
+    SchemaError::InvalidDocument { reason: err.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use serde_json::{json, Value};
+
+    use crate::llm::{AssistantMessageContent, AssistantMessagePart, CompletionRequest, Message, SystemPrompt, UserMessageContent, UserMessagePart};
+
+    use super::{from_json, from_value, to_value, SchemaError, Versioned};
+
+    fn conversation() -> CompletionRequest {
+        CompletionRequest {
+            system: vec![SystemPrompt { content: "Be brief".to_string() }],
+            messages: vec![
+                Message::User { parts: vec![
+                    UserMessagePart { content: UserMessageContent::Text { text: "Weather?".to_string() } }
+                ] },
+                Message::Assistant { parts: vec![
+                    AssistantMessagePart { complete: true, content: AssistantMessageContent::ToolUse { id: "call_1".to_string(), name: "weather".to_string(), input: json!({ "city": "Berlin" }) } }
+                ] },
+                Message::User { parts: vec![
+                    UserMessagePart { content: UserMessageContent::ToolResult { tool_use_id: "call_1".to_string(), content: "Sunny".to_string(), is_error: false } }
+                ] },
+                Message::Assistant { parts: vec![
+                    AssistantMessagePart { complete: true, content: AssistantMessageContent::Text { text: "Sunny".to_string() } }
+                ] }
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn writes_stable_format() {
+        assert_eq!(to_value(&conversation()).unwrap(), json!({
+            "version": 1,
+            "system": [{ "content": "Be brief" }],
+            "messages": [
+                { "role": "user", "parts": [{ "type": "text", "text": "Weather?" }] },
+                { "role": "assistant", "parts": [{ "complete": true, "type": "tool_use", "id": "call_1", "name": "weather", "input": { "city": "Berlin" } }] },
+                { "role": "user", "parts": [{ "type": "tool_result", "tool_use_id": "call_1", "content": "Sunny", "is_error": false }] },
+                { "role": "assistant", "parts": [{ "complete": true, "type": "text", "text": "Sunny" }] }
+            ],
+            "tools": [],
+            "response_format": null
+        }));
+    }
+
+    #[test]
+    fn reads_written_format() {
+        let json = super::to_json(&conversation()).unwrap();
+
+        assert_eq!(from_json::<CompletionRequest>(&json), Ok(conversation()));
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let result = from_value::<CompletionRequest>(json!({ "version": 2, "messages": [] }));
+
+        assert_eq!(result, Err(SchemaError::UnsupportedVersion { version: 2 }));
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Renamed {
+        title: String
+    }
+
+    impl Versioned for Renamed {
+        const VERSION: u64 = 2;
+
+        fn migrate(version: u64, mut document: Value) -> Result<Value, SchemaError> {
+            match version {
+                1 => {
+                    let name = document["name"].take();
+                    Ok(json!({ "title": name }))
+                },
+                _ => Err(SchemaError::UnsupportedVersion { version })
+            }
+        }
+    }
+
+    #[test]
+    fn migrates_older_versions() {
+        let result = from_value::<Renamed>(json!({ "version": 1, "name": "Old" }));
+
+        assert_eq!(result, Ok(Renamed { title: "Old".to_string() }));
+    }
+}
@@ -0,0 +1,176 @@
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+
+use super::{final_response, CompletionRequest, LlmClient, LlmError, Message, ResponseFormat, Result, UserMessageContent, UserMessagePart};
+
+/// The number of requests which are sent before giving up on getting a valid response
+pub const DEFAULT_ATTEMPTS: usize = 3;
+
+/// Complete a request and deserialize the response into a value of type `T`
+///
+/// The JSON schema of `T` is sent as response format, so `T` should be represented as a JSON
+/// object. If the response does not match, the model is asked to repair it, see
+/// [`complete_structured_with_attempts`].
+pub async fn complete_structured<T, C>(client: &mut C, request: &CompletionRequest) -> Result<T>
+    where T: DeserializeOwned + JsonSchema, C: LlmClient
+{
+    complete_structured_with_attempts(client, request, DEFAULT_ATTEMPTS).await
+}
+
+/// Complete a request and deserialize the response into a value of type `T`
+///
+/// Whenever the response cannot be deserialized, the invalid response and the error are appended
+/// to the conversation and the model is asked for a corrected answer. At most `attempts` requests
+/// are sent.
+pub async fn complete_structured_with_attempts<T, C>(client: &mut C, request: &CompletionRequest, attempts: usize) -> Result<T>
+    where T: DeserializeOwned + JsonSchema, C: LlmClient
+{
+    let mut request = CompletionRequest {
+        response_format: Some(response_format::<T>()),
+        ..request.clone()
+    };
+    let mut reason = String::new();
+    for _ in 0..attempts.max(1) {
+        let mut response = final_response(client, &request).await?;
+        match parse_document(&response_text(&response)) {
+            Ok(value) => return Ok(value),
+            Err(err) => {
+                reason = err.to_string();
+                request.messages.append(&mut response);
+                request.messages.push(repair_message(&reason));
+            }
+        }
+    }
+    Err(LlmError::InvalidStructuredOutput { reason })
+}
+
+fn response_format<T: JsonSchema>() -> ResponseFormat {
+    let mut schema = schemars::schema_for!(T);
+    // The meta schema is irrelevant for the model and not accepted by every provider
+    schema.remove("$schema");
+    ResponseFormat::JsonSchema { name: format_name(&T::schema_name()), schema: schema.to_value() }
+}
+
+/// Restrict the schema name to the characters which providers accept as tool or format name
+fn format_name(schema_name: &str) -> String {
+    schema_name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .take(64)
+        .collect()
+}
+
+fn response_text(messages: &[Message]) -> String {
+    messages.iter()
+        .filter(|message| matches!(message, Message::Assistant { .. }))
+        .map(Message::text)
+        .collect()
+}
+
+/// Parse the JSON document, which might be wrapped in a Markdown code block
+fn parse_document<T: DeserializeOwned>(text: &str) -> serde_json::Result<T> {
+    let text = text.trim();
+    let document = text.strip_prefix("```")
+        .and_then(|fenced| fenced.strip_suffix("```"))
+        .map(|fenced| fenced.split_once('\n').map_or("", |(_language, code)| code))
+        .unwrap_or(text);
+    serde_json::from_str(document)
+}
+
+fn repair_message(reason: &str) -> Message {
+    Message::User { parts: vec![
+        UserMessagePart {
+            content: UserMessageContent::Text {
+                text: format!("The previous response is invalid: {reason}. Respond again with only a JSON document which conforms to the requested schema.")
+            }
+        }
+    ] }
+}
+
+#[cfg(test)]
+mod tests {
+    use schemars::JsonSchema;
+    use serde::Deserialize;
+
+    use crate::llm::{base::test::TestLlmClient, CompletionRequest, LlmError, Message, ResponseFormat, UserMessageContent, UserMessagePart};
+
+    use super::{complete_structured, parse_document};
+
+    #[derive(Debug, Deserialize, JsonSchema, PartialEq)]
+    struct Person {
+        name: String,
+        age: u32
+    }
+
+    fn request() -> CompletionRequest {
+        CompletionRequest {
+            system: vec![],
+            messages: vec![
+                Message::User { parts: vec![
+                    UserMessagePart { content: UserMessageContent::Text { text: "Alice is 30".to_string() } }
+                ] }
+            ],
+            tools: vec![],
+            response_format: None
+        }
+    }
+
+    #[tokio::test]
+    async fn deserializes_response() {
+        let mut client = TestLlmClient::default();
+        client.set_response_message(r#"{"name": "Alice", "age": 30}"#.to_string());
+
+        let person = complete_structured::<Person, _>(&mut client, &request()).await;
+
+        assert_eq!(person, Ok(Person { name: "Alice".to_string(), age: 30 }));
+        assert_eq!(client.requests.len(), 1);
+        match &client.requests[0].response_format {
+            Some(ResponseFormat::JsonSchema { name, schema }) => {
+                assert_eq!(name, "Person");
+                assert_eq!(schema["type"], "object");
+                assert!(schema.get("$schema").is_none());
+            },
+            None => panic!("No response format requested")
+        }
+    }
+
+    #[tokio::test]
+    async fn repairs_invalid_response() {
+        let mut client = TestLlmClient::default();
+        client.set_response_factory(|request| match request.messages.len() {
+            1 => vec![TestLlmClient::response_for_message(r#"{"name": "Alice"}"#.to_string())],
+            _ => vec![TestLlmClient::response_for_message("```json\n{\"name\": \"Alice\", \"age\": 30}\n```".to_string())]
+        });
+
+        let person = complete_structured::<Person, _>(&mut client, &request()).await;
+
+        assert_eq!(person, Ok(Person { name: "Alice".to_string(), age: 30 }));
+        assert_eq!(client.requests.len(), 2);
+        let repair_request = &client.requests[1];
+        assert_eq!(repair_request.messages.len(), 3);
+        assert!(matches!(repair_request.messages[1], Message::Assistant { .. }));
+        match &repair_request.messages[2] {
+            Message::User { parts } => match &parts[0].content {
+                UserMessageContent::Text { text } => assert!(text.contains("missing field `age`")),
+                _ => panic!("Not a text part")
+            },
+            _ => panic!("Not a user message")
+        }
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_attempts() {
+        let mut client = TestLlmClient::default();
+        client.set_response_message("Alice is 30 years old".to_string());
+
+        let person = complete_structured::<Person, _>(&mut client, &request()).await;
+
+        assert!(matches!(person, Err(LlmError::InvalidStructuredOutput { .. })));
+        assert_eq!(client.requests.len(), super::DEFAULT_ATTEMPTS);
+    }
+
+    #[test]
+    fn parses_fenced_document() {
+        assert_eq!(parse_document::<u32>("```\n42\n```").unwrap(), 42);
+        assert_eq!(parse_document::<u32>(" 42 ").unwrap(), 42);
+    }
+}
@@ -1,55 +1,205 @@
+use std::{cell::{Cell, RefCell}, sync::Arc};
+
 use crossterm::event::Event as CrosstermEvent;
-use ratatui::{buffer::Buffer, layout::Rect, widgets::Widget, DefaultTerminal, Frame};
+use lliminal::llm::agent::ToolRegistry;
+use ratatui::{buffer::Buffer, layout::{Constraint, Layout, Rect}, widgets::Widget, DefaultTerminal, Frame};
 use tokio::sync::{mpsc::UnboundedSender, watch};
-use tui_input::Input;
 
-use super::{controller::{ChatController, Controller, CrosstermController}, event::{Event, EventHandler}, view::ChatWidget, viewmodel::{AppState, Chat}};
+use super::{clipboard, config::ContextConfig, controller::{ChatController, Controller, CrosstermController, Keymap, SidebarController}, event::{Event, EventHandler}, external_editor, session::SessionStore, terminal, view::{ChatWidget, SidebarWidget, Theme}, viewmodel::{AppState, Chat, ChatError, Editor, Sidebar, VimMode}};
 
+/// The number of ticks per frame of animations, which are drawn while a request is running
+const FRAME_TICKS: usize = 3;
 
 pub struct App {
-    state: watch::Receiver<AppState>,
+    state: watch::Sender<AppState>,
     event_handler: EventHandler,
     crossterm_controller: UnboundedSender<CrosstermEvent>,
-    chat_widget: ChatWidget
+    chat_widget: ChatWidget,
+    sidebar_widget: SidebarWidget,
+    chat: watch::Sender<Chat>,
+    chat_input: watch::Sender<Editor>,
+    sessions: SessionStore,
+    /// Tells whether any view model changed since the last draw
+    changes: Changes,
+    /// The number of ticks since the last frame of animations
+    ticks: usize
+}
+
+/// Receivers of all view models, so that the app only draws after something changed
+struct Changes {
+    app_state: watch::Receiver<AppState>,
+    chat: watch::Receiver<Chat>,
+    chat_input: watch::Receiver<Editor>,
+    sidebar: watch::Receiver<Sidebar>
 }
 
-impl Default for App {
-    fn default() -> Self {
+impl Changes {
+    /// Whether any view model changed since the last call
+    fn take(&mut self) -> bool {
+        let changed = [
+            self.app_state.has_changed(),
+            self.chat.has_changed(),
+            self.chat_input.has_changed(),
+            self.sidebar.has_changed(),
+        ].into_iter().any(|changed| changed.unwrap_or_default());
+        self.app_state.mark_unchanged();
+        self.chat.mark_unchanged();
+        self.chat_input.mark_unchanged();
+        self.sidebar.mark_unchanged();
+        changed
+    }
+}
+
+impl App {
+    pub fn new(chat: Chat, tools: ToolRegistry, context: ContextConfig, theme: Theme, keymap: Keymap, sessions: SessionStore) -> Self {
         // View model
-        let (chat_rx, chat_tx) = watch::channel(Chat::default());
-        let (chat_input_rx, chat_input_tx) = watch::channel(Input::default());
-        let (app_state_rx, app_state_tx) = watch::channel(AppState::default());
+        let (chat_rx, chat_tx) = watch::channel(chat);
+        let (chat_input_rx, chat_input_tx) = watch::channel(Editor::default());
+        let app_state_rx = watch::Sender::new(AppState { vim: keymap.vim.then_some(VimMode::Insert), ..AppState::default() });
+        let (sidebar_rx, sidebar_tx) = watch::channel(Sidebar::default());
 
         // Controller
-        let chat_controller = (ChatController { chat: chat_rx, chat_input: chat_input_rx.clone(), self_sender: None }).launch();
-        let crossterm_controller = (CrosstermController { app_state: app_state_rx.clone(), chat: chat_tx.clone(), chat_input: chat_input_rx, chat_controller: chat_controller.clone() }).launch();
+        let sidebar_controller = (SidebarController { app_state: app_state_rx.clone(), sidebar: sidebar_rx, chat: chat_rx.clone(), sessions: sessions.clone() }).launch();
+        let chat_controller = (ChatController {
+            app_state: app_state_rx.clone(),
+            chat: chat_rx.clone(),
+            chat_input: chat_input_rx.clone(),
+            tools,
+            context: context.context_manager(&chat_rx.borrow().model),
+            context_config: context,
+            sessions: sessions.clone(),
+            sidebar_controller: sidebar_controller.clone(),
+            self_sender: None,
+            approved_tools: Arc::default(),
+            tool_approval: Arc::default()
+        }).launch();
+        let crossterm_controller = (CrosstermController {
+            app_state: app_state_rx.clone(),
+            chat: chat_tx.clone(),
+            chat_input: chat_input_rx.clone(),
+            chat_controller: chat_controller.clone(),
+            sidebar: sidebar_tx.clone(),
+            sidebar_controller,
+            keymap,
+            pending_keys: RefCell::default(),
+            pending_modes: Cell::new(&[])
+        }).launch();
 
         // View
-        let chat_widget = ChatWidget { app_state: app_state_rx, chat: chat_tx, chat_input: chat_input_tx };
+        let chat_widget = ChatWidget { app_state: app_state_rx.clone(), chat: chat_tx.clone(), chat_input: chat_input_tx.clone(), theme: theme.clone(), line_cache: RefCell::default() };
+        let changes = Changes { app_state: app_state_rx.subscribe(), chat: chat_tx.clone(), chat_input: chat_input_tx.clone(), sidebar: sidebar_tx.clone() };
+        let sidebar_widget = SidebarWidget { app_state: app_state_rx.clone(), sidebar: sidebar_tx, chat: chat_tx.clone(), theme };
 
         Self {
-            state: app_state_tx,
+            state: app_state_rx,
             event_handler: EventHandler::new(),
             crossterm_controller,
-            chat_widget
+            chat_widget,
+            sidebar_widget,
+            chat: chat_rx,
+            chat_input: chat_input_rx,
+            sessions,
+            changes,
+            ticks: 0
         }
     }
-}
 
-impl App {
     pub async fn run(mut self, mut terminal: DefaultTerminal) -> color_eyre::Result<()> {
+        // Terminal events, such as resizing, and returning from the editor are drawn even if they change nothing
+        let mut terminal_event = true;
         while self.state.borrow().running {
-            terminal.draw(|frame| self.draw(frame))?;
+            // The controllers update the view models in the background; ticks wake the loop to draw their changes
+            if self.changes.take() || terminal_event {
+                let size = terminal.size()?;
+                self.layout(Rect::new(0, 0, size.width, size.height));
+                // The new layout is drawn right away
+                self.changes.take();
+                terminal.draw(|frame| self.draw(frame))?;
+            }
+            terminal_event = false;
             for event in self.event_handler.recv_many().await? {
                 match event {
-                    Event::Crossterm(event) => self.crossterm_controller.send(event)?,
-                    Event::Tick => {},
+                    Event::Crossterm(event) => {
+                        terminal_event = true;
+                        self.crossterm_controller.send(event)?;
+                    },
+                    Event::Tick => self.tick()
                 }
             }
+            if self.state.borrow().external_editor {
+                self.edit_input(&mut terminal).await?;
+                terminal_event = true;
+            }
+            let mut copied = None;
+            self.state.send_if_modified(|state| {
+                copied = state.clipboard.take();
+                false
+            });
+            if let Some(text) = copied && let Err(err) = clipboard::copy_with_terminal(terminal.backend_mut(), &text) {
+                self.chat.send_modify(|chat| {
+                    chat.notice = None;
+                    chat.error = Some(ChatError::Message(format!("Cannot copy to the clipboard: {err}")));
+                });
+            }
+        }
+        // Save the latest scroll position
+        self.sessions.save_chat(&self.chat.borrow())
+    }
+
+    /// Advance animations while a request is running
+    fn tick(&mut self) {
+        if self.chat.borrow().user_input {
+            return;
+        }
+        self.ticks += 1;
+        if self.ticks >= FRAME_TICKS {
+            self.ticks = 0;
+            self.state.send_modify(|state| { state.tick = state.tick.wrapping_add(1); });
+        }
+    }
+
+    /// Edit the input in an external editor, which takes over the terminal meanwhile
+    async fn edit_input(&mut self, terminal: &mut DefaultTerminal) -> color_eyre::Result<()> {
+        self.state.send_modify(|state| { state.external_editor = false; });
+        let text = self.chat_input.borrow().text();
+        self.event_handler.stop().await;
+        terminal::suspend()?;
+        let edited = external_editor::edit(&text).await;
+        terminal::resume(terminal)?;
+        self.event_handler = EventHandler::new();
+        match edited {
+            Ok(text) => { self.chat_input.send_replace(Editor::new(&text)); },
+            Err(err) => self.chat.send_modify(|chat| {
+                chat.error = Some(ChatError::Editor(format!("{err:#}")));
+            })
         }
         Ok(())
     }
 
+    /// Lay out the chat before it is drawn into the area
+    fn layout(&self, area: Rect) {
+        let (_, chat_area) = self.areas(area);
+        let layout = self.chat_widget.layout(chat_area);
+        self.state.send_if_modified(|state| {
+            let modified = state.chat_layout != layout;
+            state.chat_layout = layout;
+            modified
+        });
+    }
+
+    /// The area of the sidebar, if it is visible, and the area of the chat
+    fn areas(&self, area: Rect) -> (Option<Rect>, Rect) {
+        if self.sidebar_widget.sidebar.borrow().visible {
+            let [sidebar_area, chat_area] = Layout::horizontal([
+                Constraint::Length(32),
+                Constraint::Min(1),
+            ]).areas(area);
+            (Some(sidebar_area), chat_area)
+        } else {
+            (None, area)
+        }
+    }
+
     fn draw(&self, frame: &mut Frame) {
         frame.render_widget(self, frame.area());
         if let Some(cursor_position) = self.state.borrow().cursor_position {
@@ -60,6 +210,11 @@ impl App {
 
 impl Widget for &App {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        self.chat_widget.render(area, buf);
+        let (sidebar_area, chat_area) = self.areas(area);
+        // The chat resets the cursor, so the sidebar is rendered last
+        self.chat_widget.render(chat_area, buf);
+        if let Some(sidebar_area) = sidebar_area {
+            self.sidebar_widget.render(sidebar_area, buf);
+        }
     }
 }
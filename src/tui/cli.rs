@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+use super::export::ExportFormat;
+
+/// LLiMinal - A TUI tool for interfacing with LLMs
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Reopen the saved session with the given identifier or title
+    #[arg(long, value_name = "SESSION", conflicts_with = "continue_last")]
+    pub resume: Option<String>,
+
+    /// Reopen the most recently updated session
+    #[arg(short = 'c', long = "continue")]
+    pub continue_last: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Export a saved session as Markdown, HTML or JSON
+    Export(ExportArgs)
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// The identifier or title of the session
+    pub session: String,
+
+    /// The file to write; the session is printed if it is not given
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// The format; derived from the extension of the output file, or Markdown by default
+    #[arg(short, long)]
+    pub format: Option<ExportFormat>
+}
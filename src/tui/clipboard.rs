@@ -0,0 +1,69 @@
+use std::{env, io::Write};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+/// Copy text to the system clipboard; returns whether it is available
+///
+/// The system clipboard is only used with the `native-clipboard` feature. Otherwise, the terminal
+/// is asked to copy the text with [`copy_with_terminal`], which also works over SSH.
+pub fn copy_native(text: &str) -> bool {
+    #[cfg(feature = "native-clipboard")]
+    return native::copy(text);
+    #[cfg(not(feature = "native-clipboard"))]
+    {
+        let _ = text;
+        false
+    }
+}
+
+/// Copy text to the clipboard of the terminal with an OSC 52 escape sequence
+///
+/// Some terminals ignore the sequence or ask for permission. Within tmux, the sequence is passed
+/// through to the outer terminal, which requires `allow-passthrough` to be enabled.
+pub fn copy_with_terminal(terminal: &mut impl Write, text: &str) -> std::io::Result<()> {
+    write!(terminal, "{}", osc52_sequence(text, env::var_os("TMUX").is_some()))?;
+    terminal.flush()
+}
+
+/// The OSC 52 escape sequence which copies the text, wrapped in a passthrough sequence for tmux
+fn osc52_sequence(text: &str, tmux: bool) -> String {
+    let sequence = format!("\x1b]52;c;{}\x07", STANDARD.encode(text));
+    if tmux {
+        // Escape characters within the passthrough sequence have to be doubled
+        format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+    } else {
+        sequence
+    }
+}
+
+#[cfg(feature = "native-clipboard")]
+mod native {
+    use std::sync::{LazyLock, Mutex};
+
+    use arboard::Clipboard;
+
+    /// The clipboard is kept open, since on some platforms the text is lost when it is closed
+    static CLIPBOARD: LazyLock<Mutex<Option<Clipboard>>> = LazyLock::new(|| Mutex::new(Clipboard::new().ok()));
+
+    /// Copy text to the system clipboard; returns whether it is available
+    pub fn copy(text: &str) -> bool {
+        let mut clipboard = CLIPBOARD.lock().expect("Clipboard lock is poisoned");
+        clipboard.as_mut().is_some_and(|clipboard| clipboard.set_text(text).is_ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::osc52_sequence;
+
+    #[test]
+    fn encodes_the_text_in_an_osc52_sequence() {
+        assert_eq!(osc52_sequence("Hello", false), "\x1b]52;c;SGVsbG8=\x07");
+        assert_eq!(osc52_sequence("", false), "\x1b]52;c;\x07");
+    }
+
+    #[test]
+    fn passes_the_sequence_through_tmux() {
+        assert_eq!(osc52_sequence("Hello", true), "\x1bPtmux;\x1b\x1b]52;c;SGVsbG8=\x07\x1b\\");
+    }
+}
@@ -0,0 +1,244 @@
+use std::{collections::{BTreeMap, HashMap}, fs, path::PathBuf};
+
+use color_eyre::eyre::{eyre, WrapErr};
+use futures::future::join_all;
+use lliminal::llm::{agent::ToolRegistry, context::{self, ContextManager, ContextStrategy}, mcp::{McpClient, McpServerConfig}};
+use serde::Deserialize;
+
+use super::{controller::{Action, KeyMode, KeySequence, Keymap}, view::{StyleConfig, Theme, ThemeElement, ThemeName}};
+
+/// The configuration of the TUI, read from `lliminal/config.toml` in the user's config directory
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// MCP servers by name, whose tools are offered to the model as `<name>__<tool>`
+    pub mcp_servers: BTreeMap<String, McpServerConfig>,
+
+    /// How conversations are kept within the context window of the model
+    pub context: ContextConfig,
+
+    /// The colors and styles of the TUI
+    pub theme: ThemeConfig,
+
+    /// Changes to the default key bindings
+    pub keys: KeysConfig
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ContextConfig {
+    pub strategy: ContextStrategy,
+
+    /// Overrides the known context window of the model, in tokens
+    pub context_window: Option<usize>,
+
+    /// The number of tokens kept free for the response
+    pub reserved_tokens: Option<usize>
+}
+
+/// A built-in theme with changes to the styles of some elements, e.g.
+///
+/// ```toml
+/// [theme]
+/// name = "light"
+/// syntax = "Solarized (light)"
+///
+/// [theme.styles]
+/// user = { fg = "magenta", italic = false }
+/// code-block = { bg = "#f0f0f0" }
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub name: ThemeName,
+
+    /// The syntect theme which colors code blocks
+    pub syntax: Option<String>,
+
+    pub styles: BTreeMap<ThemeElement, StyleConfig>
+}
+
+/// Key bindings by mode, which replace the defaults for the same keys, e.g.
+///
+/// ```toml
+/// [keys]
+/// vim = true
+///
+/// [keys.input]
+/// "enter" = "newline"
+/// "ctrl+s" = "submit"
+///
+/// [keys.select]
+/// "x" = "delete-message"
+/// ```
+///
+/// Keys are chords such as "ctrl+f", "alt+enter", "shift+tab", "G" or "f2", or several chords
+/// separated by spaces, e.g. "g g". Bind a key to "none" to remove its default binding.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct KeysConfig {
+    /// Edit the input in vim-style normal and insert modes
+    pub vim: bool,
+
+    /// Keys which apply everywhere, unless the current mode binds them
+    pub global: HashMap<KeySequence, Action>,
+
+    /// Keys while typing into the input
+    pub input: HashMap<KeySequence, Action>,
+
+    /// Keys in the normal mode of the input, if `vim` is enabled
+    pub normal: HashMap<KeySequence, Action>,
+
+    /// Keys while the input has the focus, in either vim mode
+    pub scroll: HashMap<KeySequence, Action>,
+
+    /// Keys while messages are selected
+    pub select: HashMap<KeySequence, Action>,
+
+    /// Keys while moving between search matches
+    pub search: HashMap<KeySequence, Action>,
+
+    /// Keys while a tool call waits for approval
+    pub approval: HashMap<KeySequence, Action>
+}
+
+impl Config {
+    /// Load the config file, or use the default configuration if there is none
+    pub fn load() -> color_eyre::Result<Self> {
+        match config_path() {
+            Some(path) if path.exists() => {
+                let content = fs::read_to_string(&path)
+                    .wrap_err_with(|| format!("Cannot read config file {}", path.display()))?;
+                toml::from_str(&content)
+                    .wrap_err_with(|| format!("Invalid config file {}", path.display()))
+            },
+            _ => Ok(Self::default())
+        }
+    }
+
+    /// Launch the configured MCP servers at once and collect their tools
+    ///
+    /// Servers which cannot be launched, or whose tools would take the names of tools of other servers, are skipped; the
+    /// reasons are returned along with the tools.
+    pub async fn tools(&self) -> (ToolRegistry, Vec<String>) {
+        let servers = self.mcp_servers.iter().map(|(name, server)| async move {
+            McpClient::spawn(server).await
+                .map(|client| (name, client))
+                .map_err(|err| format!("Cannot launch MCP server {name}: {err:?}"))
+        });
+        let mut tools = ToolRegistry::default();
+        let mut failures = vec![];
+        for result in join_all(servers).await {
+            // The tools are registered one server after the other, so that names which are taken are detected
+            let registered = match result {
+                Ok((name, client)) => client.register_tools(name, &mut tools).await
+                    .map_err(|err| format!("Cannot register tools of MCP server {name}: {err:?}")),
+                Err(failure) => Err(failure)
+            };
+            if let Err(failure) = registered {
+                failures.push(failure);
+            }
+        }
+        (tools, failures)
+    }
+}
+
+impl ContextConfig {
+    /// The configured context window, or the known one of the model
+    pub fn context_window(&self, model: &str) -> usize {
+        self.context_window.unwrap_or_else(|| context::context_window(model))
+    }
+
+    pub fn context_manager(&self, model: &str) -> ContextManager {
+        let mut context = ContextManager::new(self.context_window(model), self.strategy);
+        if let Some(reserved_tokens) = self.reserved_tokens {
+            context.reserved_tokens = reserved_tokens;
+        }
+        context
+    }
+}
+
+impl ThemeConfig {
+    pub fn theme(&self) -> color_eyre::Result<Theme> {
+        let mut theme = Theme::builtin(self.name);
+        if let Some(syntax) = &self.syntax {
+            theme.set_syntax(syntax).map_err(|err| eyre!("Invalid theme: {err}"))?;
+        }
+        for (element, style) in &self.styles {
+            let current = theme.style_mut(*element);
+            *current = style.apply(*current);
+        }
+        Ok(theme)
+    }
+}
+
+impl KeysConfig {
+    pub fn keymap(&self) -> Keymap {
+        let mut keymap = Keymap::new(self.vim);
+        let modes = [
+            (KeyMode::Global, &self.global),
+            (KeyMode::Input, &self.input),
+            (KeyMode::Normal, &self.normal),
+            (KeyMode::Scroll, &self.scroll),
+            (KeyMode::Select, &self.select),
+            (KeyMode::Search, &self.search),
+            (KeyMode::Approval, &self.approval)
+        ];
+        for (mode, bindings) in modes {
+            for (sequence, action) in bindings {
+                keymap.bind(mode, sequence.clone(), *action);
+            }
+        }
+        keymap
+    }
+}
+
+fn config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("lliminal").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::style::{Color, Modifier};
+
+    use crate::tui::view::{Theme, ThemeName};
+
+    use super::Config;
+
+    #[test]
+    fn loads_a_built_in_theme_with_changed_styles() {
+        let config: Config = toml::from_str(r##"
+            [theme]
+            name = "light"
+            syntax = "Solarized (light)"
+
+            [theme.styles]
+            user = { fg = "magenta", italic = false }
+            code-block = { bg = "#f0f0f0" }
+        "##).unwrap();
+
+        let theme = config.theme.theme().unwrap();
+        let light = Theme::builtin(ThemeName::Light);
+
+        assert_eq!(theme.syntax, "Solarized (light)");
+        assert_eq!(theme.user, light.user.fg(Color::Magenta).remove_modifier(Modifier::ITALIC));
+        assert_eq!(theme.code_block, light.code_block.bg(Color::Rgb(0xf0, 0xf0, 0xf0)));
+        assert_eq!(theme.assistant, light.assistant);
+    }
+
+    #[test]
+    fn uses_the_dark_theme_by_default() {
+        let config: Config = toml::from_str("").unwrap();
+
+        assert_eq!(config.theme.theme().unwrap(), Theme::default());
+    }
+
+    #[test]
+    fn rejects_unknown_themes_and_elements() {
+        let config: Config = toml::from_str("[theme]\nsyntax = \"unknown\"").unwrap();
+
+        assert!(config.theme.theme().is_err());
+        assert!(toml::from_str::<Config>("[theme]\nname = \"unknown\"").is_err());
+        assert!(toml::from_str::<Config>("[theme.styles]\nunknown = { bold = true }").is_err());
+    }
+}
@@ -0,0 +1,252 @@
+use std::sync::Arc;
+
+use futures::FutureExt;
+use lliminal::llm::agent::{ToolApproval, ToolApprover};
+use serde_json::Value;
+use tokio::sync::oneshot;
+use tui_input::Input;
+
+use crate::tui::viewmodel::{ApprovalMode, Chat, ChatError, Editor, ToolApprovalRequest};
+
+use super::ChatController;
+
+/// The reason given to the model for a tool call which was left without a decision
+const LEFT_CHAT_REASON: &str = "the chat was left before the call was approved";
+
+impl ChatController {
+    /// Ask the user about each tool call of the active chat, unless the tool has been approved for all calls
+    ///
+    /// A call is denied if the user leaves the chat before deciding.
+    pub(super) fn tool_approver(&self) -> ToolApprover {
+        let chat = self.chat.clone();
+        let id = self.chat.borrow().id.clone();
+        let approved_tools = self.approved_tools.clone();
+        let tool_approval = self.tool_approval.clone();
+        Arc::new(move |name, input| {
+            let chat = chat.clone();
+            let id = id.clone();
+            let approved_tools = approved_tools.clone();
+            let tool_approval = tool_approval.clone();
+            async move {
+                if approved_tools.lock().expect("Approved tools lock is poisoned").contains(&name) {
+                    return ToolApproval::Approve { input };
+                }
+                let left_chat = || ToolApproval::Deny { reason: Some(LEFT_CHAT_REASON.to_string()) };
+                let is_active = |c: &Chat| c.id == id;
+                let (sender, receiver) = oneshot::channel();
+                let mut sender = Some(sender);
+                let shown = chat.send_if_modified(|c| {
+                    if is_active(c) {
+                        // The receiver is in place before the user can decide
+                        *tool_approval.lock().expect("Tool approval lock is poisoned") = sender.take();
+                        c.approval = Some(ToolApprovalRequest::new(&name, input.clone()));
+                    }
+                    is_active(c)
+                });
+                if !shown {
+                    return left_chat();
+                }
+                let mut changes = chat.subscribe();
+                tokio::select! {
+                    approval = receiver => approval.unwrap_or_else(|_| left_chat()),
+                    _ = changes.wait_for(|c| !is_active(c)) => left_chat()
+                }
+            }.boxed()
+        })
+    }
+
+    /// Execute the tool call with the requested input; `always` approves all further calls of the tool
+    pub(super) fn approve_tool(&self, always: bool) {
+        let Some(request) = self.chat.borrow().approval.clone() else {
+            return;
+        };
+        if always {
+            self.approved_tools.lock().expect("Approved tools lock is poisoned").insert(request.name.clone());
+        }
+        self.decide(ToolApproval::Approve { input: request.input });
+    }
+
+    /// Execute the tool call with the input from the editor, unless it is not valid JSON
+    pub(super) fn approve_edited_tool_input(&self) {
+        // The input is not borrowed while the editor is cleared
+        let text = self.chat_input.borrow().text();
+        match serde_json::from_str::<Value>(&text) {
+            Ok(input) => {
+                self.chat_input.send_replace(Editor::default());
+                self.decide(ToolApproval::Approve { input });
+            },
+            Err(err) => self.chat.send_modify(|chat| {
+                chat.error = Some(ChatError::ToolInput(err.to_string()));
+            })
+        }
+    }
+
+    /// Deny the tool call with the reason which has been typed, if any
+    pub(super) fn deny_tool(&self) {
+        let reason = match self.chat.borrow().approval.as_ref().map(|request| &request.mode) {
+            Some(ApprovalMode::Deny(reason)) => Some(reason.value().trim().to_string()).filter(|reason| !reason.is_empty()),
+            _ => return
+        };
+        self.decide(ToolApproval::Deny { reason });
+    }
+
+    pub(super) fn set_approval_mode(&self, mode: ApprovalMode) {
+        let mut edit_input = None;
+        self.chat.send_modify(|chat| {
+            if let Some(request) = &mut chat.approval {
+                if matches!(mode, ApprovalMode::Edit) {
+                    edit_input = Some(request.pretty_input());
+                } else if matches!(request.mode, ApprovalMode::Edit) {
+                    edit_input = Some(String::new());
+                }
+                request.mode = mode;
+            }
+        });
+        if let Some(text) = edit_input {
+            self.chat_input.send_replace(Editor::new(&text));
+        }
+    }
+
+    pub(super) fn start_deny_tool(&self) {
+        self.set_approval_mode(ApprovalMode::Deny(Input::default()));
+    }
+
+    /// Send the decision to the agent, which waits for it, and close the dialog
+    fn decide(&self, approval: ToolApproval) {
+        let sender = self.tool_approval.lock().expect("Tool approval lock is poisoned").take();
+        self.chat.send_modify(|chat| { chat.approval = None; });
+        if let Some(sender) = sender {
+            // The agent no longer waits if the chat has been left meanwhile
+            let _ = sender.send(approval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lliminal::llm::agent::ToolApproval;
+    use serde_json::json;
+    use tokio::sync::mpsc;
+    use tui_input::InputRequest;
+
+    use crate::tui::{session::SessionStore, viewmodel::{ApprovalMode, ChatError, Editor}};
+
+    use super::{ChatController, LEFT_CHAT_REASON};
+
+    fn controller() -> ChatController {
+        let sessions = SessionStore::new(std::env::temp_dir().join("lliminal-approval-tests"));
+        ChatController::for_tests(sessions, mpsc::unbounded_channel().0)
+    }
+
+    /// Wait until the chat shows a tool call which waits for approval
+    async fn wait_for_request(controller: &ChatController) {
+        controller.chat.subscribe().wait_for(|chat| chat.approval.is_some()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn sends_the_decision_of_the_user_to_the_agent() {
+        let controller = controller();
+        let decision = tokio::spawn(controller.tool_approver()("files__read".to_string(), json!({ "path": "a.txt" })));
+        wait_for_request(&controller).await;
+
+        let request = controller.chat.borrow().approval.clone().unwrap();
+        assert_eq!(request.name, "files__read");
+        assert!(matches!(request.mode, ApprovalMode::Choose));
+        controller.approve_tool(false);
+
+        assert_eq!(decision.await.unwrap(), ToolApproval::Approve { input: json!({ "path": "a.txt" }) });
+        assert!(controller.chat.borrow().approval.is_none());
+        assert!(controller.tool_approval.lock().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn approves_further_calls_of_a_tool_which_is_always_approved() {
+        let controller = controller();
+        let approver = controller.tool_approver();
+        let decision = tokio::spawn(approver("files__read".to_string(), json!({ "path": "a.txt" })));
+        wait_for_request(&controller).await;
+        controller.approve_tool(true);
+        decision.await.unwrap();
+
+        let approval = approver("files__read".to_string(), json!({ "path": "b.txt" })).await;
+
+        assert_eq!(approval, ToolApproval::Approve { input: json!({ "path": "b.txt" }) });
+        assert!(controller.chat.borrow().approval.is_none());
+        // A tool of the same name of another server is a different tool
+        let decision = tokio::spawn(approver("backup__read".to_string(), json!({})));
+        wait_for_request(&controller).await;
+        controller.start_deny_tool();
+        controller.deny_tool();
+        assert_eq!(decision.await.unwrap(), ToolApproval::Deny { reason: None });
+    }
+
+    #[tokio::test]
+    async fn denies_calls_when_the_chat_is_left() {
+        let controller = controller();
+        let approver = controller.tool_approver();
+        let decision = tokio::spawn(approver("files__read".to_string(), json!({})));
+        wait_for_request(&controller).await;
+
+        controller.chat.send_modify(|chat| chat.id = "other".to_string());
+
+        let left_chat = ToolApproval::Deny { reason: Some(LEFT_CHAT_REASON.to_string()) };
+        assert_eq!(decision.await.unwrap(), left_chat);
+        // Calls after the chat has been left are not shown in the other chat
+        controller.chat.send_modify(|chat| chat.approval = None);
+        assert_eq!(approver("files__read".to_string(), json!({})).await, left_chat);
+        assert!(controller.chat.borrow().approval.is_none());
+    }
+
+    #[tokio::test]
+    async fn denies_calls_with_the_typed_reason() {
+        let controller = controller();
+        let decision = tokio::spawn(controller.tool_approver()("files__read".to_string(), json!({})));
+        wait_for_request(&controller).await;
+
+        controller.start_deny_tool();
+        controller.chat.send_modify(|chat| {
+            if let Some(ApprovalMode::Deny(reason)) = chat.approval.as_mut().map(|request| &mut request.mode) {
+                "Not now ".chars().for_each(|char| { reason.handle(InputRequest::InsertChar(char)); });
+            }
+        });
+        controller.deny_tool();
+
+        assert_eq!(decision.await.unwrap(), ToolApproval::Deny { reason: Some("Not now".to_string()) });
+    }
+
+    #[tokio::test]
+    async fn keeps_editing_tool_input_which_is_invalid_json() {
+        let controller = controller();
+        let decision = tokio::spawn(controller.tool_approver()("files__read".to_string(), json!({ "path": "a.txt" })));
+        wait_for_request(&controller).await;
+
+        controller.set_approval_mode(ApprovalMode::Edit);
+        assert!(controller.chat.borrow().is_editing_tool_input());
+        assert_eq!(controller.chat_input.borrow().text(), "{\n  \"path\": \"a.txt\"\n}");
+        controller.chat_input.send_replace(Editor::new("{ \"path\": "));
+        controller.approve_edited_tool_input();
+
+        assert!(matches!(controller.chat.borrow().error, Some(ChatError::ToolInput(_))));
+        assert!(controller.chat.borrow().is_editing_tool_input());
+        assert!(controller.tool_approval.lock().unwrap().is_some());
+        controller.chat_input.send_replace(Editor::new("{ \"path\": \"b.txt\" }"));
+        controller.approve_edited_tool_input();
+
+        assert_eq!(decision.await.unwrap(), ToolApproval::Approve { input: json!({ "path": "b.txt" }) });
+        assert!(!controller.chat.borrow().is_editing_tool_input());
+        assert!(controller.chat_input.borrow().is_blank());
+    }
+
+    #[tokio::test]
+    async fn clears_the_edited_input_when_going_back_to_choosing() {
+        let controller = controller();
+        let _decision = tokio::spawn(controller.tool_approver()("files__read".to_string(), json!({ "path": "a.txt" })));
+        wait_for_request(&controller).await;
+
+        controller.set_approval_mode(ApprovalMode::Edit);
+        controller.set_approval_mode(ApprovalMode::Choose);
+
+        assert!(!controller.chat.borrow().is_editing_tool_input());
+        assert!(controller.chat_input.borrow().is_blank());
+    }
+}
@@ -0,0 +1,205 @@
+use std::{fs, path::Path};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use lliminal::llm::{UserMessageContent, UserMessagePart};
+
+use crate::tui::viewmodel::{complete, format_size, mention_at_end, mentioned_paths, text_file_part};
+
+use super::{ChatCommand, ChatController};
+
+/// Attachments which are larger in total are only sent once the user confirms them
+const CONFIRM_BYTES: usize = 100_000;
+
+/// The largest text file which can be attached
+const MAX_TEXT_BYTES: usize = 1_000_000;
+
+/// The largest image which Anthropic accepts
+const MAX_IMAGE_BYTES: usize = 5_000_000;
+
+/// The largest PDF which Anthropic accepts
+const MAX_DOCUMENT_BYTES: usize = 32_000_000;
+
+/// The number of matching paths which are listed when a path cannot be completed any further
+const MAX_LISTED_PATHS: usize = 20;
+
+/// The media types of files which Anthropic accepts as images or documents, by their extensions
+const MEDIA_TYPES: &[(&str, &str)] = &[
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("pdf", "application/pdf")
+];
+
+/// The files mentioned in the input, as parts of the message
+pub struct Attachments {
+    pub parts: Vec<UserMessagePart>,
+    /// The paths and sizes of the files
+    pub files: Vec<(String, usize)>
+}
+
+impl Attachments {
+    /// Whether the files are large enough to ask the user before sending them
+    pub fn need_confirmation(&self) -> bool {
+        self.files.iter().map(|(_, bytes)| bytes).sum::<usize>() > CONFIRM_BYTES
+    }
+
+    /// The question whether to send the files
+    pub fn confirmation(&self) -> String {
+        let files: Vec<String> = self.files.iter().map(|(path, bytes)| format!("{path} ({})", format_size(*bytes))).collect();
+        format!("Attach {}? Press Enter again to send the message with the files", files.join(", "))
+    }
+}
+
+impl ChatController {
+    /// Read the files mentioned in the input in the background, and submit the input with them afterwards
+    pub(super) fn read_attachments(&self, text: String) {
+        let self_sender = self.self_sender.clone().expect("Must call launch before handling commands");
+        tokio::spawn(async move {
+            let attachments = read_attachments(&text).await;
+            self_sender.send(ChatCommand::SubmitWithAttachments { text, attachments }).expect("Chat controller does not receive values");
+        });
+    }
+
+    /// Complete the path of the file which is mentioned before the cursor, or list the matching paths
+    pub(super) fn complete_path(&self) {
+        let line = self.chat_input.borrow().line_before_cursor();
+        let Some(path) = mention_at_end(&line) else {
+            return;
+        };
+        let candidates = path_candidates(path);
+        match complete(path, candidates.iter().map(String::as_str)) {
+            Some(completed) => self.chat_input.send_modify(|editor| editor.insert(&completed[path.len()..])),
+            None if candidates.len() > 1 => {
+                let directory = &path[..path.rfind('/').map_or(0, |index| index + 1)];
+                let mut names: Vec<&str> = candidates.iter()
+                    .take(MAX_LISTED_PATHS)
+                    .map(|candidate| candidate[directory.len()..].trim_end())
+                    .collect();
+                if candidates.len() > MAX_LISTED_PATHS {
+                    names.push("…");
+                }
+                self.chat.send_modify(|chat| {
+                    chat.notice = Some(names.join("  "));
+                    chat.confirm_input = None;
+                });
+            },
+            None => {}
+        }
+    }
+}
+
+/// Read the files which are mentioned as `@path` in the input
+///
+/// Mentions of files which do not exist and of directories are left as they are.
+pub(super) async fn read_attachments(text: &str) -> Result<Attachments, String> {
+    let mut attachments = Attachments { parts: vec![], files: vec![] };
+    for path in mentioned_paths(text) {
+        let Ok(metadata) = tokio::fs::metadata(path).await else {
+            continue;
+        };
+        if metadata.is_dir() {
+            continue;
+        }
+        let bytes = usize::try_from(metadata.len()).unwrap_or(usize::MAX);
+        let media_type = Path::new(path).extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| MEDIA_TYPES.iter().find(|(known, _)| known.eq_ignore_ascii_case(extension)))
+            .map(|(_, media_type)| media_type.to_string());
+        let part = match media_type {
+            Some(media_type) => {
+                let max_bytes = if media_type.starts_with("image/") { MAX_IMAGE_BYTES } else { MAX_DOCUMENT_BYTES };
+                check_size(path, bytes, max_bytes)?;
+                let data = STANDARD.encode(read_file(path).await?);
+                let content = if media_type.starts_with("image/") {
+                    UserMessageContent::Image { media_type, data }
+                } else {
+                    UserMessageContent::Document { media_type, data }
+                };
+                UserMessagePart { content }
+            },
+            None => {
+                check_size(path, bytes, MAX_TEXT_BYTES)?;
+                let contents = String::from_utf8(read_file(path).await?)
+                    .map_err(|_| format!("Cannot attach {path}, only text files, images and PDFs are supported"))?;
+                text_file_part(path, &contents)
+            }
+        };
+        attachments.parts.push(part);
+        attachments.files.push((path.to_string(), bytes));
+    }
+    Ok(attachments)
+}
+
+async fn read_file(path: &str) -> Result<Vec<u8>, String> {
+    tokio::fs::read(path).await.map_err(|err| format!("Cannot read {path}: {err}"))
+}
+
+fn check_size(path: &str, bytes: usize, max_bytes: usize) -> Result<(), String> {
+    if bytes > max_bytes {
+        return Err(format!("Cannot attach {path}, it has {} but at most {} are supported", format_size(bytes), format_size(max_bytes)));
+    }
+    Ok(())
+}
+
+/// The files and directories whose paths start with `path`; directories end with a slash and files with a space
+fn path_candidates(path: &str) -> Vec<String> {
+    let (directory, name) = match path.rsplit_once('/') {
+        Some(("", name)) => ("/".to_string(), name),
+        Some((directory, name)) => (format!("{directory}/"), name),
+        None => (String::new(), path)
+    };
+    let Ok(entries) = fs::read_dir(if directory.is_empty() { "." } else { &directory }) else {
+        return vec![];
+    };
+    let mut candidates: Vec<String> = entries.filter_map(Result::ok)
+        .filter_map(|entry| {
+            let entry_name = entry.file_name().into_string().ok()?;
+            // Hidden files are only offered once their name is being typed
+            if !entry_name.starts_with(name) || (entry_name.starts_with('.') && !name.starts_with('.')) {
+                return None;
+            }
+            let suffix = if entry.path().is_dir() { "/" } else { " " };
+            Some(format!("{directory}{entry_name}{suffix}"))
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use lliminal::llm::UserMessageContent;
+
+    use super::read_attachments;
+
+    #[tokio::test]
+    async fn attaches_files_and_skips_directories_and_missing_files() {
+        let directory = tempfile::tempdir().unwrap();
+        let text_file = directory.path().join("notes.txt");
+        let image = directory.path().join("image.PNG");
+        fs::write(&text_file, "Hello\n").unwrap();
+        fs::write(&image, [0x89, b'P', b'N', b'G']).unwrap();
+        let text = format!("Read @{} and @{} but not @{} or @missing.txt", text_file.display(), image.display(), directory.path().display());
+
+        let attachments = read_attachments(&text).await.unwrap();
+
+        assert_eq!(attachments.files, vec![(text_file.display().to_string(), 6), (image.display().to_string(), 4)]);
+        assert!(matches!(&attachments.parts[0].content, UserMessageContent::Text { text } if text.ends_with("\nHello\n</file>")));
+        assert!(matches!(&attachments.parts[1].content, UserMessageContent::Image { media_type, data } if media_type == "image/png" && data == "iVBORw=="));
+    }
+
+    #[tokio::test]
+    async fn rejects_binary_files_which_are_no_images_or_documents() {
+        let directory = tempfile::tempdir().unwrap();
+        let binary = directory.path().join("data.bin");
+        fs::write(&binary, [0xff, 0xfe, 0x00]).unwrap();
+
+        let error = read_attachments(&format!("@{}", binary.display())).await.err().unwrap();
+
+        assert!(error.contains("only text files, images and PDFs are supported"));
+    }
+}
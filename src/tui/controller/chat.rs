@@ -1,42 +1,224 @@
-use std::env;
+use std::{collections::HashSet, env, sync::{Arc, Mutex}};
 
-use lliminal::llm::{anthropic::{AnthropicLlmClient, AnthropicLlmClientConfig}, CompletionRequest, LlmClient};
-use tokio::sync::{mpsc, watch};
+use crossterm::event::Event;
+use lliminal::llm::{agent::{Agent, AgentEvent, ToolApproval, ToolApprover, ToolRegistry}, anthropic::{AnthropicLlmClient, AnthropicLlmClientConfig}, context::ContextManager, Message};
+use tokio::sync::{mpsc, oneshot, watch};
 use tokio_stream::StreamExt;
-use tui_input::Input;
+use tui_input::backend::crossterm::EventHandler;
 use url::Url;
 
-use crate::tui::viewmodel::Chat;
+use crate::tui::{clipboard, config::ContextConfig, session::SessionStore, viewmodel::{code_blocks, AppState, ApprovalMode, Chat, ChatError, Editor, SlashInput, TextCell, ToolApprovalRequest}};
 
-use super::Controller;
+use super::{attachment::Attachments, Controller, SidebarCommand};
+
+/// The model which is used for new chats
+pub const MODEL: &str = "claude-3-5-haiku-latest";
 
 pub struct ChatController {
+    pub app_state: watch::Sender<AppState>,
     pub chat: watch::Sender<Chat>,
-    pub chat_input: watch::Sender<Input>,
-    pub self_sender: Option<mpsc::UnboundedSender<ChatCommand>>
+    pub chat_input: watch::Sender<Editor>,
+    pub tools: ToolRegistry,
+    /// Shared by all requests, so that summaries are reused
+    pub context: ContextManager,
+    pub context_config: ContextConfig,
+    pub sessions: SessionStore,
+    pub sidebar_controller: mpsc::UnboundedSender<SidebarCommand>,
+    pub self_sender: Option<mpsc::UnboundedSender<ChatCommand>>,
+    /// The tools whose calls are executed without asking, for all chats
+    ///
+    /// Tools are known by their names in the registry, which are unique; tools of MCP servers are named
+    /// `<server>__<tool>`, so approving a tool of one server does not approve a tool of the same name of another.
+    pub approved_tools: Arc<Mutex<HashSet<String>>>,
+    /// Receives the decision about the tool call which waits for the approval of the user
+    pub tool_approval: Arc<Mutex<Option<oneshot::Sender<ToolApproval>>>>
 }
 
 impl Controller<ChatCommand> for ChatController {
     fn handle(&self, event: ChatCommand) {
         match event {
             ChatCommand::Submit => {
-                        let old_input = self.chat_input.send_replace(Input::default());
+                        if self.chat.borrow().is_editing_tool_input() {
+                            return self.approve_edited_tool_input();
+                        }
+                        if self.chat_input.borrow().is_blank() {
+                            return;
+                        }
+                        let text = self.chat_input.borrow().text();
+                        if let Some(input) = SlashInput::parse(&text) {
+                            self.run_slash_command(&input);
+                            return;
+                        }
+                        self.read_attachments(text);
+                    },
+            ChatCommand::SubmitWithAttachments { text, attachments } => {
+                        // The input may have been changed while the files were read
+                        if self.chat_input.borrow().text() != text {
+                            return;
+                        }
+                        let attachments = match attachments {
+                            Ok(attachments) => attachments,
+                            Err(err) => return self.chat.send_modify(|chat| {
+                                chat.notice = None;
+                                chat.error = Some(ChatError::Attachment(err));
+                            })
+                        };
+                        if attachments.need_confirmation() && self.chat.borrow().confirm_input.as_ref() != Some(&text) {
+                            return self.chat.send_modify(|chat| {
+                                chat.error = None;
+                                chat.notice = Some(attachments.confirmation());
+                                chat.confirm_input = Some(text.clone());
+                            });
+                        }
+                        self.chat_input.send_replace(Editor::default());
+                        // A double slash sends a message which starts with a slash
+                        let text = text.strip_prefix('/').unwrap_or(&text);
                         self.chat.send_modify(|chat| {
-                            chat.submit_user_input(old_input.value());
-                        });
-                        let chat_sender = self.chat.clone();
-                        let self_sender = self.self_sender.clone();
-                        tokio::spawn(async move {
-                            ChatController::call_llm(chat_sender, self_sender.expect("Must call launch before handling commands")).await;
+                            chat.submit_user_input(text, attachments.parts);
                         });
+                        self.start_completion();
                     },
+            ChatCommand::Retry => {
+                        if !self.chat.borrow().can_retry() {
+                            return;
+                        }
+                        self.chat.send_modify(Chat::prepare_retry);
+                        self.start_completion();
+                    },
+            ChatCommand::Complete => {
+                if self.chat_input.borrow().text().starts_with('/') {
+                    self.complete_slash_command();
+                } else {
+                    self.complete_path();
+                }
+            },
+            ChatCommand::Dismiss => self.chat.send_modify(|chat| {
+                chat.error = None;
+                chat.notice = None;
+                chat.confirm_input = None;
+            }),
+            ChatCommand::SelectMessages => self.chat.send_modify(Chat::select_last),
+            ChatCommand::SelectFirst => self.chat.send_modify(Chat::select_first),
+            ChatCommand::SelectPrevious => self.chat.send_modify(Chat::select_previous),
+            ChatCommand::SelectNext => self.chat.send_modify(Chat::select_next),
+            ChatCommand::ClearSelection => self.chat.send_modify(|chat| { chat.selected = None; }),
+            ChatCommand::PreviousAlternative => self.chat.send_modify(|chat| chat.cycle_alternative(false)),
+            ChatCommand::NextAlternative => self.chat.send_modify(|chat| chat.cycle_alternative(true)),
+            ChatCommand::CopyMessage => {
+                let text = self.chat.borrow().selected_message().map(Message::text);
+                if let Some(text) = text {
+                    self.report_message_action(Ok(self.copy_to_clipboard(&text, "the message")));
+                }
+            },
+            ChatCommand::CopyCodeBlock(number) => {
+                let code = self.chat.borrow().selected_message().map(|message| code_block(message, number));
+                if let Some(code) = code {
+                    self.report_message_action(code.map(|code| self.copy_to_clipboard(&code, &format!("code block {number}"))));
+                }
+            },
+            ChatCommand::DeleteMessage => {
+                let selected = self.chat.borrow().selected;
+                if let Some(index) = selected {
+                    self.chat.send_modify(|chat| chat.delete_message(index));
+                    self.save_chat();
+                }
+            },
+            ChatCommand::EditMessage => {
+                let mut text = None;
+                self.chat.send_modify(|chat| {
+                    text = chat.selected.and_then(|index| chat.start_edit(index));
+                    if text.is_none() {
+                        chat.error = Some(ChatError::Message("Only your own messages can be edited".to_string()));
+                    }
+                });
+                if let Some(text) = text {
+                    self.chat_input.send_replace(Editor::new(&text));
+                }
+            },
+            ChatCommand::CancelEdit => {
+                self.chat.send_modify(|chat| { chat.editing = None; });
+                self.chat_input.send_replace(Editor::default());
+            },
+            ChatCommand::RegenerateMessage => {
+                let mut regenerate = false;
+                self.chat.send_modify(|chat| {
+                    regenerate = chat.selected.is_some_and(|index| chat.prepare_regenerate(index));
+                    if !regenerate {
+                        chat.error = Some(ChatError::Message("Only responses can be regenerated".to_string()));
+                    }
+                });
+                if regenerate {
+                    self.start_completion();
+                }
+            },
+            ChatCommand::TogglePin => {
+                self.chat.send_modify(Chat::toggle_pinned);
+                self.save_chat();
+            },
             ChatCommand::WaitForUser => {
                         self.chat.send_modify(|chat| {
                             chat.wait_for_user();
                         });
                     }
-            ChatCommand::ScrollUp => self.chat.send_modify(|chat| chat.scroll_up(4)),
-            ChatCommand::ScrollDown => self.chat.send_modify(|chat| chat.scroll_down(4)),
+            ChatCommand::ApproveTool => self.approve_tool(false),
+            ChatCommand::AlwaysApproveTool => self.approve_tool(true),
+            ChatCommand::StartDenyTool => self.start_deny_tool(),
+            ChatCommand::EditDenyReason(event) => self.chat.send_modify(|chat| {
+                if let Some(ToolApprovalRequest { mode: ApprovalMode::Deny(reason), .. }) = &mut chat.approval {
+                    reason.handle_event(&event);
+                }
+            }),
+            ChatCommand::DenyTool => self.deny_tool(),
+            ChatCommand::EditToolInput => self.set_approval_mode(ApprovalMode::Edit),
+            ChatCommand::CancelToolDecision => self.set_approval_mode(ApprovalMode::Choose),
+            ChatCommand::StartSearch => self.chat.send_modify(Chat::start_search),
+            ChatCommand::EditSearch(event) => self.chat.send_modify(|chat| {
+                if let Some(search) = &mut chat.search {
+                    search.edit_query(|query| { query.handle_event(&event); });
+                }
+            }),
+            ChatCommand::ToggleSearchRegex => self.chat.send_modify(|chat| {
+                if let Some(search) = &mut chat.search {
+                    search.toggle_regex();
+                }
+            }),
+            ChatCommand::ConfirmSearch => {
+                let layout = self.app_state.borrow().chat_layout.clone();
+                self.chat.send_modify(|chat| {
+                    if let Some(search) = &mut chat.search {
+                        search.editing = false;
+                    }
+                    chat.scroll_to_match(&layout);
+                });
+            },
+            ChatCommand::NextMatch | ChatCommand::PreviousMatch => {
+                let forward = matches!(event, ChatCommand::NextMatch);
+                let layout = self.app_state.borrow().chat_layout.clone();
+                self.chat.send_modify(|chat| chat.move_to_match(&layout, forward));
+            },
+            ChatCommand::CloseSearch => self.chat.send_modify(|chat| { chat.search = None; }),
+            ChatCommand::ScrollUp(lines) => {
+                let layout = self.app_state.borrow().chat_layout.clone();
+                self.chat.send_modify(|chat| chat.scroll_up(lines, &layout));
+            },
+            ChatCommand::ScrollDown(lines) => {
+                let layout = self.app_state.borrow().chat_layout.clone();
+                self.chat.send_modify(|chat| chat.scroll_down(lines, &layout));
+            },
+            ChatCommand::ScrollToTop => {
+                let layout = self.app_state.borrow().chat_layout.clone();
+                self.chat.send_modify(|chat| chat.scroll_to_top(&layout));
+            },
+            ChatCommand::ScrollToBottom => self.chat.send_modify(Chat::scroll_to_bottom),
+            ChatCommand::StartMouseSelection(cell) => self.chat.send_modify(|chat| chat.start_mouse_selection(cell)),
+            ChatCommand::ExtendMouseSelection(cell) => self.chat.send_modify(|chat| chat.extend_mouse_selection(cell)),
+            ChatCommand::FinishMouseSelection => {
+                let mut text = None;
+                self.chat.send_modify(|chat| { text = chat.finish_mouse_selection(); });
+                if let Some(text) = text {
+                    self.report_message_action(Ok(self.copy_to_clipboard(&text, "the selected text")));
+                }
+            },
         }
     }
 
@@ -46,48 +228,231 @@ impl Controller<ChatCommand> for ChatController {
 }
 
 impl ChatController {
-    async fn call_llm(chat: watch::Sender<Chat>, chat_controller: mpsc::UnboundedSender<ChatCommand>) {
-        let mut client = AnthropicLlmClient {
-            config: AnthropicLlmClientConfig {
-                base_url: Url::parse(
-                              &env::var("ANTHROPIC_URL").unwrap_or("https://api.anthropic.com".to_string())
-                          ).expect("Invalid URL provided"),
-                api_key: env::var("ANTHROPIC_API_KEY").unwrap(),
-                model: "claude-3-5-haiku-latest".to_string(),
-                max_tokens: 1024
+    /// Copy text to the system clipboard, or else have the app ask the terminal to copy it; returns a notice for the user
+    pub(super) fn copy_to_clipboard(&self, text: &str, description: &str) -> String {
+        if !clipboard::copy_native(text) {
+            self.app_state.send_modify(|state| { state.clipboard = Some(text.to_string()); });
+        }
+        format!("Copied {description} to the clipboard")
+    }
+
+    /// Show the outcome of an action on a message
+    fn report_message_action(&self, result: Result<String, String>) {
+        self.chat.send_modify(|chat| {
+            match result {
+                Ok(notice) => chat.notice = Some(notice),
+                Err(err) => chat.error = Some(ChatError::Message(err))
             }
-        };
-        let messages = chat.borrow().messages.clone();
-        let request = CompletionRequest {
-            system: vec![],
-            messages: messages.clone()
-        };
+            chat.confirm_input = None;
+        });
+    }
+
+    /// Save the active chat, showing an error if it fails
+    fn save_chat(&self) {
+        let saved = self.sessions.save_chat(&self.chat.borrow());
+        if let Err(err) = saved {
+            self.chat.send_modify(|chat| {
+                chat.error = Some(ChatError::Session(format!("{err:#}")));
+            });
+        }
+    }
+
+    /// Complete the chat in the background and save it afterwards
+    pub(super) fn start_completion(&self) {
+        self.chat.send_modify(Chat::start_turn);
+        let chat_sender = self.chat.clone();
+        let tools = self.tools.clone();
+        let mut context = self.context.clone();
+        context.context_window = self.context_config.context_window(&self.chat.borrow().model);
+        context.pinned = self.chat.borrow().pinned_messages();
+        let approver = self.tool_approver();
+        let sessions = self.sessions.clone();
+        let self_sender = self.self_sender.clone();
+        tokio::spawn(async move {
+            let result = ChatController::call_llm(chat_sender.clone(), tools, approver, context, self_sender.expect("Must call launch before handling commands")).await;
+            // The user may have switched to another chat in the meantime, or deleted this one
+            let saved = if chat_sender.borrow().id == result.id {
+                sessions.save_chat(&chat_sender.borrow())
+            } else if sessions.contains(&result.id) {
+                sessions.save_chat(&result)
+            } else {
+                Ok(())
+            };
+            if let Err(err) = saved {
+                chat_sender.send_modify(|chat| {
+                    chat.error = Some(ChatError::Session(format!("{err:#}")));
+                });
+            }
+        });
+    }
+
+    /// Complete the active chat and return its final state
+    ///
+    /// Updates are only shown while the chat is still active; a chat which has been left keeps
+    /// running in the background.
+    async fn call_llm(
+        chat: watch::Sender<Chat>,
+        tools: ToolRegistry,
+        approver: ToolApprover,
+        context: ContextManager,
+        chat_controller: mpsc::UnboundedSender<ChatCommand>
+    ) -> Chat {
+        let mut result = chat.borrow().clone();
+        let client = AnthropicLlmClient::new(AnthropicLlmClientConfig {
+            base_url: Url::parse(
+                          &env::var("ANTHROPIC_URL").unwrap_or("https://api.anthropic.com".to_string())
+                      ).expect("Invalid URL provided"),
+            api_key: env::var("ANTHROPIC_API_KEY").unwrap(),
+            model: result.model.clone(),
+            max_tokens: 1024
+        });
+        let mut agent = Agent { system: result.system.clone(), tools, context: Some(context), approver: Some(approver), ..Agent::new(client) };
+        let messages = result.messages().to_vec();
+        // The agent reports the whole conversation; the response starts after the request
+        let start = messages.len();
+        let id = result.id.clone();
+        let is_active = |c: &Chat| c.id == id;
 
-        let mut response = client.complete(&request).await;
-        while let Some(response) = response.next().await {
-            match response {
-                Ok(mut response) => {
-                    let mut new_messages = messages.clone();
-                    new_messages.append(&mut response);
-                    chat.send_modify(|c| {
-                        c.messages = new_messages;
-                    });
-                    chat_controller.send(ChatCommand::WaitForUser).expect("Chat controller does not receive values");
-                },
-                Err(err) => {
-                    chat.send_modify(|c| {
-                        c.error = Some(err);
-                    });
-                },
+        let (event_sender, mut events) = futures::channel::mpsc::unbounded();
+        let update_chat = async {
+            while let Some(event) = events.next().await {
+                match event {
+                    AgentEvent::Messages(conversation) => {
+                        let response = conversation.get(start..).unwrap_or_default();
+                        chat.send_if_modified(|c| {
+                            if is_active(c) {
+                                c.update_response(start, response);
+                            }
+                            is_active(c)
+                        });
+                        result.update_response(start, response);
+                    },
+                    AgentEvent::Usage(usage) => {
+                        chat.send_if_modified(|c| {
+                            if is_active(c) {
+                                c.record_usage(usage);
+                            }
+                            is_active(c)
+                        });
+                        result.record_usage(usage);
+                    },
+                    AgentEvent::ToolCallStarted { .. } | AgentEvent::ToolCallFinished { .. } => {}
+                }
             }
+        };
+        let (agent_result, ()) = tokio::join!(agent.run(messages, event_sender), update_chat);
+
+        let active = is_active(&chat.borrow());
+        match agent_result {
+            Ok(_) if active => chat_controller.send(ChatCommand::WaitForUser).expect("Chat controller does not receive values"),
+            Ok(_) => result.wait_for_user(),
+            Err(err) => {
+                chat.send_if_modified(|c| {
+                    if is_active(c) {
+                        c.fail(ChatError::Llm(err.clone()));
+                    }
+                    is_active(c)
+                });
+                result.fail(ChatError::Llm(err));
+            },
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+impl ChatController {
+    /// A controller of a new chat without tools, which stores its sessions in `sessions`
+    pub(super) fn for_tests(sessions: SessionStore, sidebar_controller: mpsc::UnboundedSender<SidebarCommand>) -> Self {
+        let context_config = ContextConfig::default();
+        Self {
+            app_state: watch::Sender::new(AppState::default()),
+            chat: watch::Sender::new(Chat::new("model")),
+            chat_input: watch::Sender::new(Editor::default()),
+            tools: ToolRegistry::default(),
+            context: context_config.context_manager("model"),
+            context_config,
+            sessions,
+            sidebar_controller,
+            self_sender: None,
+            approved_tools: Arc::default(),
+            tool_approval: Arc::default()
         }
     }
 }
 
+/// The code block of the message with the given number, counted from 1
+pub(super) fn code_block(message: &Message, number: usize) -> Result<String, String> {
+    number.checked_sub(1)
+        .and_then(|index| code_blocks(message).into_iter().nth(index))
+        .ok_or_else(|| format!("There is no code block {number}"))
+}
+
 pub enum ChatCommand {
     Submit,
+    /// Submit the input once the files mentioned in it have been read
+    SubmitWithAttachments { text: String, attachments: Result<Attachments, String> },
+    /// Send the failed request again
+    Retry,
+    /// Complete the slash command in the input, or the path of a file mentioned before the cursor
+    Complete,
+    /// Hide the error or notice
+    Dismiss,
+    /// Select the last message to act on it
+    SelectMessages,
+    SelectFirst,
+    SelectPrevious,
+    SelectNext,
+    ClearSelection,
+    /// Show another branch at the selected message
+    PreviousAlternative,
+    NextAlternative,
+    CopyMessage,
+    /// Copy a code block of the selected message, counted from 1
+    CopyCodeBlock(usize),
+    /// Delete the selected message with the tool calls or results belonging to it
+    DeleteMessage,
+    /// Edit the selected user message; submitting adds the edited message as an alternative
+    EditMessage,
+    CancelEdit,
+    /// Request the selected response again, as an alternative to it
+    RegenerateMessage,
+    /// Pin or unpin the selected message, which keeps it in the context window
+    TogglePin,
     WaitForUser,
-    ScrollUp,
-    ScrollDown
+    /// Start a search, or edit the query of the current search
+    StartSearch,
+    EditSearch(Event),
+    /// Switch between a plain text and a regular expression query
+    ToggleSearchRegex,
+    /// Finish editing the query and show the current match
+    ConfirmSearch,
+    NextMatch,
+    PreviousMatch,
+    CloseSearch,
+    /// Start selecting text with the mouse at the text in the cell
+    StartMouseSelection(TextCell),
+    ExtendMouseSelection(TextCell),
+    /// Copy the text selected with the mouse, or select a clicked message for an action
+    FinishMouseSelection,
+    /// Execute the tool call which waits for approval
+    ApproveTool,
+    /// Execute this and all further calls of the tool
+    AlwaysApproveTool,
+    /// Start typing the reason for denying the tool call
+    StartDenyTool,
+    EditDenyReason(Event),
+    /// Deny the tool call with the reason which has been typed
+    DenyTool,
+    /// Edit the input of the tool call in the editor; it is executed on submit
+    EditToolInput,
+    /// Go back to choosing what happens with the tool call
+    CancelToolDecision,
+    /// Scroll by a number of lines, within the messages
+    ScrollUp(usize),
+    ScrollDown(usize),
+    ScrollToTop,
+    /// Scroll to the bottom and follow the response from there
+    ScrollToBottom
 }
 
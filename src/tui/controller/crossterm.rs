@@ -1,38 +1,447 @@
-use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use std::cell::{Cell, RefCell};
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::layout::Position;
 use tokio::sync::{mpsc, watch};
-use tui_input::{backend::crossterm::EventHandler, Input};
+use tui_textarea::{CursorMove, Key};
+
+use crate::tui::viewmodel::{mention_at_end, AppState, ApprovalMode, Chat, Editor, Focus, Sidebar, SidebarMode, VimMode};
 
-use crate::tui::viewmodel::{AppState, Chat};
+use super::{keymap::{Binding, KeyChord}, Action, ChatCommand, Controller, KeyMode, Keymap, SidebarCommand};
 
-use super::{ChatCommand, Controller};
+/// The number of lines scrolled by PageUp and PageDown
+const PAGE_LINES: usize = 4;
+
+/// The number of lines scrolled by a step of the mouse wheel
+const WHEEL_LINES: usize = 3;
 
 pub struct CrosstermController {
     pub app_state: watch::Sender<AppState>,
     pub chat: watch::Receiver<Chat>,
-    pub chat_input: watch::Sender<Input>,
+    pub chat_input: watch::Sender<Editor>,
     pub chat_controller: mpsc::UnboundedSender<ChatCommand>,
+    pub sidebar: watch::Receiver<Sidebar>,
+    pub sidebar_controller: mpsc::UnboundedSender<SidebarCommand>,
+    pub keymap: Keymap,
+    /// The keys pressed so far of a binding with several keys
+    pub pending_keys: RefCell<Vec<KeyChord>>,
+    /// The modes in which the pending keys were pressed; the keys are dropped when the modes change
+    pub pending_modes: Cell<&'static [KeyMode]>
+}
+
+/// Where keys go which are not bound in the current modes
+enum Fallback {
+    Sidebar,
+    SearchQuery,
+    /// The reason for denying a tool call
+    DenyReason,
+    Editor,
+    None
 }
 
 impl Controller<Event> for CrosstermController {
     fn handle(&self, event: Event) {
-        let chat = self.chat.borrow();
         match event {
-            Event::Key(
-                KeyEvent { modifiers: KeyModifiers::CONTROL, code: KeyCode::Char('c' | 'C'), .. }
-            ) => self.app_state.send_modify(|state| { state.running = false; }),
-            Event::Key(
-                KeyEvent { code: KeyCode::PageUp, .. }
-            ) => self.chat_controller.send(ChatCommand::ScrollUp).expect("Chat controller does not receive values"),
-            Event::Key(
-                KeyEvent { code: KeyCode::PageDown, .. }
-            ) => self.chat_controller.send(ChatCommand::ScrollDown).expect("Chat controller does not receive values"),
-            Event::Key(
-                KeyEvent { modifiers: KeyModifiers::NONE, code: KeyCode::Enter, .. }
-            ) if chat.user_input => self.chat_controller.send(ChatCommand::Submit).expect("Chat controller does not receive values"),
-            _ if chat.user_input => self.chat_input.send_modify(|input| {
-                input.handle_event(&event);
+            Event::Key(key) if key.kind != KeyEventKind::Release => self.handle_key(key),
+            Event::Mouse(mouse) => self.handle_mouse(mouse),
+            Event::Paste(text) if self.accepts_input() => self.chat_input.send_modify(|editor| {
+                editor.paste(&text);
+            }),
+            _ => {}
+        }
+    }
+}
+
+impl CrosstermController {
+    /// Perform the action bound to the key in the current modes, or else pass it on
+    fn handle_key(&self, key: KeyEvent) {
+        let (modes, fallback) = self.modes();
+        let binding = {
+            let mut pending_keys = self.pending_keys.borrow_mut();
+            if self.pending_modes.replace(modes) != modes {
+                pending_keys.clear();
+            }
+            pending_keys.push(KeyChord::from(key));
+            let binding = self.keymap.resolve(modes, &pending_keys);
+            match binding {
+                Binding::Pending => return,
+                // An unbound key after the beginning of a binding cancels it, like in vim
+                Binding::Unbound if pending_keys.len() > 1 => {
+                    pending_keys.clear();
+                    return;
+                },
+                _ => pending_keys.clear()
+            }
+            binding
+        };
+        if let Binding::Actions(actions) = binding && actions.into_iter().any(|action| self.perform(action)) {
+            return;
+        }
+        match fallback {
+            Fallback::Sidebar => self.handle_sidebar(Event::Key(key)),
+            Fallback::SearchQuery => self.handle_search_query(key),
+            Fallback::DenyReason => self.handle_deny_reason(key),
+            Fallback::Editor if self.accepts_input() => self.chat_input.send_modify(|editor| {
+                editor.input(editor_input(key));
             }),
+            Fallback::Editor | Fallback::None => {}
+        }
+    }
+
+    /// The modes whose bindings apply, in order of precedence, and where unbound keys go
+    fn modes(&self) -> (&'static [KeyMode], Fallback) {
+        if self.app_state.borrow().focus == Focus::Sidebar {
+            return (&[KeyMode::Global], Fallback::Sidebar);
+        }
+        let chat = self.chat.borrow();
+        match &chat.search {
+            Some(search) if search.editing => return (&[KeyMode::Global], Fallback::SearchQuery),
+            Some(_) => return (&[KeyMode::Search, KeyMode::Global], Fallback::None),
+            None => {}
+        }
+        match chat.approval.as_ref().map(|approval| &approval.mode) {
+            Some(ApprovalMode::Choose) => return (&[KeyMode::Approval, KeyMode::Scroll, KeyMode::Global], Fallback::None),
+            Some(ApprovalMode::Deny(_)) => return (&[KeyMode::Global], Fallback::DenyReason),
+            // The input of the tool call is edited like a message
+            Some(ApprovalMode::Edit) | None => {}
+        }
+        if chat.selected.is_some() {
+            return (&[KeyMode::Select, KeyMode::Global], Fallback::None);
+        }
+        match self.app_state.borrow().vim {
+            Some(VimMode::Normal) => (&[KeyMode::Normal, KeyMode::Scroll, KeyMode::Global], Fallback::None),
+            _ => (&[KeyMode::Input, KeyMode::Scroll, KeyMode::Global], Fallback::Editor)
+        }
+    }
+
+    /// Whether the input starts a slash command or a file is mentioned before the cursor
+    fn can_complete(&self) -> bool {
+        let editor = self.chat_input.borrow();
+        editor.text().starts_with('/') || mention_at_end(&editor.line_before_cursor()).is_some()
+    }
+
+    /// Whether keys are typed into the input
+    fn accepts_input(&self) -> bool {
+        let chat = self.chat.borrow();
+        let editing = chat.user_input || chat.is_editing_tool_input();
+        self.app_state.borrow().focus == Focus::Chat && editing && chat.selected.is_none() && chat.search.is_none()
+    }
+
+    /// Perform the action, unless it does not apply; returns whether it was performed
+    fn perform(&self, action: Action) -> bool {
+        let (user_input, editing, selected, searching, approving, can_retry) = {
+            let chat = self.chat.borrow();
+            let editing = chat.user_input || chat.is_editing_tool_input();
+            (chat.user_input, editing, chat.selected.is_some(), chat.search.is_some(), chat.approval.is_some(), chat.can_retry())
+        };
+        let command = match action {
+            Action::Quit => {
+                self.app_state.send_modify(|state| { state.running = false; });
+                return true;
+            },
+            Action::ToggleSidebar => {
+                self.send_sidebar(SidebarCommand::Toggle);
+                return true;
+            },
+            Action::NewSession => {
+                self.send_sidebar(SidebarCommand::New);
+                return true;
+            },
+            Action::SwitchFocus if self.sidebar.borrow().visible => {
+                self.send_sidebar(SidebarCommand::SwitchFocus);
+                return true;
+            },
+            Action::Submit if editing => ChatCommand::Submit,
+            Action::Complete if self.can_complete() => ChatCommand::Complete,
+            Action::Retry if can_retry => ChatCommand::Retry,
+            Action::Back => {
+                let chat = self.chat.borrow();
+                if chat.error.is_some() || chat.notice.is_some() {
+                    ChatCommand::Dismiss
+                } else if chat.approval.is_some() {
+                    ChatCommand::CancelToolDecision
+                } else if chat.editing.is_some() {
+                    ChatCommand::CancelEdit
+                } else if chat.user_input && !chat.messages().is_empty() {
+                    ChatCommand::SelectMessages
+                } else {
+                    return false;
+                }
+            },
+            Action::ExternalEditor if editing => {
+                self.app_state.send_modify(|state| { state.external_editor = true; });
+                return true;
+            },
+            Action::ScrollUp => ChatCommand::ScrollUp(PAGE_LINES),
+            Action::ScrollDown => ChatCommand::ScrollDown(PAGE_LINES),
+            Action::ScrollLineUp => ChatCommand::ScrollUp(1),
+            Action::ScrollLineDown => ChatCommand::ScrollDown(1),
+            Action::ScrollTop => ChatCommand::ScrollToTop,
+            Action::ScrollBottom => ChatCommand::ScrollToBottom,
+            Action::Search => ChatCommand::StartSearch,
+            Action::SelectPrevious if selected => ChatCommand::SelectPrevious,
+            Action::SelectNext if selected => ChatCommand::SelectNext,
+            Action::SelectFirst if selected => ChatCommand::SelectFirst,
+            Action::SelectLast if selected || user_input => ChatCommand::SelectMessages,
+            Action::PreviousAlternative if selected => ChatCommand::PreviousAlternative,
+            Action::NextAlternative if selected => ChatCommand::NextAlternative,
+            Action::CopyMessage if selected => ChatCommand::CopyMessage,
+            Action::CopyCodeBlock(number) if selected => ChatCommand::CopyCodeBlock(number),
+            Action::DeleteMessage if selected => ChatCommand::DeleteMessage,
+            Action::EditMessage if selected => ChatCommand::EditMessage,
+            Action::RegenerateMessage if selected => ChatCommand::RegenerateMessage,
+            Action::TogglePin if selected => ChatCommand::TogglePin,
+            Action::ClearSelection if selected => ChatCommand::ClearSelection,
+            Action::NextMatch if searching => ChatCommand::NextMatch,
+            Action::PreviousMatch if searching => ChatCommand::PreviousMatch,
+            Action::CloseSearch if searching => ChatCommand::CloseSearch,
+            Action::ApproveTool if approving => ChatCommand::ApproveTool,
+            Action::AlwaysApproveTool if approving => ChatCommand::AlwaysApproveTool,
+            Action::DenyTool if approving => ChatCommand::StartDenyTool,
+            Action::EditToolInput if approving => ChatCommand::EditToolInput,
+            Action::NormalMode => return self.set_vim_mode(VimMode::Normal),
+            Action::InsertMode => return self.set_vim_mode(VimMode::Insert),
+            action => return editing && self.edit(action)
+        };
+        self.chat_controller.send(command).expect("Chat controller does not receive values");
+        true
+    }
+
+    /// Perform an action on the input; returns whether it is one
+    fn edit(&self, action: Action) -> bool {
+        let (movement, mode) = match action {
+            Action::Append => (Some(CursorMove::Forward), Some(VimMode::Insert)),
+            Action::InsertLineStart => (Some(CursorMove::Head), Some(VimMode::Insert)),
+            Action::AppendLineEnd => (Some(CursorMove::End), Some(VimMode::Insert)),
+            Action::CursorLeft => (Some(CursorMove::Back), None),
+            Action::CursorRight => (Some(CursorMove::Forward), None),
+            Action::CursorUp => (Some(CursorMove::Up), None),
+            Action::CursorDown => (Some(CursorMove::Down), None),
+            Action::WordForward => (Some(CursorMove::WordForward), None),
+            Action::WordBack => (Some(CursorMove::WordBack), None),
+            Action::LineStart => (Some(CursorMove::Head), None),
+            Action::LineEnd => (Some(CursorMove::End), None),
+            Action::InputTop => (Some(CursorMove::Top), None),
+            Action::InputBottom => (Some(CursorMove::Bottom), None),
+            Action::OpenLineBelow | Action::OpenLineAbove => (None, Some(VimMode::Insert)),
+            Action::Newline | Action::DeleteChar | Action::DeleteLine | Action::Undo | Action::Redo => (None, None),
+            _ => return false
+        };
+        self.chat_input.send_modify(|editor| match (action, movement) {
+            (_, Some(movement)) => editor.move_cursor(movement),
+            (Action::OpenLineBelow, _) => editor.open_line(true),
+            (Action::OpenLineAbove, _) => editor.open_line(false),
+            (Action::Newline, _) => editor.insert_newline(),
+            (Action::DeleteChar, _) => editor.delete_char(),
+            (Action::DeleteLine, _) => editor.delete_line(),
+            (Action::Undo, _) => editor.undo(),
+            (Action::Redo, _) => editor.redo(),
             _ => {}
+        });
+        if let Some(mode) = mode {
+            self.set_vim_mode(mode);
         }
+        true
+    }
+
+    /// Switch between the vim modes; returns whether vim mode is enabled
+    fn set_vim_mode(&self, mode: VimMode) -> bool {
+        self.app_state.send_if_modified(|state| match &mut state.vim {
+            Some(vim) if *vim != mode => {
+                *vim = mode;
+                true
+            },
+            _ => false
+        });
+        self.keymap.vim
+    }
+
+    /// Edit the query of the search
+    fn handle_search_query(&self, key: KeyEvent) {
+        let command = match (key.modifiers, key.code) {
+            (_, KeyCode::Enter) => ChatCommand::ConfirmSearch,
+            (KeyModifiers::ALT, KeyCode::Char('r' | 'R')) => ChatCommand::ToggleSearchRegex,
+            (_, KeyCode::Esc) => ChatCommand::CloseSearch,
+            _ => ChatCommand::EditSearch(Event::Key(key))
+        };
+        self.chat_controller.send(command).expect("Chat controller does not receive values");
+    }
+
+    /// Edit the reason for denying a tool call
+    fn handle_deny_reason(&self, key: KeyEvent) {
+        let command = match key.code {
+            KeyCode::Enter => ChatCommand::DenyTool,
+            KeyCode::Esc => ChatCommand::CancelToolDecision,
+            _ => ChatCommand::EditDenyReason(Event::Key(key))
+        };
+        self.chat_controller.send(command).expect("Chat controller does not receive values");
+    }
+
+    /// Scroll with the wheel, click to focus the input or select a message, drag to copy text
+    fn handle_mouse(&self, mouse: MouseEvent) {
+        let position = Position::new(mouse.column, mouse.row);
+        let (selected, user_input) = {
+            let chat = self.chat.borrow();
+            (chat.selected.is_some(), chat.user_input)
+        };
+        let (messages_area, input_area, cell) = {
+            let state = self.app_state.borrow();
+            let cell = match mouse.kind {
+                MouseEventKind::Down(MouseButton::Left) if state.chat_layout.message_at(position).is_some() => {
+                    state.chat_layout.text_cell_at(position, self.chat.borrow().messages())
+                },
+                MouseEventKind::Drag(MouseButton::Left) => state.chat_layout.text_cell_at(position, self.chat.borrow().messages()),
+                _ => None
+            };
+            (state.chat_layout.messages, state.chat_layout.input, cell)
+        };
+        let command = match mouse.kind {
+            MouseEventKind::ScrollUp if messages_area.contains(position) && selected => ChatCommand::SelectPrevious,
+            MouseEventKind::ScrollUp if messages_area.contains(position) => ChatCommand::ScrollUp(WHEEL_LINES),
+            MouseEventKind::ScrollDown if messages_area.contains(position) && selected => ChatCommand::SelectNext,
+            MouseEventKind::ScrollDown if messages_area.contains(position) => ChatCommand::ScrollDown(WHEEL_LINES),
+            MouseEventKind::Down(MouseButton::Left) if input_area.contains(position) => {
+                self.app_state.send_modify(|state| { state.focus = Focus::Chat; });
+                ChatCommand::ClearSelection
+            },
+            MouseEventKind::Down(MouseButton::Left) if user_input => match cell {
+                Some(cell) => {
+                    self.app_state.send_modify(|state| { state.focus = Focus::Chat; });
+                    ChatCommand::StartMouseSelection(cell)
+                },
+                None => return
+            },
+            // The selection continues above or below the messages
+            MouseEventKind::Drag(MouseButton::Left) => match cell {
+                Some(cell) => ChatCommand::ExtendMouseSelection(cell),
+                None => return
+            },
+            MouseEventKind::Up(MouseButton::Left) => ChatCommand::FinishMouseSelection,
+            _ => return
+        };
+        self.chat_controller.send(command).expect("Chat controller does not receive values");
+    }
+
+    fn handle_sidebar(&self, event: Event) {
+        let Event::Key(KeyEvent { code, .. }) = event else {
+            return;
+        };
+        let mode = self.sidebar.borrow().mode.clone();
+        let command = match (mode, code) {
+            (SidebarMode::Browse, KeyCode::Up | KeyCode::Char('k')) => SidebarCommand::SelectPrevious,
+            (SidebarMode::Browse, KeyCode::Down | KeyCode::Char('j')) => SidebarCommand::SelectNext,
+            (SidebarMode::Browse, KeyCode::Enter) => SidebarCommand::Open,
+            (SidebarMode::Browse, KeyCode::Char('n')) => SidebarCommand::New,
+            (SidebarMode::Browse, KeyCode::Char('r')) => SidebarCommand::StartRename,
+            (SidebarMode::Browse, KeyCode::Char('d')) => SidebarCommand::StartDelete,
+            (SidebarMode::Browse, KeyCode::Char('/')) => SidebarCommand::StartFilter,
+            (SidebarMode::Browse, KeyCode::Esc) => SidebarCommand::Cancel,
+            (SidebarMode::Browse, _) => return,
+            (SidebarMode::Filter, KeyCode::Up) => SidebarCommand::SelectPrevious,
+            (SidebarMode::Filter, KeyCode::Down) => SidebarCommand::SelectNext,
+            (SidebarMode::Filter | SidebarMode::Rename(_), KeyCode::Enter) => SidebarCommand::Confirm,
+            (SidebarMode::Filter | SidebarMode::Rename(_), KeyCode::Esc) => SidebarCommand::Cancel,
+            (SidebarMode::Filter | SidebarMode::Rename(_), _) => SidebarCommand::Edit(event),
+            (SidebarMode::ConfirmDelete, KeyCode::Char('y') | KeyCode::Enter) => SidebarCommand::Confirm,
+            (SidebarMode::ConfirmDelete, _) => SidebarCommand::Cancel
+        };
+        self.send_sidebar(command);
+    }
+
+    fn send_sidebar(&self, command: SidebarCommand) {
+        self.sidebar_controller.send(command).expect("Sidebar controller does not receive values");
+    }
+}
+
+/// Convert a key event for the editor
+fn editor_input(key: KeyEvent) -> tui_textarea::Input {
+    let key_code = match key.code {
+        KeyCode::Char(char) => Key::Char(char),
+        KeyCode::F(number) => Key::F(number),
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Enter => Key::Enter,
+        KeyCode::Left => Key::Left,
+        KeyCode::Right => Key::Right,
+        KeyCode::Up => Key::Up,
+        KeyCode::Down => Key::Down,
+        KeyCode::Tab => Key::Tab,
+        KeyCode::Delete => Key::Delete,
+        KeyCode::Home => Key::Home,
+        KeyCode::End => Key::End,
+        KeyCode::PageUp => Key::PageUp,
+        KeyCode::PageDown => Key::PageDown,
+        KeyCode::Esc => Key::Esc,
+        _ => Key::Null
+    };
+    tui_textarea::Input {
+        key: key_code,
+        ctrl: key.modifiers.contains(KeyModifiers::CONTROL),
+        alt: key.modifiers.contains(KeyModifiers::ALT),
+        shift: key.modifiers.contains(KeyModifiers::SHIFT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+    use tokio::sync::{mpsc, watch};
+
+    use crate::tui::viewmodel::{AppState, Chat, Editor, Search, Sidebar, VimMode};
+
+    use super::{ChatCommand, Controller, CrosstermController, KeyChord, Keymap};
+
+    /// A controller of the chat and its commands, with vim keys in normal mode if `vim` is set
+    fn controller(chat: watch::Receiver<Chat>, vim: bool) -> (CrosstermController, mpsc::UnboundedReceiver<ChatCommand>) {
+        let (chat_controller, commands) = mpsc::unbounded_channel();
+        let controller = CrosstermController {
+            app_state: watch::Sender::new(AppState { vim: vim.then_some(VimMode::Normal), ..AppState::default() }),
+            chat,
+            chat_input: watch::Sender::new(Editor::default()),
+            chat_controller,
+            sidebar: watch::channel(Sidebar::default()).1,
+            sidebar_controller: mpsc::unbounded_channel().0,
+            keymap: Keymap::new(vim),
+            pending_keys: RefCell::default(),
+            pending_modes: Cell::new(&[])
+        };
+        (controller, commands)
+    }
+
+    #[test]
+    fn inserts_a_new_line_with_shift_or_alt_enter() {
+        let (_chat, chat_rx) = watch::channel(Chat::new("model"));
+        let (controller, mut commands) = controller(chat_rx, false);
+        controller.chat_input.send_replace(Editor::new("Hello"));
+
+        controller.handle(Event::Key(KeyEvent::new(KeyCode::Enter, KeyModifiers::SHIFT)));
+        controller.handle(Event::Key(KeyEvent::new(KeyCode::Enter, KeyModifiers::ALT)));
+
+        assert_eq!(controller.chat_input.borrow().text(), "Hello\n\n");
+        assert!(commands.try_recv().is_err());
+        controller.handle(Event::Key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)));
+        assert!(matches!(commands.try_recv(), Ok(ChatCommand::Submit)));
+    }
+
+    #[test]
+    fn drops_pending_keys_when_the_modes_change() {
+        let (chat, chat_rx) = watch::channel(Chat::new("model"));
+        let (controller, mut commands) = controller(chat_rx, true);
+        let key = Event::Key(KeyEvent::new(KeyCode::Char('g'), KeyModifiers::NONE));
+
+        controller.handle(key.clone());
+        chat.send_modify(|chat| {
+            let mut search = Search::new();
+            search.editing = false;
+            chat.search = Some(search);
+        });
+        controller.handle(key.clone());
+
+        // The key starts "g g" of the search, instead of completing the one of the input
+        assert_eq!(*controller.pending_keys.borrow(), vec![KeyChord::new(KeyCode::Char('g'), KeyModifiers::NONE)]);
+        assert!(commands.try_recv().is_err());
+        controller.handle(key);
+        assert!(matches!(commands.try_recv(), Ok(ChatCommand::ScrollToTop)));
     }
 }
@@ -0,0 +1,546 @@
+use std::{collections::HashMap, str::FromStr};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+
+/// A key together with the modifiers held down
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers
+}
+
+/// Keys pressed one after another, e.g. "g g"
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub struct KeySequence(pub Vec<KeyChord>);
+
+/// The set of bindings which applies, depending on what has the focus
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum KeyMode {
+    /// Everywhere, unless a binding of the current mode takes precedence
+    Global,
+    /// Typing into the input
+    Input,
+    /// The vim-style normal mode of the input
+    Normal,
+    /// While the input has the focus, in either vim mode
+    Scroll,
+    /// While messages are selected
+    Select,
+    /// While moving between the matches of a search
+    Search,
+    /// While a tool call waits for the approval of the user
+    Approval
+}
+
+/// Something the user can do with a key
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Action {
+    Quit,
+    ToggleSidebar,
+    NewSession,
+    /// Move the focus between the chat and the sidebar
+    SwitchFocus,
+    Submit,
+    Newline,
+    /// Complete the slash command or the path of a mentioned file
+    Complete,
+    Retry,
+    /// Dismiss the error, cancel editing, or else select the messages
+    Back,
+    ExternalEditor,
+    /// Scroll by a page
+    ScrollUp,
+    ScrollDown,
+    ScrollLineUp,
+    ScrollLineDown,
+    ScrollTop,
+    /// Scroll to the bottom and follow the response
+    ScrollBottom,
+    Search,
+    SelectPrevious,
+    SelectNext,
+    SelectFirst,
+    SelectLast,
+    PreviousAlternative,
+    NextAlternative,
+    CopyMessage,
+    /// Copy the code block with the number, counted from 1
+    CopyCodeBlock(usize),
+    DeleteMessage,
+    EditMessage,
+    RegenerateMessage,
+    /// Always keep the selected message when the conversation exceeds the context window
+    TogglePin,
+    ClearSelection,
+    NextMatch,
+    PreviousMatch,
+    CloseSearch,
+    /// Execute the tool call which waits for approval
+    ApproveTool,
+    /// Execute this and all further calls of the tool
+    AlwaysApproveTool,
+    /// Type the reason for denying the tool call
+    DenyTool,
+    /// Edit the input of the tool call before executing it
+    EditToolInput,
+    NormalMode,
+    InsertMode,
+    Append,
+    InsertLineStart,
+    AppendLineEnd,
+    OpenLineBelow,
+    OpenLineAbove,
+    CursorLeft,
+    CursorRight,
+    CursorUp,
+    CursorDown,
+    WordForward,
+    WordBack,
+    LineStart,
+    LineEnd,
+    InputTop,
+    InputBottom,
+    DeleteChar,
+    DeleteLine,
+    Undo,
+    Redo,
+    /// Leave the key to the next mode or the input, to remove a default binding
+    None
+}
+
+/// The names of the actions in the config file, except `copy-code-block-N`
+const ACTIONS: &[(&str, Action)] = &[
+    ("quit", Action::Quit),
+    ("toggle-sidebar", Action::ToggleSidebar),
+    ("new-session", Action::NewSession),
+    ("switch-focus", Action::SwitchFocus),
+    ("submit", Action::Submit),
+    ("newline", Action::Newline),
+    ("complete", Action::Complete),
+    ("retry", Action::Retry),
+    ("back", Action::Back),
+    ("external-editor", Action::ExternalEditor),
+    ("scroll-up", Action::ScrollUp),
+    ("scroll-down", Action::ScrollDown),
+    ("scroll-line-up", Action::ScrollLineUp),
+    ("scroll-line-down", Action::ScrollLineDown),
+    ("scroll-top", Action::ScrollTop),
+    ("scroll-bottom", Action::ScrollBottom),
+    ("search", Action::Search),
+    ("select-previous", Action::SelectPrevious),
+    ("select-next", Action::SelectNext),
+    ("select-first", Action::SelectFirst),
+    ("select-last", Action::SelectLast),
+    ("previous-alternative", Action::PreviousAlternative),
+    ("next-alternative", Action::NextAlternative),
+    ("copy-message", Action::CopyMessage),
+    ("delete-message", Action::DeleteMessage),
+    ("edit-message", Action::EditMessage),
+    ("regenerate-message", Action::RegenerateMessage),
+    ("toggle-pin", Action::TogglePin),
+    ("clear-selection", Action::ClearSelection),
+    ("next-match", Action::NextMatch),
+    ("previous-match", Action::PreviousMatch),
+    ("close-search", Action::CloseSearch),
+    ("approve-tool", Action::ApproveTool),
+    ("always-approve-tool", Action::AlwaysApproveTool),
+    ("deny-tool", Action::DenyTool),
+    ("edit-tool-input", Action::EditToolInput),
+    ("normal-mode", Action::NormalMode),
+    ("insert-mode", Action::InsertMode),
+    ("append", Action::Append),
+    ("insert-line-start", Action::InsertLineStart),
+    ("append-line-end", Action::AppendLineEnd),
+    ("open-line-below", Action::OpenLineBelow),
+    ("open-line-above", Action::OpenLineAbove),
+    ("cursor-left", Action::CursorLeft),
+    ("cursor-right", Action::CursorRight),
+    ("cursor-up", Action::CursorUp),
+    ("cursor-down", Action::CursorDown),
+    ("word-forward", Action::WordForward),
+    ("word-back", Action::WordBack),
+    ("line-start", Action::LineStart),
+    ("line-end", Action::LineEnd),
+    ("input-top", Action::InputTop),
+    ("input-bottom", Action::InputBottom),
+    ("delete-char", Action::DeleteChar),
+    ("delete-line", Action::DeleteLine),
+    ("undo", Action::Undo),
+    ("redo", Action::Redo),
+    ("none", Action::None)
+];
+
+const DEFAULT_BINDINGS: &[(KeyMode, &str, Action)] = &[
+    (KeyMode::Global, "ctrl+c", Action::Quit),
+    (KeyMode::Global, "ctrl+q", Action::Quit),
+    (KeyMode::Global, "ctrl+b", Action::ToggleSidebar),
+    (KeyMode::Global, "ctrl+n", Action::NewSession),
+    (KeyMode::Global, "tab", Action::SwitchFocus),
+    (KeyMode::Input, "enter", Action::Submit),
+    (KeyMode::Input, "tab", Action::Complete),
+    (KeyMode::Input, "ctrl+r", Action::Retry),
+    (KeyMode::Input, "esc", Action::Back),
+    (KeyMode::Input, "ctrl+g", Action::ExternalEditor),
+    (KeyMode::Scroll, "pageup", Action::ScrollUp),
+    (KeyMode::Scroll, "pagedown", Action::ScrollDown),
+    (KeyMode::Scroll, "ctrl+up", Action::ScrollLineUp),
+    (KeyMode::Scroll, "ctrl+down", Action::ScrollLineDown),
+    (KeyMode::Scroll, "ctrl+home", Action::ScrollTop),
+    (KeyMode::Scroll, "ctrl+end", Action::ScrollBottom),
+    (KeyMode::Scroll, "ctrl+f", Action::Search),
+    (KeyMode::Select, "up", Action::SelectPrevious),
+    (KeyMode::Select, "k", Action::SelectPrevious),
+    (KeyMode::Select, "down", Action::SelectNext),
+    (KeyMode::Select, "j", Action::SelectNext),
+    (KeyMode::Select, "home", Action::SelectFirst),
+    (KeyMode::Select, "end", Action::SelectLast),
+    (KeyMode::Select, "left", Action::PreviousAlternative),
+    (KeyMode::Select, "h", Action::PreviousAlternative),
+    (KeyMode::Select, "right", Action::NextAlternative),
+    (KeyMode::Select, "l", Action::NextAlternative),
+    (KeyMode::Select, "y", Action::CopyMessage),
+    (KeyMode::Select, "c", Action::CopyMessage),
+    (KeyMode::Select, "d", Action::DeleteMessage),
+    (KeyMode::Select, "delete", Action::DeleteMessage),
+    (KeyMode::Select, "e", Action::EditMessage),
+    (KeyMode::Select, "enter", Action::EditMessage),
+    (KeyMode::Select, "r", Action::RegenerateMessage),
+    (KeyMode::Select, "p", Action::TogglePin),
+    (KeyMode::Select, "/", Action::Search),
+    (KeyMode::Select, "esc", Action::ClearSelection),
+    (KeyMode::Select, "q", Action::ClearSelection),
+    (KeyMode::Search, "n", Action::NextMatch),
+    (KeyMode::Search, "N", Action::PreviousMatch),
+    (KeyMode::Search, "/", Action::Search),
+    (KeyMode::Search, "ctrl+f", Action::Search),
+    (KeyMode::Search, "pageup", Action::ScrollUp),
+    (KeyMode::Search, "pagedown", Action::ScrollDown),
+    (KeyMode::Search, "up", Action::ScrollLineUp),
+    (KeyMode::Search, "down", Action::ScrollLineDown),
+    (KeyMode::Search, "home", Action::ScrollTop),
+    (KeyMode::Search, "end", Action::ScrollBottom),
+    (KeyMode::Search, "esc", Action::CloseSearch),
+    (KeyMode::Search, "enter", Action::CloseSearch),
+    (KeyMode::Search, "q", Action::CloseSearch),
+    (KeyMode::Approval, "y", Action::ApproveTool),
+    (KeyMode::Approval, "a", Action::AlwaysApproveTool),
+    (KeyMode::Approval, "n", Action::DenyTool),
+    (KeyMode::Approval, "d", Action::DenyTool),
+    (KeyMode::Approval, "esc", Action::DenyTool),
+    (KeyMode::Approval, "e", Action::EditToolInput)
+];
+
+/// The bindings which are added, or replace the defaults, in vim mode
+const VIM_BINDINGS: &[(KeyMode, &str, Action)] = &[
+    (KeyMode::Input, "esc", Action::NormalMode),
+    (KeyMode::Normal, "i", Action::InsertMode),
+    (KeyMode::Normal, "a", Action::Append),
+    (KeyMode::Normal, "I", Action::InsertLineStart),
+    (KeyMode::Normal, "A", Action::AppendLineEnd),
+    (KeyMode::Normal, "o", Action::OpenLineBelow),
+    (KeyMode::Normal, "O", Action::OpenLineAbove),
+    (KeyMode::Normal, "h", Action::CursorLeft),
+    (KeyMode::Normal, "left", Action::CursorLeft),
+    (KeyMode::Normal, "l", Action::CursorRight),
+    (KeyMode::Normal, "right", Action::CursorRight),
+    (KeyMode::Normal, "k", Action::CursorUp),
+    (KeyMode::Normal, "up", Action::CursorUp),
+    (KeyMode::Normal, "j", Action::CursorDown),
+    (KeyMode::Normal, "down", Action::CursorDown),
+    (KeyMode::Normal, "w", Action::WordForward),
+    (KeyMode::Normal, "b", Action::WordBack),
+    (KeyMode::Normal, "0", Action::LineStart),
+    (KeyMode::Normal, "$", Action::LineEnd),
+    (KeyMode::Normal, "g g", Action::InputTop),
+    (KeyMode::Normal, "G", Action::InputBottom),
+    (KeyMode::Normal, "x", Action::DeleteChar),
+    (KeyMode::Normal, "d d", Action::DeleteLine),
+    (KeyMode::Normal, "u", Action::Undo),
+    (KeyMode::Normal, "ctrl+r", Action::Redo),
+    (KeyMode::Normal, "enter", Action::Submit),
+    (KeyMode::Normal, "esc", Action::Back),
+    (KeyMode::Normal, "/", Action::Search),
+    (KeyMode::Normal, "ctrl+u", Action::ScrollUp),
+    (KeyMode::Normal, "ctrl+d", Action::ScrollDown),
+    (KeyMode::Normal, "ctrl+y", Action::ScrollLineUp),
+    (KeyMode::Normal, "ctrl+e", Action::ScrollLineDown),
+    (KeyMode::Normal, "ctrl+g", Action::ExternalEditor),
+    (KeyMode::Select, "d", Action::None),
+    (KeyMode::Select, "d d", Action::DeleteMessage),
+    (KeyMode::Select, "g g", Action::SelectFirst),
+    (KeyMode::Select, "G", Action::SelectLast),
+    (KeyMode::Search, "k", Action::ScrollLineUp),
+    (KeyMode::Search, "j", Action::ScrollLineDown),
+    (KeyMode::Search, "g g", Action::ScrollTop),
+    (KeyMode::Search, "G", Action::ScrollBottom)
+];
+
+/// Which actions belong to the keys of each mode
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    /// The input has vim-style normal and insert modes
+    pub vim: bool,
+    bindings: HashMap<KeyMode, HashMap<KeySequence, Action>>
+}
+
+/// What the keys pressed so far are bound to
+pub enum Binding {
+    /// The actions bound to the keys, from the first mode with a binding on
+    Actions(Vec<Action>),
+    /// The keys are the beginning of a longer binding
+    Pending,
+    Unbound
+}
+
+impl Keymap {
+    /// The default bindings, with those of the vim mode if it is enabled
+    pub fn new(vim: bool) -> Self {
+        let mut keymap = Self { vim, bindings: HashMap::new() };
+        let vim_bindings = if vim { VIM_BINDINGS } else { &[] };
+        for (mode, keys, action) in DEFAULT_BINDINGS.iter().chain(vim_bindings) {
+            let sequence = keys.parse().expect("Default key binding is invalid");
+            keymap.bind(*mode, sequence, *action);
+        }
+        for number in 1..=9 {
+            let digit = char::from_digit(number, 10).expect("Code block number is not a digit");
+            keymap.bind(KeyMode::Select, KeySequence(vec![KeyChord::new(KeyCode::Char(digit), KeyModifiers::NONE)]), Action::CopyCodeBlock(number as usize));
+        }
+        keymap
+    }
+
+    pub fn bind(&mut self, mode: KeyMode, sequence: KeySequence, action: Action) {
+        self.bindings.entry(mode).or_default().insert(sequence, action);
+    }
+
+    /// Look up the keys in the modes, in order of precedence
+    ///
+    /// A binding of the exact keys takes precedence over longer bindings which start with them.
+    /// Keys bound to `Action::None` count as unbound.
+    pub fn resolve(&self, modes: &[KeyMode], keys: &[KeyChord]) -> Binding {
+        let bindings: Vec<&HashMap<KeySequence, Action>> = modes.iter().filter_map(|mode| self.bindings.get(mode)).collect();
+        let actions: Vec<Action> = bindings.iter()
+            .filter_map(|bindings| bindings.iter().find(|(sequence, _)| sequence.0 == keys))
+            .map(|(_, action)| *action)
+            .filter(|action| *action != Action::None)
+            .collect();
+        if !actions.is_empty() {
+            return Binding::Actions(actions);
+        }
+        let pending = bindings.iter().flat_map(|bindings| bindings.keys())
+            .any(|sequence| sequence.0.len() > keys.len() && sequence.0.starts_with(keys));
+        if pending { Binding::Pending } else { Binding::Unbound }
+    }
+}
+
+impl From<KeyEvent> for KeyChord {
+    fn from(key: KeyEvent) -> Self {
+        Self::new(key.code, key.modifiers)
+    }
+}
+
+impl KeyChord {
+    /// The chord in a normal form, so that it matches however the terminal reports it
+    ///
+    /// The case of a character tells whether Shift is held, except together with Ctrl or Alt,
+    /// which are reported inconsistently with Shift.
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers = modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        let code = match code {
+            KeyCode::Char(char) => {
+                let shift = modifiers.contains(KeyModifiers::SHIFT);
+                modifiers.remove(KeyModifiers::SHIFT);
+                match (modifiers.is_empty(), shift) {
+                    (false, _) => KeyCode::Char(char.to_ascii_lowercase()),
+                    (true, true) => KeyCode::Char(char.to_ascii_uppercase()),
+                    (true, false) => KeyCode::Char(char)
+                }
+            },
+            KeyCode::BackTab => {
+                modifiers.insert(KeyModifiers::SHIFT);
+                KeyCode::Tab
+            },
+            code => code
+        };
+        Self { code, modifiers }
+    }
+}
+
+/// Parses chords such as "ctrl+f", "alt+enter", "shift+tab" or "G"
+impl FromStr for KeyChord {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (modifier_names, key) = match text.rsplit_once('+') {
+            Some((modifiers, key)) if !key.is_empty() => (modifiers, key),
+            _ => ("", text)
+        };
+        let mut modifiers = KeyModifiers::NONE;
+        for name in modifier_names.split('+').filter(|name| !name.is_empty()) {
+            modifiers |= match name.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("Unknown modifier {name} in {text}"))
+            };
+        }
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(char), None) => KeyCode::Char(char),
+            _ => match key.to_ascii_lowercase().as_str() {
+                "enter" | "return" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "space" => KeyCode::Char(' '),
+                "plus" => KeyCode::Char('+'),
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                name => match name.strip_prefix('f').and_then(|number| number.parse().ok()) {
+                    Some(number) => KeyCode::F(number),
+                    None => return Err(format!("Unknown key {key} in {text}"))
+                }
+            }
+        };
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl FromStr for KeySequence {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let chords = text.split_whitespace().map(str::parse).collect::<Result<Vec<KeyChord>, _>>()?;
+        if chords.is_empty() {
+            return Err("Empty key binding".to_string());
+        }
+        Ok(Self(chords))
+    }
+}
+
+impl TryFrom<String> for KeySequence {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        if let Some(number) = name.strip_prefix("copy-code-block-") {
+            return number.parse().ok()
+                .filter(|number| *number > 0)
+                .map(Action::CopyCodeBlock)
+                .ok_or_else(|| format!("Invalid code block number in {name}"));
+        }
+        ACTIONS.iter()
+            .find(|(action_name, _)| *action_name == name)
+            .map(|(_, action)| *action)
+            .ok_or_else(|| format!("Unknown action {name}"))
+    }
+}
+
+impl TryFrom<String> for Action {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        name.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyCode, KeyModifiers};
+
+    use super::{Action, Binding, KeyChord, KeyMode, KeySequence, Keymap};
+
+    fn chord(text: &str) -> KeyChord {
+        text.parse().unwrap()
+    }
+
+    fn keys(text: &str) -> Vec<KeyChord> {
+        text.parse::<KeySequence>().unwrap().0
+    }
+
+    #[test]
+    fn normalizes_shift_and_the_case_of_characters() {
+        assert_eq!(KeyChord::new(KeyCode::Char('g'), KeyModifiers::SHIFT), KeyChord::new(KeyCode::Char('G'), KeyModifiers::NONE));
+        assert_eq!(KeyChord::new(KeyCode::Char('G'), KeyModifiers::SHIFT).modifiers, KeyModifiers::NONE);
+        assert_eq!(KeyChord::new(KeyCode::Char('R'), KeyModifiers::CONTROL | KeyModifiers::SHIFT), KeyChord::new(KeyCode::Char('r'), KeyModifiers::CONTROL));
+        assert_eq!(KeyChord::new(KeyCode::BackTab, KeyModifiers::NONE), KeyChord::new(KeyCode::Tab, KeyModifiers::SHIFT));
+        assert_eq!(KeyChord::new(KeyCode::Enter, KeyModifiers::SUPER | KeyModifiers::ALT).modifiers, KeyModifiers::ALT);
+    }
+
+    #[test]
+    fn parses_chords_and_sequences() {
+        assert_eq!(chord("ctrl+f"), KeyChord::new(KeyCode::Char('f'), KeyModifiers::CONTROL));
+        assert_eq!(chord("Alt+Enter"), KeyChord::new(KeyCode::Enter, KeyModifiers::ALT));
+        assert_eq!(chord("shift+g"), chord("G"));
+        assert_eq!(chord("ctrl+plus"), KeyChord::new(KeyCode::Char('+'), KeyModifiers::CONTROL));
+        assert_eq!(chord("space"), KeyChord::new(KeyCode::Char(' '), KeyModifiers::NONE));
+        assert_eq!(chord("f5"), KeyChord::new(KeyCode::F(5), KeyModifiers::NONE));
+        assert!("hyper+a".parse::<KeyChord>().is_err());
+        assert!("foo".parse::<KeyChord>().is_err());
+
+        assert_eq!(keys("g g"), vec![chord("g"), chord("g")]);
+        assert!("  ".parse::<KeySequence>().is_err());
+        assert!("g foo".parse::<KeySequence>().is_err());
+    }
+
+    #[test]
+    fn parses_actions() {
+        assert_eq!("approve-tool".parse(), Ok(Action::ApproveTool));
+        assert_eq!("none".parse(), Ok(Action::None));
+        assert_eq!("copy-code-block-3".parse(), Ok(Action::CopyCodeBlock(3)));
+        assert!("copy-code-block-0".parse::<Action>().is_err());
+        assert!("copy-code-block-x".parse::<Action>().is_err());
+        assert!("ApproveTool".parse::<Action>().is_err());
+    }
+
+    #[test]
+    fn resolves_bindings_in_the_order_of_the_modes() {
+        let keymap = Keymap::new(false);
+        let modes = [KeyMode::Select, KeyMode::Global];
+
+        assert!(matches!(keymap.resolve(&modes, &keys("1")), Binding::Actions(actions) if actions == vec![Action::CopyCodeBlock(1)]));
+        assert!(matches!(keymap.resolve(&[KeyMode::Scroll, KeyMode::Search], &keys("pageup")), Binding::Actions(actions) if actions == vec![Action::ScrollUp, Action::ScrollUp]));
+        assert!(matches!(keymap.resolve(&modes, &keys("g")), Binding::Unbound));
+        assert!(matches!(keymap.resolve(&modes, &keys("g g")), Binding::Unbound));
+    }
+
+    #[test]
+    fn adds_and_overrides_bindings_in_vim_mode() {
+        let keymap = Keymap::new(true);
+        let modes = [KeyMode::Select, KeyMode::Global];
+
+        assert!(matches!(keymap.resolve(&modes, &keys("g")), Binding::Pending));
+        assert!(matches!(keymap.resolve(&modes, &keys("g g")), Binding::Actions(actions) if actions == vec![Action::SelectFirst]));
+        // "d" is unbound in favor of "d d"
+        assert!(matches!(keymap.resolve(&modes, &keys("d")), Binding::Pending));
+        assert!(matches!(keymap.resolve(&modes, &keys("d d")), Binding::Actions(actions) if actions == vec![Action::DeleteMessage]));
+        assert!(matches!(keymap.resolve(&modes, &keys("g x")), Binding::Unbound));
+    }
+
+    #[test]
+    fn unbinds_keys_bound_to_none() {
+        let mut keymap = Keymap::new(false);
+        keymap.bind(KeyMode::Select, KeySequence(keys("y")), Action::None);
+        keymap.bind(KeyMode::Select, KeySequence(keys("ctrl+y")), Action::CopyMessage);
+
+        assert!(matches!(keymap.resolve(&[KeyMode::Select], &keys("y")), Binding::Unbound));
+        assert!(matches!(keymap.resolve(&[KeyMode::Select], &keys("ctrl+y")), Binding::Actions(actions) if actions == vec![Action::CopyMessage]));
+    }
+}
@@ -1,8 +1,15 @@
+mod approval;
+mod attachment;
 mod chat;
 mod crossterm;
+mod keymap;
+mod sidebar;
+mod slash;
 
 pub use chat::*;
 pub use crossterm::*;
+pub use keymap::{Action, KeyMode, KeySequence, Keymap};
+pub use sidebar::*;
 
 use tokio::sync::mpsc;
 
@@ -23,4 +30,3 @@ pub trait Controller<C> {
         sender
     }
 }
-
@@ -0,0 +1,312 @@
+use crossterm::event::Event;
+use tokio::sync::watch;
+use tui_input::{backend::crossterm::EventHandler, Input};
+
+use crate::tui::{session::SessionStore, viewmodel::{AppState, Chat, ChatError, Focus, SessionEntry, Sidebar, SidebarMode}};
+
+use super::Controller;
+
+pub struct SidebarController {
+    pub app_state: watch::Sender<AppState>,
+    pub sidebar: watch::Sender<Sidebar>,
+    pub chat: watch::Sender<Chat>,
+    pub sessions: SessionStore
+}
+
+impl Controller<SidebarCommand> for SidebarController {
+    fn handle(&self, command: SidebarCommand) {
+        match command {
+            SidebarCommand::Toggle => {
+                let visible = !self.sidebar.borrow().visible;
+                if visible {
+                    self.refresh();
+                }
+                self.sidebar.send_modify(|sidebar| {
+                    sidebar.visible = visible;
+                    sidebar.mode = SidebarMode::Browse;
+                });
+                self.focus(if visible { Focus::Sidebar } else { Focus::Chat });
+            },
+            SidebarCommand::SwitchFocus if self.sidebar.borrow().visible => {
+                let focus = self.app_state.borrow().focus;
+                if focus == Focus::Chat {
+                    self.refresh();
+                    self.focus(Focus::Sidebar);
+                } else {
+                    self.focus(Focus::Chat);
+                }
+            },
+            SidebarCommand::SwitchFocus => {},
+            SidebarCommand::SelectNext => self.sidebar.send_modify(Sidebar::select_next),
+            SidebarCommand::SelectPrevious => self.sidebar.send_modify(Sidebar::select_previous),
+            SidebarCommand::Open => {
+                let selected = self.sidebar.borrow().selected_session().map(|session| session.id.clone());
+                if let Some(id) = selected {
+                    self.open(&id);
+                }
+            },
+            SidebarCommand::OpenSession(id) => self.open(&id),
+            SidebarCommand::New => self.new_chat(false),
+            SidebarCommand::Clear => self.new_chat(true),
+            SidebarCommand::DeleteSession(id) => self.delete(&id),
+            SidebarCommand::StartFilter => self.set_mode(SidebarMode::Filter),
+            SidebarCommand::StartRename => {
+                let title = self.sidebar.borrow().selected_session().map(|session| session.title.clone());
+                if let Some(title) = title {
+                    self.set_mode(SidebarMode::Rename(Input::new(title)));
+                }
+            },
+            SidebarCommand::StartDelete => self.set_mode(SidebarMode::ConfirmDelete),
+            SidebarCommand::Confirm => {
+                let mode = self.sidebar.borrow().mode.clone();
+                let selected = self.sidebar.borrow().selected_session().map(|session| session.id.clone());
+                match (mode, selected) {
+                    (SidebarMode::Rename(input), Some(id)) => self.rename(&id, input.value()),
+                    (SidebarMode::ConfirmDelete, Some(id)) => self.delete(&id),
+                    _ => {}
+                }
+                self.set_mode(SidebarMode::Browse);
+            },
+            SidebarCommand::Cancel => {
+                let mode = self.sidebar.borrow().mode.clone();
+                match mode {
+                    SidebarMode::Browse => self.handle(SidebarCommand::Toggle),
+                    SidebarMode::Filter => self.sidebar.send_modify(|sidebar| {
+                        sidebar.filter = Input::default();
+                        sidebar.mode = SidebarMode::Browse;
+                        sidebar.clamp_selection();
+                    }),
+                    SidebarMode::Rename(_) | SidebarMode::ConfirmDelete => self.set_mode(SidebarMode::Browse)
+                }
+            },
+            SidebarCommand::Edit(event) => self.sidebar.send_modify(|sidebar| {
+                match &mut sidebar.mode {
+                    SidebarMode::Filter => {
+                        sidebar.filter.handle_event(&event);
+                        sidebar.clamp_selection();
+                    },
+                    SidebarMode::Rename(input) => {
+                        input.handle_event(&event);
+                    },
+                    SidebarMode::Browse | SidebarMode::ConfirmDelete => {}
+                }
+            })
+        }
+    }
+}
+
+impl SidebarController {
+    fn focus(&self, focus: Focus) {
+        self.app_state.send_modify(|state| { state.focus = focus; });
+    }
+
+    fn set_mode(&self, mode: SidebarMode) {
+        self.sidebar.send_modify(|sidebar| { sidebar.mode = mode; });
+    }
+
+    /// Reload the sessions from the session store
+    fn refresh(&self) {
+        match self.sessions.list() {
+            Ok(sessions) => self.sidebar.send_modify(|sidebar| {
+                sidebar.sessions = sessions.iter().map(|session| SessionEntry {
+                    id: session.id.clone(),
+                    title: session.display_title(),
+                    model: session.model.clone(),
+                    updated_at: session.updated_at
+                }).collect();
+                sidebar.clamp_selection();
+            }),
+            Err(err) => self.report_error(err)
+        }
+    }
+
+    fn open(&self, id: &str) {
+        if self.chat.borrow().id != id {
+            self.save_chat();
+            match self.sessions.load(id) {
+                Ok(session) => { self.chat.send_replace(session.into_chat()); },
+                Err(err) => self.report_error(err)
+            }
+        }
+        self.focus(Focus::Chat);
+    }
+
+    /// Save the chat and start a new one with the same model; `keep_system` keeps the system prompt as well
+    fn new_chat(&self, keep_system: bool) {
+        self.save_chat();
+        let (model, system) = {
+            let chat = self.chat.borrow();
+            (chat.model.clone(), if keep_system { chat.system.clone() } else { vec![] })
+        };
+        let mut chat = Chat::new(&model);
+        chat.system = system;
+        self.chat.send_replace(chat);
+        self.refresh();
+        self.focus(Focus::Chat);
+    }
+
+    fn rename(&self, id: &str, title: &str) {
+        let title = Some(title.trim().to_string()).filter(|title| !title.is_empty());
+        if self.chat.borrow().id == id {
+            self.chat.send_modify(|chat| { chat.title = title; });
+            self.save_chat();
+        } else {
+            let result = self.sessions.load(id).and_then(|mut session| {
+                session.title = title;
+                self.sessions.save(&session)
+            });
+            if let Err(err) = result {
+                self.report_error(err);
+            }
+        }
+        self.refresh();
+    }
+
+    fn delete(&self, id: &str) {
+        if let Err(err) = self.sessions.delete(id) {
+            self.report_error(err);
+        }
+        if self.chat.borrow().id == id {
+            let model = self.chat.borrow().model.clone();
+            self.chat.send_replace(Chat::new(&model));
+        }
+        self.refresh();
+    }
+
+    fn save_chat(&self) {
+        let saved = self.sessions.save_chat(&self.chat.borrow());
+        if let Err(err) = saved {
+            self.report_error(err);
+        }
+    }
+
+    fn report_error(&self, err: color_eyre::Report) {
+        self.chat.send_modify(|chat| {
+            chat.error = Some(ChatError::Session(format!("{err:#}")));
+        });
+    }
+}
+
+pub enum SidebarCommand {
+    Toggle,
+    SwitchFocus,
+    SelectNext,
+    SelectPrevious,
+    Open,
+    /// Open the session with the given identifier
+    OpenSession(String),
+    New,
+    /// Start a new chat with the model and the system prompt of the current one
+    Clear,
+    /// Delete the session with the given identifier, without confirmation
+    DeleteSession(String),
+    StartFilter,
+    StartRename,
+    StartDelete,
+    Confirm,
+    Cancel,
+    /// Edit the filter or the new title
+    Edit(Event)
+}
+
+#[cfg(test)]
+mod tests {
+    use lliminal::llm::SystemPrompt;
+    use tokio::sync::watch;
+    use tui_input::Input;
+
+    use crate::tui::{session::SessionStore, viewmodel::{AppState, Chat, Sidebar, SidebarMode}};
+
+    use super::{Controller, SidebarCommand, SidebarController};
+
+    fn controller(sessions: SessionStore) -> SidebarController {
+        SidebarController {
+            app_state: watch::Sender::new(AppState::default()),
+            sidebar: watch::Sender::new(Sidebar::default()),
+            chat: watch::Sender::new(Chat::new("model")),
+            sessions
+        }
+    }
+
+    /// A chat with a message, which can be saved; its identifier differs from those of new chats,
+    /// which are equal if they are created within the same millisecond
+    fn chat(id: &str) -> Chat {
+        let mut chat = Chat::new("model");
+        chat.id = id.to_string();
+        chat.submit_user_input("Hello", vec![]);
+        chat
+    }
+
+    /// Rename the selected session in the sidebar
+    fn rename(controller: &SidebarController, title: &str) {
+        controller.handle(SidebarCommand::Toggle);
+        controller.sidebar.send_modify(|sidebar| { sidebar.mode = SidebarMode::Rename(Input::new(title.to_string())); });
+        controller.handle(SidebarCommand::Confirm);
+    }
+
+    #[test]
+    fn renames_the_active_chat_and_saves_it() {
+        let directory = tempfile::tempdir().unwrap();
+        let controller = controller(SessionStore::new(directory.path().to_path_buf()));
+        controller.chat.send_replace(chat("20250102-030405-678"));
+        controller.sessions.save_chat(&controller.chat.borrow()).unwrap();
+
+        rename(&controller, "  Greeting ");
+
+        assert_eq!(controller.chat.borrow().title.as_deref(), Some("Greeting"));
+        assert_eq!(controller.sessions.load("20250102-030405-678").unwrap().title.as_deref(), Some("Greeting"));
+        assert_eq!(controller.sidebar.borrow().sessions[0].title, "Greeting");
+        assert!(matches!(controller.sidebar.borrow().mode, SidebarMode::Browse));
+    }
+
+    #[test]
+    fn renames_a_stored_session_without_changing_the_active_chat() {
+        let directory = tempfile::tempdir().unwrap();
+        let controller = controller(SessionStore::new(directory.path().to_path_buf()));
+        controller.sessions.save_chat(&chat("20250102-030405-678")).unwrap();
+
+        rename(&controller, "Greeting");
+
+        assert_eq!(controller.sessions.load("20250102-030405-678").unwrap().title.as_deref(), Some("Greeting"));
+        assert_eq!(controller.chat.borrow().title, None);
+        assert_eq!(controller.sessions.list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn replaces_the_active_chat_when_its_session_is_deleted() {
+        let directory = tempfile::tempdir().unwrap();
+        let controller = controller(SessionStore::new(directory.path().to_path_buf()));
+        controller.chat.send_replace(chat("20250102-030405-678"));
+        controller.sessions.save_chat(&controller.chat.borrow()).unwrap();
+
+        controller.handle(SidebarCommand::Toggle);
+        controller.handle(SidebarCommand::StartDelete);
+        controller.handle(SidebarCommand::Confirm);
+
+        assert!(!controller.sessions.contains("20250102-030405-678"));
+        let chat = controller.chat.borrow();
+        assert_ne!(chat.id, "20250102-030405-678");
+        assert!(chat.messages().is_empty());
+        assert_eq!(chat.model, "model");
+        assert!(controller.sidebar.borrow().sessions.is_empty());
+    }
+
+    #[test]
+    fn clears_the_chat_but_keeps_its_session_and_system_prompt() {
+        let directory = tempfile::tempdir().unwrap();
+        let controller = controller(SessionStore::new(directory.path().to_path_buf()));
+        let system = vec![SystemPrompt { content: "Be brief".to_string() }];
+        let mut saved = chat("20250102-030405-678");
+        saved.system = system.clone();
+        controller.chat.send_replace(saved);
+
+        controller.handle(SidebarCommand::Clear);
+
+        let chat = controller.chat.borrow();
+        assert_ne!(chat.id, "20250102-030405-678");
+        assert!(chat.messages().is_empty());
+        assert_eq!(chat.system, system);
+        assert_eq!(chat.model, "model");
+        assert!(controller.sessions.contains("20250102-030405-678"));
+    }
+}
@@ -0,0 +1,257 @@
+use std::{fs, path::Path};
+
+use lliminal::llm::SystemPrompt;
+
+use crate::tui::{export::{self, ExportFormat}, session::Session, viewmodel::{complete, ChatError, Editor, SlashCommand, SlashCommandKind, SlashInput, SLASH_COMMANDS}};
+
+use super::{chat::code_block, ChatController, SidebarCommand};
+
+/// Models offered for completion; any other model name can be entered as well
+const MODELS: &[&str] = &[
+    "claude-3-5-haiku-latest",
+    "claude-3-7-sonnet-latest",
+    "claude-sonnet-4-0",
+    "claude-opus-4-0",
+    "claude-opus-4-1",
+];
+
+/// The outcome of a slash command: a notice for the user, or an error
+type SlashResult = Result<Option<String>, String>;
+
+impl ChatController {
+    /// Run the slash command in the input; the input is kept if the command is invalid
+    pub(super) fn run_slash_command(&self, input: &SlashInput) {
+        let Some(command) = SlashCommand::find(input.name) else {
+            return self.report(Err(format!("Unknown command /{}, type /help for a list of commands", input.name)));
+        };
+        let arguments = input.arguments.unwrap_or_default();
+        if !command.accepts(arguments) {
+            return self.report(Err(format!("Usage: {}", command.help())));
+        }
+        let confirmation = format!("/{}", command.name);
+        if command.kind == SlashCommandKind::Delete && self.chat.borrow().confirm_input.as_ref() != Some(&confirmation) {
+            // The input is kept, so that submitting it again confirms the deletion
+            return self.chat.send_modify(|chat| {
+                chat.error = None;
+                chat.notice = Some("Delete this chat and its saved session? Press Enter again to delete it".to_string());
+                chat.confirm_input = Some(confirmation);
+            });
+        }
+        self.chat_input.send_replace(Editor::default());
+        let result = match command.kind {
+            SlashCommandKind::Model => self.change_model(arguments),
+            SlashCommandKind::System => self.change_system_prompt(arguments),
+            SlashCommandKind::Clear => {
+                self.send_sidebar(SidebarCommand::Clear);
+                Ok(None)
+            },
+            SlashCommandKind::New => {
+                self.send_sidebar(SidebarCommand::New);
+                Ok(None)
+            },
+            SlashCommandKind::Save => self.save(arguments),
+            SlashCommandKind::Load => self.sessions.find(arguments)
+                .map(|session| {
+                    self.send_sidebar(SidebarCommand::OpenSession(session.id));
+                    None
+                })
+                .map_err(|err| format!("{err:#}")),
+            SlashCommandKind::Delete => {
+                let id = self.chat.borrow().id.clone();
+                // A chat which has not been saved yet only needs to be replaced
+                let command = if self.sessions.contains(&id) { SidebarCommand::DeleteSession(id) } else { SidebarCommand::New };
+                self.send_sidebar(command);
+                Ok(None)
+            },
+            SlashCommandKind::Export => self.export(arguments),
+            SlashCommandKind::Retry => self.retry(),
+            SlashCommandKind::Copy => self.copy(arguments),
+            SlashCommandKind::Help => help(arguments)
+        };
+        self.report(result);
+    }
+
+    /// Complete the name or the arguments of the slash command in the input
+    pub(super) fn complete_slash_command(&self) {
+        let text = self.chat_input.borrow().text();
+        let Some(input) = SlashInput::parse(&text) else {
+            return;
+        };
+        let completed = match input.arguments {
+            None => {
+                // The space completes a unique command name as well
+                let names: Vec<String> = SLASH_COMMANDS.iter().map(|command| format!("{} ", command.name)).collect();
+                complete(input.name, names.iter().map(String::as_str)).map(|name| format!("/{name}"))
+            },
+            Some(arguments) => {
+                let candidates: Vec<String> = match SlashCommand::find(input.name).map(|command| command.kind) {
+                    Some(SlashCommandKind::Model) => MODELS.iter().map(ToString::to_string).collect(),
+                    Some(SlashCommandKind::Load) => self.sessions.list().unwrap_or_default().iter().map(Session::display_title).collect(),
+                    Some(SlashCommandKind::Help) => SLASH_COMMANDS.iter().map(|command| command.name.to_string()).collect(),
+                    _ => vec![]
+                };
+                complete(arguments, candidates.iter().map(String::as_str)).map(|arguments| format!("/{} {arguments}", input.name))
+            }
+        };
+        if let Some(completed) = completed {
+            self.chat_input.send_replace(Editor::new(&completed));
+        }
+    }
+
+    fn change_model(&self, model: &str) -> SlashResult {
+        if model.is_empty() {
+            return Ok(Some(format!("The chat uses {}", self.chat.borrow().model)));
+        }
+        self.chat.send_modify(|chat| { chat.model = model.to_string(); });
+        Ok(Some(format!("The chat now uses {model}")))
+    }
+
+    fn change_system_prompt(&self, prompt: &str) -> SlashResult {
+        self.chat.send_modify(|chat| {
+            chat.system = if prompt.is_empty() { vec![] } else { vec![SystemPrompt { content: prompt.to_string() }] };
+        });
+        Ok(Some(if prompt.is_empty() { "Removed the system prompt" } else { "Changed the system prompt" }.to_string()))
+    }
+
+    fn save(&self, title: &str) -> SlashResult {
+        if !title.is_empty() {
+            self.chat.send_modify(|chat| { chat.title = Some(title.to_string()); });
+        }
+        let session = Session::from_chat(&self.chat.borrow());
+        self.sessions.save(&session).map_err(|err| format!("{err:#}"))?;
+        Ok(Some(format!("Saved as {}", session.display_title())))
+    }
+
+    fn export(&self, path: &str) -> SlashResult {
+        let format = ExportFormat::from_path(Path::new(path))
+            .ok_or_else(|| "Cannot derive the format from the path, use .md, .html or .json".to_string())?;
+        let session = Session::from_chat(&self.chat.borrow());
+        let exported = export::export(&session, format).map_err(|err| format!("{err:#}"))?;
+        fs::write(path, exported).map_err(|err| format!("Cannot write {path}: {err}"))?;
+        Ok(Some(format!("Exported to {path}")))
+    }
+
+    fn copy(&self, code_block_number: &str) -> SlashResult {
+        let chat = self.chat.borrow();
+        let response = chat.last_response().ok_or_else(|| "There is no response to copy".to_string())?;
+        if code_block_number.is_empty() {
+            return Ok(Some(self.copy_to_clipboard(&response.text(), "the last response")));
+        }
+        let number = code_block_number.parse().map_err(|_| format!("Invalid code block number: {code_block_number}"))?;
+        Ok(Some(self.copy_to_clipboard(&code_block(response, number)?, &format!("code block {number}"))))
+    }
+
+    fn retry(&self) -> SlashResult {
+        let mut resend = false;
+        self.chat.send_modify(|chat| { resend = chat.prepare_resend(); });
+        if !resend {
+            return Err("There is no request to send again".to_string());
+        }
+        self.start_completion();
+        Ok(None)
+    }
+
+    fn send_sidebar(&self, command: SidebarCommand) {
+        self.sidebar_controller.send(command).expect("Sidebar controller does not receive values");
+    }
+
+    fn report(&self, result: SlashResult) {
+        self.chat.send_modify(|chat| {
+            match result {
+                Ok(notice) => {
+                    chat.notice = notice;
+                    chat.error = None;
+                },
+                Err(err) => {
+                    chat.notice = None;
+                    chat.error = Some(ChatError::Command(err));
+                }
+            }
+            chat.confirm_input = None;
+        });
+    }
+}
+
+fn help(command: &str) -> SlashResult {
+    if command.is_empty() {
+        return Ok(Some(SLASH_COMMANDS.iter().map(SlashCommand::help).collect::<Vec<_>>().join("\n")));
+    }
+    let name = command.trim_start_matches('/');
+    SlashCommand::find(name)
+        .map(|command| Some(command.help()))
+        .ok_or_else(|| format!("Unknown command /{name}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use crate::tui::{controller::{ChatCommand, Controller}, session::SessionStore, viewmodel::{ChatError, Editor}};
+
+    use super::{ChatController, SidebarCommand};
+
+    fn controller(sessions: SessionStore) -> (ChatController, mpsc::UnboundedReceiver<SidebarCommand>) {
+        let (sidebar_controller, commands) = mpsc::unbounded_channel();
+        (ChatController::for_tests(sessions, sidebar_controller), commands)
+    }
+
+    fn submit(controller: &ChatController, input: &str) {
+        controller.chat_input.send_replace(Editor::new(input));
+        controller.handle(ChatCommand::Submit);
+    }
+
+    #[test]
+    fn deletes_only_when_the_command_is_submitted_again() {
+        let directory = tempfile::tempdir().unwrap();
+        let (controller, mut commands) = controller(SessionStore::new(directory.path().to_path_buf()));
+
+        submit(&controller, "/delete");
+        assert!(controller.chat.borrow().notice.is_some());
+        assert_eq!(controller.chat_input.borrow().text(), "/delete");
+        assert!(commands.try_recv().is_err());
+
+        controller.handle(ChatCommand::Submit);
+        // The chat has not been saved, so it only needs to be replaced
+        assert!(matches!(commands.try_recv(), Ok(SidebarCommand::New)));
+        assert!(controller.chat_input.borrow().is_blank());
+        assert_eq!(controller.chat.borrow().confirm_input, None);
+    }
+
+    #[test]
+    fn deletes_the_saved_session_of_the_chat() {
+        let directory = tempfile::tempdir().unwrap();
+        let (controller, mut commands) = controller(SessionStore::new(directory.path().to_path_buf()));
+        controller.chat.send_modify(|chat| chat.submit_user_input("Hello", vec![]));
+        controller.sessions.save_chat(&controller.chat.borrow()).unwrap();
+
+        submit(&controller, "/delete");
+        controller.handle(ChatCommand::Submit);
+
+        let id = controller.chat.borrow().id.clone();
+        assert!(matches!(commands.try_recv(), Ok(SidebarCommand::DeleteSession(deleted)) if deleted == id));
+    }
+
+    #[test]
+    fn clears_the_chat_through_the_sidebar() {
+        let directory = tempfile::tempdir().unwrap();
+        let (controller, mut commands) = controller(SessionStore::new(directory.path().to_path_buf()));
+
+        submit(&controller, "/clear");
+
+        assert!(matches!(commands.try_recv(), Ok(SidebarCommand::Clear)));
+        assert!(controller.chat_input.borrow().is_blank());
+    }
+
+    #[test]
+    fn rejects_exports_to_unknown_file_extensions() {
+        let directory = tempfile::tempdir().unwrap();
+        let (controller, _commands) = controller(SessionStore::new(directory.path().to_path_buf()));
+        let path = directory.path().join("chat.txt");
+
+        submit(&controller, &format!("/export {}", path.display()));
+
+        let error = controller.chat.borrow().error.clone();
+        assert_eq!(error, Some(ChatError::Command("Cannot derive the format from the path, use .md, .html or .json".to_string())));
+        assert!(!path.exists());
+    }
+}
@@ -2,7 +2,7 @@ use color_eyre::eyre::eyre;
 use futures::{FutureExt, StreamExt};
 use crossterm::event::Event as CrosstermEvent;
 use std::time::Duration;
-use tokio::sync::mpsc;
+use tokio::{sync::mpsc, task::JoinHandle};
 
 /// The frequency at which tick events are emitted.
 const TICK_FPS: f64 = 30.0;
@@ -27,6 +27,8 @@ pub enum Event {
 pub struct EventHandler {
     /// Event receiver channel.
     receiver: mpsc::UnboundedReceiver<Event>,
+    /// The task which reads the events.
+    task: JoinHandle<color_eyre::Result<()>>,
 }
 
 impl EventHandler {
@@ -34,8 +36,8 @@ impl EventHandler {
     pub fn new() -> Self {
         let (sender, receiver) = mpsc::unbounded_channel();
         let actor = EventTask::new(sender);
-        tokio::spawn(async { actor.run().await });
-        Self { receiver }
+        let task = tokio::spawn(async { actor.run().await });
+        Self { receiver, task }
     }
 
     /// Receives an event from the sender.
@@ -55,6 +57,14 @@ impl EventHandler {
             Ok(events)
         }
     }
+
+    /// Stops reading terminal events, so that another program can read them.
+    ///
+    /// No more events are received afterwards; a new handler has to be constructed instead.
+    pub async fn stop(&mut self) {
+        self.receiver.close();
+        let _ = (&mut self.task).await;
+    }
 }
 
 /// A thread that handles reading crossterm events and emitting tick events on a regular schedule.
@@ -0,0 +1,249 @@
+use std::path::Path;
+
+use clap::ValueEnum;
+use color_eyre::eyre::eyre;
+use lliminal::llm::{schema, AssistantMessageContent, Message, UserMessageContent};
+use pulldown_cmark::{html, Event, Tag, TagEnd};
+use url::Url;
+
+use super::{session::Session, viewmodel::{attached_text_file, attachment_label, markdown_parser}};
+
+/// A format in which a chat can be exported
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// Markdown with a heading per message
+    Markdown,
+    /// A self-contained HTML page
+    Html,
+    /// The JSON format of saved sessions, including all branches, which can be loaded again
+    Json
+}
+
+impl ExportFormat {
+    /// The format which belongs to the extension of the path
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "md" | "markdown" => Some(Self::Markdown),
+            "html" | "htm" => Some(Self::Html),
+            "json" => Some(Self::Json),
+            _ => None
+        }
+    }
+}
+
+/// The styles of an exported HTML page
+const STYLE: &str = "
+body { max-width: 50rem; margin: 2rem auto; padding: 0 1rem; font-family: system-ui, sans-serif; line-height: 1.5; color: #1f2328; }
+h1 { border-bottom: 1px solid #d0d7de; padding-bottom: 0.3rem; }
+h2 { margin-top: 2rem; font-size: 1rem; text-transform: uppercase; letter-spacing: 0.05em; color: #59636e; }
+pre { padding: 0.75rem; overflow-x: auto; background: #f6f8fa; border-radius: 6px; }
+code { font-family: ui-monospace, monospace; font-size: 0.9em; }
+blockquote { margin: 0; padding-left: 1rem; border-left: 3px solid #d0d7de; color: #59636e; }
+table { border-collapse: collapse; }
+th, td { padding: 0.25rem 0.75rem; border: 1px solid #d0d7de; }
+";
+
+/// Export the active branch of the session
+pub fn export(session: &Session, format: ExportFormat) -> color_eyre::Result<String> {
+    match format {
+        ExportFormat::Markdown => Ok(markdown(session)),
+        ExportFormat::Html => Ok(html(session)),
+        ExportFormat::Json => schema::to_json(session).map_err(|err| eyre!("Cannot serialize the chat: {err:?}"))
+    }
+}
+
+fn markdown(session: &Session) -> String {
+    let mut markdown = format!("# {}\n\n*{}*\n", session.display_title(), session.model);
+    for prompt in &session.system {
+        markdown.push_str(&format!("\n## System\n\n{}\n", prompt.content.trim_end()));
+    }
+    for message in session.tree.messages() {
+        markdown.push_str(&message_markdown(&message));
+    }
+    markdown
+}
+
+fn message_markdown(message: &Message) -> String {
+    let mut sections = vec![];
+    let heading = match message {
+        Message::User { parts } => {
+            for (index, part) in parts.iter().enumerate() {
+                sections.push(match &part.content {
+                    UserMessageContent::Text { text } => match attached_text_file(text).filter(|_| index > 0) {
+                        Some((path, contents)) => format!("**Attachment** `{path}`\n\n{}", code_block(contents, "")),
+                        None => text.trim_end().to_string()
+                    },
+                    UserMessageContent::Image { .. } | UserMessageContent::Document { .. } => {
+                        format!("**Attachment** {}", attachment_label(&part.content).unwrap_or_default())
+                    },
+                    UserMessageContent::ToolResult { content, is_error, .. } => {
+                        let label = if *is_error { "Tool error" } else { "Tool result" };
+                        format!("**{label}**\n\n{}", code_block(content, ""))
+                    }
+                });
+            }
+            let tool_results = parts.iter().all(|part| matches!(part.content, UserMessageContent::ToolResult { .. }));
+            if tool_results && !parts.is_empty() { "Tool" } else { "User" }
+        },
+        Message::Assistant { parts } => {
+            for part in parts {
+                sections.push(match &part.content {
+                    AssistantMessageContent::Text { text } => text.trim_end().to_string(),
+                    AssistantMessageContent::ToolUse { name, input, .. } => {
+                        let input = serde_json::to_string_pretty(input).unwrap_or_default();
+                        format!("**Tool call** `{name}`\n\n{}", code_block(&input, "json"))
+                    }
+                });
+            }
+            "Assistant"
+        }
+    };
+    format!("\n## {heading}\n\n{}\n", sections.join("\n\n"))
+}
+
+/// A fenced code block which is longer than any backtick run in the code
+fn code_block(code: &str, language: &str) -> String {
+    let longest_run = code.split(|char| char != '`').map(str::len).max().unwrap_or_default();
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    format!("{fence}{language}\n{}\n{fence}", code.trim_end_matches('\n'))
+}
+
+fn html(session: &Session) -> String {
+    let markdown = markdown(session);
+    let mut body = String::new();
+    // Messages are shown as written, so any HTML in them is not interpreted, and links which could run scripts are left out
+    let mut allowed_links = vec![];
+    let events = markdown_parser(&markdown).filter_map(|event| match event {
+        Event::Html(html) | Event::InlineHtml(html) => Some(Event::Text(html)),
+        Event::Start(Tag::Link { ref dest_url, .. } | Tag::Image { ref dest_url, .. }) => {
+            let allowed = is_safe_url(dest_url);
+            allowed_links.push(allowed);
+            allowed.then_some(event)
+        },
+        Event::End(TagEnd::Link | TagEnd::Image) => allowed_links.pop().unwrap_or_default().then_some(event),
+        event => Some(event)
+    });
+    html::push_html(&mut body, events);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n{body}</body>\n</html>\n",
+        escape_html(&session.display_title())
+    )
+}
+
+/// Whether the URL may be linked from an exported page
+fn is_safe_url(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https" | "mailto"))
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use lliminal::llm::{schema, AssistantMessageContent, AssistantMessagePart, Message, SystemPrompt, UserMessageContent, UserMessagePart};
+    use serde_json::json;
+
+    use crate::tui::{session::Session, viewmodel::MessageTree};
+
+    use super::{code_block, escape_html, export, html, markdown, ExportFormat};
+
+    fn user(texts: &[&str]) -> Message {
+        let parts = texts.iter().map(|text| UserMessagePart { content: UserMessageContent::Text { text: text.to_string() } }).collect();
+        Message::User { parts }
+    }
+
+    fn assistant(content: AssistantMessageContent) -> Message {
+        Message::Assistant { parts: vec![AssistantMessagePart { complete: true, content }] }
+    }
+
+    fn session(messages: Vec<Message>) -> Session {
+        Session {
+            id: "20250102-030405-678".to_string(),
+            title: None,
+            model: "model".to_string(),
+            system: vec![SystemPrompt { content: "Be brief\n".to_string() }],
+            tree: MessageTree::from(messages),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            scroll: 0,
+            usage: Default::default()
+        }
+    }
+
+    #[test]
+    fn exports_messages_as_markdown_sections() {
+        let session = session(vec![
+            user(&["Summarize this", "<file path=\"notes.md\">\n# Notes\n</file>"]),
+            assistant(AssistantMessageContent::ToolUse { id: "call_1".to_string(), name: "files__read".to_string(), input: json!({ "path": "a" }) }),
+            Message::User { parts: vec![UserMessagePart { content: UserMessageContent::ToolResult { tool_use_id: "call_1".to_string(), content: "a".to_string(), is_error: true } }] },
+            assistant(AssistantMessageContent::Text { text: "Done\n".to_string() }),
+        ]);
+
+        assert_eq!(markdown(&session), concat!(
+            "# Summarize this\n\n*model*\n",
+            "\n## System\n\nBe brief\n",
+            "\n## User\n\nSummarize this\n\n**Attachment** `notes.md`\n\n```\n# Notes\n```\n",
+            "\n## Assistant\n\n**Tool call** `files__read`\n\n```json\n{\n  \"path\": \"a\"\n}\n```\n",
+            "\n## Tool\n\n**Tool error**\n\n```\na\n```\n",
+            "\n## Assistant\n\nDone\n",
+        ));
+    }
+
+    #[test]
+    fn fences_code_blocks_longer_than_backtick_runs() {
+        assert_eq!(code_block("let a = 1;\n", "rust"), "```rust\nlet a = 1;\n```");
+        assert_eq!(code_block("```\nnested\n```", ""), "````\n```\nnested\n```\n````");
+        assert_eq!(code_block("````", ""), "`````\n````\n`````");
+    }
+
+    #[test]
+    fn escapes_html_special_characters() {
+        assert_eq!(escape_html("<a href=\"x\">Tom & Jerry</a>"), "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&lt;/a&gt;");
+    }
+
+    #[test]
+    fn shows_html_of_messages_as_text() {
+        let html = html(&session(vec![user(&["<script>alert(1)</script> <b>bold</b>"])]));
+
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("<b>"));
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(html.contains("<title>&lt;script&gt;alert(1)&lt;/script&gt; &lt;b&gt;bold&lt;/b&gt;</title>"));
+    }
+
+    #[test]
+    fn links_only_web_and_mail_addresses() {
+        let mut session = session(vec![user(&[concat!(
+            "[web](https://example.com) [mail](mailto:a@example.com) ",
+            "[script](javascript:alert(1)) [data](data:text/html,x) [relative](notes.html) ",
+            "<JavaScript:alert(2)> ![image](data:image/png;base64,AA)"
+        )])]);
+        session.title = Some("Links".to_string());
+
+        let html = html(&session);
+
+        assert!(html.contains("<a href=\"https://example.com\">web</a>"));
+        assert!(html.contains("<a href=\"mailto:a@example.com\">mail</a>"));
+        assert_eq!(html.matches("href=").count(), 2);
+        assert!(!html.contains("src="));
+        assert!(html.contains("</a> script data relative JavaScript:alert(2) image</p>"));
+    }
+
+    #[test]
+    fn exports_json_which_loads_as_session() {
+        let mut session = session(vec![user(&["Hello"]), assistant(AssistantMessageContent::Text { text: "Hi".to_string() })]);
+        session.tree.branch(1);
+        session.tree.push(assistant(AssistantMessageContent::Text { text: "Hello".to_string() }));
+
+        let json = export(&session, ExportFormat::Json).unwrap();
+        let loaded: Session = schema::from_json(&json).unwrap();
+
+        assert_eq!(loaded.id, session.id);
+        assert_eq!(loaded.model, session.model);
+        assert_eq!(loaded.system, session.system);
+        assert_eq!(loaded.tree, session.tree);
+        assert_eq!(loaded.tree.path_alternatives(), vec![(0, 1), (1, 2)]);
+    }
+}
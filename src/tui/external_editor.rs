@@ -0,0 +1,59 @@
+use std::{env, fs, io::Write};
+
+use color_eyre::eyre::{eyre, WrapErr};
+use tokio::process::Command;
+
+/// The editor used if neither `$VISUAL` nor `$EDITOR` is set
+const DEFAULT_EDITOR: &str = "vi";
+
+/// Edit text in the user's editor and return the result
+///
+/// The terminal must be suspended while the editor runs.
+pub async fn edit(text: &str) -> color_eyre::Result<String> {
+    let editor = env::var("VISUAL").or_else(|_| env::var("EDITOR")).unwrap_or(DEFAULT_EDITOR.to_string());
+    let (program, arguments) = split_command(&editor)?;
+
+    let mut file = tempfile::Builder::new().prefix("lliminal-").suffix(".md").tempfile()
+        .wrap_err("Cannot create a temporary file for the editor")?;
+    file.write_all(text.as_bytes()).wrap_err("Cannot write the temporary file for the editor")?;
+
+    let status = Command::new(program).args(arguments).arg(file.path()).status().await
+        .wrap_err_with(|| format!("Cannot run editor {editor}"))?;
+    if !status.success() {
+        return Err(eyre!("Editor {editor} failed with {status}"));
+    }
+    let edited = fs::read_to_string(file.path()).wrap_err("Cannot read the temporary file of the editor")?;
+    // Editors usually end the file with a newline
+    Ok(edited.strip_suffix('\n').unwrap_or(&edited).to_string())
+}
+
+/// Split the editor command into the program and its arguments
+///
+/// The command may contain arguments, e.g. `code --wait`, which are quoted like in a shell if they
+/// contain spaces, e.g. `"/Applications/Sublime Text.app/Contents/MacOS/subl" -w`.
+fn split_command(command: &str) -> color_eyre::Result<(String, Vec<String>)> {
+    let mut words = shlex::split(command).ok_or_else(|| eyre!("Cannot parse the editor command {command}"))?.into_iter();
+    let program = words.next().ok_or_else(|| eyre!("The editor command is empty"))?;
+    Ok((program, words.collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_command;
+
+    #[test]
+    fn splits_the_editor_command_like_a_shell() {
+        assert_eq!(split_command("vi").unwrap(), ("vi".to_string(), vec![]));
+        assert_eq!(split_command("code  --wait").unwrap(), ("code".to_string(), vec!["--wait".to_string()]));
+        assert_eq!(
+            split_command("'/opt/My Editor/edit' -w \"a b\"").unwrap(),
+            ("/opt/My Editor/edit".to_string(), vec!["-w".to_string(), "a b".to_string()])
+        );
+    }
+
+    #[test]
+    fn rejects_empty_and_unbalanced_commands() {
+        assert!(split_command(" ").is_err());
+        assert!(split_command("'vi").is_err());
+    }
+}
@@ -1,15 +1,62 @@
+use std::fs;
+
 use app::App;
+use clap::Parser;
+use cli::{Cli, Command, ExportArgs};
+use color_eyre::eyre::WrapErr;
+use config::Config;
+use export::ExportFormat;
+use session::{Session, SessionStore};
+use viewmodel::Chat;
 
 mod app;
+mod cli;
+mod clipboard;
+mod config;
 mod controller;
 mod event;
+mod export;
+mod external_editor;
+mod session;
+mod terminal;
 mod view;
 mod viewmodel;
 
 pub async fn main() -> color_eyre::Result<()> {
     color_eyre::install()?;
-    let terminal = ratatui::init();
-    let result = App::default().run(terminal).await;
-    ratatui::restore();
+    let cli = Cli::parse();
+    let config = Config::load()?;
+    let sessions = SessionStore::open_default()?;
+    if let Some(Command::Export(args)) = cli.command {
+        return export_session(&sessions, args);
+    }
+    let mut chat = match (cli.resume, cli.continue_last) {
+        (Some(name), _) => sessions.find(&name)?.into_chat(),
+        (None, true) => sessions.latest()?.map_or_else(|| Chat::new(controller::MODEL), Session::into_chat),
+        (None, false) => Chat::new(controller::MODEL)
+    };
+    let theme = config.theme.theme()?;
+    let (tools, failures) = config.tools().await;
+    if !failures.is_empty() {
+        chat.notice = Some(failures.join("; "));
+    }
+    let terminal = terminal::init();
+    let result = App::new(chat, tools, config.context, theme, config.keys.keymap(), sessions).run(terminal).await;
+    terminal::restore();
     result
 }
+
+fn export_session(sessions: &SessionStore, args: ExportArgs) -> color_eyre::Result<()> {
+    let session = sessions.find(&args.session)?;
+    let format = args.format
+        .or_else(|| args.output.as_deref().and_then(ExportFormat::from_path))
+        .unwrap_or(ExportFormat::Markdown);
+    let exported = export::export(&session, format)?;
+    match args.output {
+        Some(path) => fs::write(&path, exported).wrap_err_with(|| format!("Cannot write {}", path.display())),
+        None => {
+            print!("{exported}");
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use std::{cmp::Reverse, collections::HashSet, fs, path::PathBuf, sync::{Arc, Mutex}};
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{eyre, WrapErr};
+use lliminal::llm::{schema::{self, SchemaError, Versioned}, Message, SystemPrompt, Usage};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::viewmodel::{Chat, MessageTree};
+
+/// The maximum number of characters of a title derived from the first message
+const TITLE_LENGTH: usize = 60;
+
+/// A chat as it is saved in the data directory
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    pub title: Option<String>,
+    pub model: String,
+    pub system: Vec<SystemPrompt>,
+    /// The messages, including the branches which were created by editing or regenerating
+    pub tree: MessageTree,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub scroll: usize,
+    /// The tokens used by all requests; missing in sessions which were saved before it was counted
+    #[serde(default)]
+    pub usage: Usage
+}
+
+/// Version 2 replaces the list of `messages` by a `tree` of messages with alternative branches.
+/// Version 3 adds `image` and `document` parts to user messages for attached files; older sessions
+/// are read as they are.
+impl Versioned for Session {
+    const VERSION: u64 = 3;
+
+    fn migrate(version: u64, mut document: Value) -> Result<Value, SchemaError> {
+        match version {
+            1 => {
+                let messages: Vec<Message> = serde_json::from_value(document["messages"].take())
+                    .map_err(|err| SchemaError::InvalidDocument { reason: err.to_string() })?;
+                let tree = serde_json::to_value(MessageTree::from(messages))
+                    .map_err(|err| SchemaError::InvalidDocument { reason: err.to_string() })?;
+                if let Value::Object(fields) = &mut document {
+                    fields.remove("messages");
+                    fields.insert("tree".to_string(), tree);
+                }
+                Ok(document)
+            },
+            2 => Ok(document),
+            _ => Err(SchemaError::UnsupportedVersion { version })
+        }
+    }
+}
+
+impl Session {
+    pub fn from_chat(chat: &Chat) -> Self {
+        Self {
+            id: chat.id.clone(),
+            title: chat.title.clone(),
+            model: chat.model.clone(),
+            system: chat.system.clone(),
+            tree: chat.tree().clone(),
+            created_at: chat.created_at,
+            updated_at: Utc::now(),
+            scroll: chat.scroll,
+            usage: chat.usage
+        }
+    }
+
+    pub fn into_chat(self) -> Chat {
+        let mut chat = Chat::new(&self.model);
+        chat.id = self.id;
+        chat.title = self.title;
+        chat.created_at = self.created_at;
+        chat.system = self.system;
+        chat.scroll = self.scroll;
+        chat.usage = self.usage;
+        chat.set_tree(self.tree);
+        chat
+    }
+
+    /// The title of the session, or the beginning of the first message if it has none
+    pub fn display_title(&self) -> String {
+        self.title.clone().unwrap_or_else(|| {
+            let text = self.tree.messages().first().map(Message::text).unwrap_or_default();
+            let first_line = text.lines().next().unwrap_or("New chat");
+            first_line.chars().take(TITLE_LENGTH).collect()
+        })
+    }
+}
+
+/// Saves sessions as JSON files in a directory
+#[derive(Clone, Debug)]
+pub struct SessionStore {
+    directory: PathBuf,
+    /// The sessions which have been deleted; clones share them, so that no request which finishes later saves them again
+    deleted: Arc<Mutex<HashSet<String>>>
+}
+
+impl SessionStore {
+    pub fn new(directory: PathBuf) -> Self {
+        Self { directory, deleted: Arc::default() }
+    }
+
+    /// The store in `lliminal/sessions` of the user's data directory
+    pub fn open_default() -> color_eyre::Result<Self> {
+        let data_dir = dirs::data_dir().ok_or_else(|| eyre!("Cannot determine the data directory"))?;
+        Ok(Self::new(data_dir.join("lliminal").join("sessions")))
+    }
+
+    /// Save the chat, unless it has no messages yet
+    pub fn save_chat(&self, chat: &Chat) -> color_eyre::Result<()> {
+        if chat.tree().is_empty() {
+            return Ok(());
+        }
+        self.save(&Session::from_chat(chat))
+    }
+
+    /// Save the session, unless it has been deleted
+    pub fn save(&self, session: &Session) -> color_eyre::Result<()> {
+        if self.deleted.lock().expect("Deleted sessions lock is poisoned").contains(&session.id) {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.directory)
+            .wrap_err_with(|| format!("Cannot create session directory {}", self.directory.display()))?;
+        let json = schema::to_json(session).map_err(|err| eyre!("Cannot serialize session: {err:?}"))?;
+        // Write to a temporary file first, so a crash never leaves a truncated session behind
+        let path = self.path(&session.id)?;
+        let temporary_path = path.with_extension("json.tmp");
+        fs::write(&temporary_path, json)
+            .and_then(|()| fs::rename(&temporary_path, &path))
+            .wrap_err_with(|| format!("Cannot write session {}", path.display()))
+    }
+
+    pub fn load(&self, id: &str) -> color_eyre::Result<Session> {
+        let path = self.path(id)?;
+        let json = fs::read_to_string(&path).wrap_err_with(|| format!("Cannot read session {}", path.display()))?;
+        schema::from_json(&json).map_err(|err| eyre!("Invalid session {}: {err:?}", path.display()))
+    }
+
+    /// All sessions, most recently updated first; unreadable files are skipped
+    pub fn list(&self) -> color_eyre::Result<Vec<Session>> {
+        if !self.directory.exists() {
+            return Ok(vec![]);
+        }
+        let entries = fs::read_dir(&self.directory)
+            .wrap_err_with(|| format!("Cannot read session directory {}", self.directory.display()))?;
+        let mut sessions: Vec<Session> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.path().file_name()?.to_str()?.strip_suffix(".json").map(str::to_string))
+            .filter_map(|id| self.load(&id).ok())
+            .collect();
+        sessions.sort_by_key(|session| Reverse(session.updated_at));
+        Ok(sessions)
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.path(id).is_ok_and(|path| path.exists())
+    }
+
+    pub fn delete(&self, id: &str) -> color_eyre::Result<()> {
+        let path = self.path(id)?;
+        self.deleted.lock().expect("Deleted sessions lock is poisoned").insert(id.to_string());
+        fs::remove_file(&path).wrap_err_with(|| format!("Cannot delete session {}", path.display()))
+    }
+
+    /// Find a session by its identifier or its title
+    pub fn find(&self, name: &str) -> color_eyre::Result<Session> {
+        if self.contains(name) {
+            return self.load(name);
+        }
+        self.list()?.into_iter()
+            .find(|session| session.display_title() == name)
+            .ok_or_else(|| eyre!("No session named {name}"))
+    }
+
+    /// The most recently updated session
+    pub fn latest(&self) -> color_eyre::Result<Option<Session>> {
+        Ok(self.list()?.into_iter().next())
+    }
+
+    /// The file of the session; identifiers other than generated ones are rejected, so that no other file is accessed
+    fn path(&self, id: &str) -> color_eyre::Result<PathBuf> {
+        if !is_session_id(id) {
+            return Err(eyre!("Invalid session identifier {id}"));
+        }
+        Ok(self.directory.join(format!("{id}.json")))
+    }
+}
+
+/// Whether the text has the format of generated session identifiers, e.g. `20250102-030405-678`
+fn is_session_id(text: &str) -> bool {
+    let groups: Vec<&str> = text.split('-').collect();
+    groups.iter().map(|group| group.len()).eq([8, 6, 3])
+        && groups.iter().all(|group| group.bytes().all(|byte| byte.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use lliminal::llm::{schema, Message, Usage, UserMessageContent, UserMessagePart};
+
+    use crate::tui::viewmodel::Chat;
+
+    use super::{is_session_id, Session, SessionStore};
+
+    fn chat_with_message() -> Chat {
+        let mut chat = Chat::new("model");
+        chat.submit_user_input("Hello", vec![]);
+        chat
+    }
+
+    #[test]
+    fn accepts_generated_identifiers() {
+        assert!(is_session_id(&Chat::new("model").id));
+        assert!(!is_session_id("../20250102-030405-678"));
+        assert!(!is_session_id("20250102-030405-678/.."));
+        assert!(!is_session_id("2025010a-030405-678"));
+        assert!(!is_session_id(""));
+    }
+
+    #[test]
+    fn does_not_access_files_outside_the_directory() {
+        let directory = tempfile::tempdir().unwrap();
+        let sessions = SessionStore::new(directory.path().join("sessions"));
+        fs::create_dir(directory.path().join("sessions")).unwrap();
+        fs::write(directory.path().join("secret.json"), "{}").unwrap();
+
+        assert!(!sessions.contains("../secret"));
+        assert!(sessions.load("../secret").is_err());
+        assert!(sessions.delete("../secret").is_err());
+        assert!(sessions.find("../secret").is_err());
+        assert!(directory.path().join("secret.json").exists());
+    }
+
+    #[test]
+    fn never_saves_deleted_sessions_again() {
+        let directory = tempfile::tempdir().unwrap();
+        let sessions = SessionStore::new(directory.path().to_path_buf());
+        let chat = chat_with_message();
+        sessions.save_chat(&chat).unwrap();
+
+        sessions.clone().delete(&chat.id).unwrap();
+        sessions.save_chat(&chat).unwrap();
+
+        assert!(!sessions.contains(&chat.id));
+    }
+
+    #[test]
+    fn migrates_message_list_into_tree() {
+        let json = r#"{
+            "version": 1,
+            "id": "20250102-030405-678",
+            "title": null,
+            "model": "claude-3-5-haiku-latest",
+            "system": [{ "content": "Be brief" }],
+            "messages": [
+                { "role": "user", "parts": [{ "type": "text", "text": "Hello" }] },
+                { "role": "assistant", "parts": [{ "complete": true, "type": "text", "text": "Hi" }] }
+            ],
+            "created_at": "2025-01-02T03:04:05.678Z",
+            "updated_at": "2025-01-02T03:05:00Z",
+            "scroll": 2
+        }"#;
+
+        let session: Session = schema::from_json(json).unwrap();
+
+        assert_eq!(session.tree.messages().iter().map(Message::text).collect::<Vec<_>>(), vec!["Hello", "Hi"]);
+        assert_eq!(session.tree.path_alternatives(), vec![(0, 1), (0, 1)]);
+        assert_eq!(session.display_title(), "Hello");
+        assert_eq!(session.scroll, 2);
+        assert_eq!(session.usage, Usage::default());
+        let chat = session.into_chat();
+        assert_eq!(chat.messages().len(), 2);
+        assert_eq!(chat.system[0].content, "Be brief");
+    }
+
+    #[test]
+    fn saves_and_loads_current_version() {
+        let directory = tempfile::tempdir().unwrap();
+        let sessions = SessionStore::new(directory.path().to_path_buf());
+        let mut chat = chat_with_message();
+        chat.selected = Some(0);
+        chat.toggle_pinned();
+        sessions.save_chat(&chat).unwrap();
+
+        let session = sessions.load(&chat.id).unwrap();
+
+        assert_eq!(session.tree, *chat.tree());
+        assert_eq!(session.into_chat().pinned_messages().into_iter().collect::<Vec<_>>(), vec![0]);
+    }
+
+    #[test]
+    fn reads_version_2_and_saves_attached_images() {
+        let mut chat = Chat::new("model");
+        let image = UserMessagePart { content: UserMessageContent::Image { media_type: "image/png".to_string(), data: "iVBORw0K".to_string() } };
+        chat.submit_user_input("Look", vec![image]);
+        let mut document = schema::to_value(&Session::from_chat(&chat)).unwrap();
+
+        assert_eq!(document["version"], 3);
+        document["version"] = 2.into();
+        let session: Session = schema::from_value(document).unwrap();
+
+        assert_eq!(session.tree, *chat.tree());
+    }
+}
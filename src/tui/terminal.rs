@@ -0,0 +1,47 @@
+use std::io::stdout;
+
+use crossterm::{event::{DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags}, execute, terminal::{disable_raw_mode, enable_raw_mode, supports_keyboard_enhancement, EnterAlternateScreen, LeaveAlternateScreen}};
+use ratatui::DefaultTerminal;
+
+/// Take over the terminal, with bracketed paste, mouse events and modifiers on keys like Enter
+pub fn init() -> DefaultTerminal {
+    let terminal = ratatui::init();
+    enable_extensions();
+    terminal
+}
+
+pub fn restore() {
+    disable_extensions();
+    ratatui::restore();
+}
+
+/// Hand the terminal to another program until [`resume`] is called
+pub fn suspend() -> color_eyre::Result<()> {
+    disable_extensions();
+    execute!(stdout(), LeaveAlternateScreen)?;
+    disable_raw_mode()?;
+    Ok(())
+}
+
+pub fn resume(terminal: &mut DefaultTerminal) -> color_eyre::Result<()> {
+    enable_raw_mode()?;
+    execute!(stdout(), EnterAlternateScreen)?;
+    enable_extensions();
+    terminal.clear()?;
+    Ok(())
+}
+
+// Terminals without support for these simply ignore them, so errors are ignored as well
+fn enable_extensions() {
+    let _ = execute!(stdout(), EnableBracketedPaste, EnableMouseCapture);
+    if supports_keyboard_enhancement().unwrap_or(false) {
+        let _ = execute!(stdout(), PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES));
+    }
+}
+
+fn disable_extensions() {
+    if supports_keyboard_enhancement().unwrap_or(false) {
+        let _ = execute!(stdout(), PopKeyboardEnhancementFlags);
+    }
+    let _ = execute!(stdout(), DisableBracketedPaste, DisableMouseCapture);
+}
@@ -1,86 +1,504 @@
-use lliminal::llm::{AssistantMessageContent, AssistantMessagePart, UserMessageContent, UserMessagePart};
-use ratatui::{buffer::Buffer, layout::{Constraint, Layout, Position, Rect}, style::{Style, Stylize}, text::Line, widgets::{Block, Paragraph, Widget}};
+use std::{cell::RefCell, collections::{HashMap, HashSet}, ops::Range, sync::Arc};
+
+use lliminal::llm::{AssistantMessageContent, AssistantMessagePart, LlmError, Message, UserMessageContent, UserMessagePart};
+use ratatui::{buffer::Buffer, layout::{Constraint, Layout, Position, Rect}, style::{Style, Stylize}, text::{Line, Span}, widgets::{Block, Clear, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState, StatefulWidget, Widget}};
 use tokio::sync::watch;
-use tui_input::Input;
 
-use crate::tui::viewmodel::{AppState, Chat};
+use textwrap::core::display_width;
+
+use crate::tui::viewmodel::{attachment_label, estimated_cost, ApprovalMode, AppState, Chat, ChatError, ChatLayout, Editor, Focus, LineSource, MessageLines, Search, SlashCommand, SlashInput, VimMode};
+
+use super::{highlight::highlight_code, markdown::{markdown_lines, plain_lines}, set_cursor_position, Theme};
+
+/// The provider of all models
+const PROVIDER: &str = "Anthropic";
+
+/// The frames of the animation while a response is received
+const SPINNER: &[&str] = &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// The number of lines the input grows to before it scrolls
+const MAX_INPUT_LINES: usize = 10;
 
 pub struct ChatWidget {
     pub app_state: watch::Sender<AppState>,
     pub chat: watch::Receiver<Chat>,
-    pub chat_input: watch::Receiver<Input>
+    pub chat_input: watch::Receiver<Editor>,
+    pub theme: Theme,
+    pub line_cache: RefCell<LineCache>
+}
+
+/// The wrapped lines of the messages, so that only messages which changed are wrapped again
+#[derive(Default)]
+pub struct LineCache {
+    width: u16,
+    /// The lines of the messages and the parts of the text which they show, by the versions of their content
+    lines: HashMap<u64, (Vec<Line<'static>>, Arc<[LineSource]>)>
+}
+
+impl LineCache {
+    /// Wrap the messages which are new or changed, and forget those which are gone
+    fn update(&mut self, messages: &[Message], versions: &[u64], width: u16, theme: &Theme) {
+        if width != self.width {
+            self.lines.clear();
+            self.width = width;
+        }
+        for (message, version) in messages.iter().zip(versions) {
+            self.lines.entry(*version).or_insert_with(|| {
+                let (lines, sources) = match message {
+                    Message::User { parts } => user_message_lines(parts, width, theme),
+                    Message::Assistant { parts } => assistant_message_lines(parts, width, theme)
+                };
+                (lines, sources.into())
+            });
+        }
+        if self.lines.len() > versions.len() {
+            let current: HashSet<&u64> = versions.iter().collect();
+            self.lines.retain(|version, _| current.contains(version));
+        }
+    }
+
+    fn lines(&self, version: u64) -> &[Line<'static>] {
+        self.lines.get(&version).map_or(&[], |(lines, _)| lines.as_slice())
+    }
+
+    fn sources(&self, version: u64) -> Arc<[LineSource]> {
+        self.lines.get(&version).map(|(_, sources)| sources.clone()).unwrap_or_default()
+    }
 }
 
 impl Widget for &ChatWidget {
-    fn render(self, area: Rect, buf: &mut Buffer) {
-        let [messages_area, input_area] = Layout::vertical([
-            Constraint::Min(1),
-            Constraint::Length(3),
-        ]).areas(area);
-        self.render_messages(messages_area, buf);
-        self.render_input(input_area, buf);
+    /// Render the chat as it was laid out last; see [`ChatWidget::layout`]
+    fn render(self, _area: Rect, buf: &mut Buffer) {
+        let layout = self.app_state.borrow().chat_layout.clone();
+        self.render_messages(&layout, buf);
+        if !layout.banner.is_empty() {
+            self.render_banner(self.banner_lines(layout.banner.width.saturating_sub(2)), layout.banner, buf);
+        }
+        self.render_input(layout.input, buf);
+        self.render_status(layout.status, buf);
+        self.render_slash_commands(layout.messages, buf);
+        self.render_approval(layout.messages, buf);
     }
 }
 
 impl ChatWidget {
-    fn render_messages(&self, area: Rect, buf: &mut Buffer) {
-        let scroll = self.chat.borrow().scroll;
-        let mut y = area.y + area.height - 1;
-        for line in self.chat.borrow().messages.iter().flat_map(|msg| match msg {
-            lliminal::llm::Message::User { parts } => user_message_lines(parts, area.width),
-            lliminal::llm::Message::Assistant { parts } => assistant_message_lines(parts, area.width)
-        }).rev().skip(scroll) {
-            if y < area.y {
+    /// Lay out the chat in the area before it is rendered, wrapping the messages which changed
+    ///
+    /// The layout is kept in the app state, so that the controllers find the targets of mouse events and scroll within the
+    /// lines which are shown.
+    pub fn layout(&self, area: Rect) -> ChatLayout {
+        // The input grows with its content, up to half of the area
+        let input_lines = self.chat_input.borrow().line_count().clamp(1, MAX_INPUT_LINES);
+        let input_height = u16::try_from(input_lines + 2).expect("Overflow for input height").min(area.height / 2).max(3);
+        let banner_lines = self.banner_lines(area.width.saturating_sub(2)).len();
+        let banner_height = if banner_lines == 0 { 0 } else { u16::try_from(banner_lines + 2).unwrap_or(u16::MAX).min(area.height / 3) };
+        let [messages, banner, input, status] = Layout::vertical([
+            Constraint::Min(1),
+            Constraint::Length(banner_height),
+            Constraint::Length(input_height),
+            Constraint::Length(1),
+        ]).areas(area);
+
+        let chat = self.chat.borrow();
+        let mut cache = self.line_cache.borrow_mut();
+        // The last column is left for the scrollbar
+        cache.update(chat.messages(), chat.versions(), messages.width.saturating_sub(1), &self.theme);
+        let message_lines: Vec<MessageLines> = chat.versions().iter().enumerate()
+            .map(|(index, version)| MessageLines { version: *version, header: message_header(&chat, index).is_some(), sources: cache.sources(*version) })
+            .collect();
+        let heights: Vec<usize> = message_lines.iter().map(MessageLines::height).collect();
+        let height = usize::from(messages.height);
+        let total_lines = heights.iter().sum::<usize>();
+        // Keep the selected message in view, showing its beginning if it is too long
+        let scroll = match chat.selected.filter(|index| *index < heights.len()) {
+            Some(index) => heights[index + 1..].iter().sum::<usize>() + heights[index].saturating_sub(height),
+            None => chat.scroll_position(total_lines, height)
+        };
+        let search_matches = chat.search.as_ref().map(|search| search.matches(chat.messages())).unwrap_or_default();
+
+        // The lines which are shown from the bottom up
+        let mut rows = vec![];
+        let mut below = 0;
+        for (index, message_height) in heights.iter().enumerate().rev() {
+            if rows.len() >= height {
                 break;
             }
-            line.render(Rect { x: area.x, y, width: area.width, height: 1 }, buf);
-            if y == 0 {
-                break;
+            if below + message_height <= scroll {
+                below += message_height;
+                continue;
             }
-            y -= 1;
+            let skip = scroll.saturating_sub(below);
+            below += message_height;
+            let remaining = height - rows.len();
+            rows.extend((0..*message_height).rev().skip(skip).take(remaining).map(|line| (index, line)));
+        }
+        let mut message_rows = vec![None; height];
+        for (line, row) in rows.into_iter().zip((0..height).rev()) {
+            message_rows[row] = Some(line);
+        }
+        ChatLayout { messages, banner, input, status, message_lines, message_rows, scroll, total_lines, search_matches }
+    }
+
+    /// Render the lines of the messages in their rows, and a scrollbar
+    fn render_messages(&self, layout: &ChatLayout, buf: &mut Buffer) {
+        let chat = self.chat.borrow();
+        let cache = self.line_cache.borrow();
+        let area = layout.messages;
+        // The last column is left for the scrollbar
+        let width = area.width.saturating_sub(1);
+        let height = usize::from(area.height);
+        let current_match = chat.search.as_ref().and_then(|search| search.current_match(layout.search_matches.len()));
+        let selection = chat.mouse_selection.and_then(|selection| selection.range());
+        // The texts of the messages which contain selected text or search matches
+        let mut texts: HashMap<usize, String> = HashMap::new();
+        for (row, position) in layout.message_rows.iter().enumerate() {
+            let (Some((index, line)), Some(lines)) = (*position, position.and_then(|(index, _)| layout.message_lines.get(index))) else {
+                continue;
+            };
+            let mut rendered = match line.checked_sub(usize::from(lines.header)) {
+                Some(line) => cache.lines(lines.version).get(line).cloned().unwrap_or_default(),
+                None => Line::styled(message_header(&chat, index).unwrap_or_default(), self.theme.hint)
+            };
+            if chat.is_highlighted(index) {
+                rendered = rendered.patch_style(self.theme.selected);
+            }
+            if let Some((start, end)) = selection && (start.message..=end.message).contains(&index)
+                && let Some(source) = lines.source(line) {
+                let text = texts.entry(index).or_insert_with(|| chat.messages().get(index).map(Message::text).unwrap_or_default());
+                let from = if index == start.message { start.offset } else { 0 };
+                let to = if index == end.message { end.offset } else { usize::MAX };
+                let columns: Vec<(Range<usize>, Style)> = source.columns(&(from..to), text).into_iter()
+                    .map(|columns| (columns, self.theme.selected))
+                    .collect();
+                rendered = highlight_columns(rendered, &columns);
+            }
+            if let Some(source) = lines.source(line) {
+                let mut columns: Vec<(Range<usize>, Style)> = vec![];
+                for (number, found) in layout.search_matches.iter().enumerate().filter(|(_, found)| found.message == index) {
+                    let text = texts.entry(index).or_insert_with(|| chat.messages().get(index).map(Message::text).unwrap_or_default());
+                    let style = if current_match == Some(number) { self.theme.current_match } else { self.theme.search_match };
+                    columns.extend(source.columns(&found.range, text).into_iter().map(|columns| (columns, style)));
+                }
+                rendered = highlight_columns(rendered, &columns);
+            }
+            let y = area.y + u16::try_from(row).expect("Overflow for message row");
+            rendered.render(Rect { x: area.x, y, width, height: 1 }, buf);
+        }
+        let total = layout.total_lines;
+        let scroll = layout.scroll;
+        if total > height {
+            let max_scroll = total - height;
+            let mut state = ScrollbarState::new(max_scroll + 1)
+                .position(max_scroll.saturating_sub(scroll))
+                .viewport_content_length(height);
+            Scrollbar::new(ScrollbarOrientation::VerticalRight)
+                .begin_symbol(None)
+                .end_symbol(None)
+                .thumb_style(self.theme.border)
+                .track_style(self.theme.hint)
+                .render(area, buf, &mut state);
+        }
+        let below = chat.lines_below(total);
+        if below > 0 && scroll > 0 && area.height > 0 {
+            let text = format!(" ↓ {below} new {} below ", if below == 1 { "line" } else { "lines" });
+            let text_width = u16::try_from(text.chars().count()).unwrap_or(u16::MAX).min(width);
+            let indicator = Rect { x: area.x + width - text_width, y: area.bottom() - 1, width: text_width, height: 1 };
+            Clear.render(indicator, buf);
+            Line::styled(text, self.theme.notice.reversed()).render(indicator, buf);
+        }
+    }
+
+    /// The model, the tokens used, their cost and the time of the request; or the search
+    fn render_status(&self, area: Rect, buf: &mut Buffer) {
+        let chat = self.chat.borrow();
+        if let Some(search) = &chat.search {
+            return self.render_search(search, area, buf);
+        }
+        let spinner = if chat.user_input {
+            " "
+        } else {
+            SPINNER[self.app_state.borrow().tick % SPINNER.len()]
+        };
+        let mut status = format!(" {PROVIDER} · {}", chat.model);
+        status.push_str(&format!(" │ turn {} · chat {} tokens", format_tokens(chat.turn_usage.total_tokens()), format_tokens(chat.usage.total_tokens())));
+        if let Some(cost) = estimated_cost(&chat.model, &chat.usage) {
+            status.push_str(&format!(" │ ~${cost:.2}"));
+        }
+        if let Some(elapsed) = chat.elapsed() {
+            status.push_str(&format!(" │ {:.1}s", elapsed.as_secs_f64()));
+        }
+        Line::from(vec![Span::styled(spinner, self.theme.spinner), Span::styled(status, self.theme.status)]).render(area, buf);
+    }
+
+    fn render_search(&self, search: &Search, area: Rect, buf: &mut Buffer) {
+        let prefix = if search.is_regex() { " Search (regex): " } else { " Search: " };
+        let matches = self.app_state.borrow().chat_layout.search_matches.len();
+        let info = match search.pattern() {
+            Err(_) => "  invalid regular expression".to_string(),
+            Ok(None) => String::new(),
+            Ok(Some(_)) => match search.current_match(matches) {
+                Some(current) => format!("  {}/{matches}", current + 1),
+                None => "  no matches".to_string()
+            }
+        };
+        let hint = if search.editing { "  Enter confirm · Alt+R regex · Esc close" } else { "  n next · N previous · / edit · Esc close" };
+        Line::from(vec![
+            Span::styled(prefix, self.theme.status.bold()),
+            Span::raw(search.query().value().to_string()),
+            Span::styled(info, self.theme.status),
+            Span::styled(hint, self.theme.hint)
+        ]).render(area, buf);
+        if search.editing {
+            let column = prefix.chars().count() + search.query().visual_cursor();
+            let x = area.x.saturating_add(u16::try_from(column).unwrap_or(u16::MAX)).min(area.right().saturating_sub(1));
+            set_cursor_position(&self.app_state, Some(Position::new(x, area.y)));
+        }
+    }
+
+    /// The error, or else the notice
+    fn banner_lines(&self, width: u16) -> Vec<Line<'static>> {
+        let chat = self.chat.borrow();
+        match (&chat.error, &chat.notice) {
+            (Some(error), _) => error_lines(error, width, &self.theme),
+            (None, Some(notice)) => into_formatted_lines(notice, width, &Style::default()),
+            (None, None) => vec![]
+        }
+    }
+
+    fn render_banner(&self, lines: Vec<Line<'static>>, area: Rect, buf: &mut Buffer) {
+        let chat = self.chat.borrow();
+        let block = match (&chat.error, chat.can_retry()) {
+            (None, _) if chat.confirm_input.is_some() => Block::bordered().title("Confirm").title_bottom(" Enter confirm · Esc cancel ").border_style(self.theme.notice),
+            (Some(_), true) => Block::bordered().title("Error").title_bottom(" Ctrl+R retry · Esc dismiss ").border_style(self.theme.error),
+            (Some(_), false) => Block::bordered().title("Error").title_bottom(" Esc dismiss ").border_style(self.theme.error),
+            (None, _) => Block::bordered().title("Info").title_bottom(" Esc dismiss ").border_style(self.theme.notice)
+        };
+        Paragraph::new(lines).block(block).render(area, buf);
+    }
+
+    /// Show the commands matching the slash command which is being typed, above the input
+    fn render_slash_commands(&self, area: Rect, buf: &mut Buffer) {
+        let editor = self.chat_input.borrow();
+        let text = editor.text();
+        let input = match SlashInput::parse(&text) {
+            Some(input) if editor.line_count() == 1 && self.app_state.borrow().focus == Focus::Chat => input,
+            _ => return
+        };
+        let commands = match input.arguments {
+            None => SlashCommand::matching(input.name),
+            Some(_) => SlashCommand::find(input.name).into_iter().collect()
+        };
+        if commands.is_empty() {
+            return;
+        }
+        let lines: Vec<Line> = commands.iter().map(|command| Line::from(vec![
+            Span::styled(format!("/{} {}", command.name, command.usage), Style::default().bold()),
+            Span::styled(format!("  {}", command.description), self.theme.hint)
+        ])).collect();
+        let height = u16::try_from(lines.len() + 2).unwrap_or(u16::MAX).min(area.height);
+        let width = lines.iter().map(Line::width).max().unwrap_or(0).saturating_add(2);
+        let popup = Rect {
+            x: area.x,
+            y: area.y + area.height - height,
+            width: u16::try_from(width).unwrap_or(u16::MAX).min(area.width),
+            height
+        };
+        Clear.render(popup, buf);
+        Paragraph::new(lines).block(Block::bordered().title("Commands").title_bottom(" Tab complete ").border_style(self.theme.focused_border)).render(popup, buf);
+    }
+
+    /// Show the tool call which waits for approval and its input, above the input
+    fn render_approval(&self, area: Rect, buf: &mut Buffer) {
+        const REASON_PREFIX: &str = "Reason: ";
+        let chat = self.chat.borrow();
+        let Some(request) = &chat.approval else {
+            return;
+        };
+        let input = request.pretty_input();
+        let mut lines: Vec<Line> = match highlight_code(&input, "json", &self.theme.syntax) {
+            Some(lines) => lines.into_iter().map(Line::from).collect(),
+            None => input.lines().map(|line| Line::raw(line.to_string())).collect()
+        };
+        let reason = match &request.mode {
+            ApprovalMode::Deny(reason) => Some(reason),
+            ApprovalMode::Choose | ApprovalMode::Edit => None
+        };
+        // The reason is typed below the input, which is cut off if it is too long
+        let max_lines = usize::from(area.height).saturating_sub(if reason.is_some() { 4 } else { 2 });
+        if lines.len() > max_lines {
+            lines.truncate(max_lines.saturating_sub(1));
+            lines.push(Line::styled("…", self.theme.hint));
+        }
+        if let Some(reason) = reason {
+            lines.push(Line::default());
+            lines.push(Line::from(vec![Span::styled(REASON_PREFIX, self.theme.hint), Span::raw(reason.value().to_string())]));
+        }
+        let title = format!(" Tool call: {} ", request.name);
+        let hint = match &request.mode {
+            ApprovalMode::Choose => " y approve · a always · d deny · e edit ",
+            ApprovalMode::Deny(_) => " Enter deny · Esc back ",
+            ApprovalMode::Edit => " Editing the input below "
+        };
+        let width = lines.iter().map(Line::width)
+            .chain([title.chars().count(), hint.chars().count()])
+            .max()
+            .unwrap_or(0)
+            .saturating_add(2);
+        let height = u16::try_from(lines.len() + 2).unwrap_or(u16::MAX).min(area.height);
+        let popup = Rect {
+            x: area.x,
+            y: area.y + area.height - height,
+            width: u16::try_from(width).unwrap_or(u16::MAX).min(area.width),
+            height
+        };
+        Clear.render(popup, buf);
+        let block = Block::bordered().title(title).title_bottom(hint).border_style(self.theme.focused_border);
+        Paragraph::new(lines).block(block).render(popup, buf);
+        if let Some(reason) = reason {
+            let column = REASON_PREFIX.chars().count() + reason.visual_cursor();
+            let x = (popup.x + 1).saturating_add(u16::try_from(column).unwrap_or(u16::MAX)).min(popup.right().saturating_sub(2));
+            let y = popup.bottom().saturating_sub(2);
+            set_cursor_position(&self.app_state, Some(Position::new(x, y)));
         }
     }
 
     fn render_input(&self, area: Rect, buf: &mut Buffer) {
-        let chat_input = self.chat_input.borrow().clone();
-        let width = area.width.saturating_sub(3);
-        let scroll = chat_input.visual_scroll(width as usize);
-
-        let input = Paragraph::new(chat_input.value())
-            .scroll((0, u16::try_from(scroll).expect("Overflow for chat scroll position")))
-            .block(Block::bordered().title("Input"));
-
-        input.render(area, buf);
-
-        if self.chat.borrow().user_input {
-            self.app_state.send_modify(|app_state| {
-                app_state.cursor_position = Some(Position::from((
-                    area.x + 1 + u16::try_from(chat_input.visual_cursor().saturating_sub(scroll)).expect("Overflow for chat scroll position"),
-                    area.y + 1
-                )));
-            });
+        let chat = self.chat.borrow();
+        let focused = self.app_state.borrow().focus == Focus::Chat;
+        let title = if chat.is_editing_tool_input() {
+            "Edit tool input"
+        } else if chat.editing.is_some() {
+            "Edit message"
         } else {
-            self.app_state.send_modify(|state| { state.cursor_position = None; });
+            "Input"
+        };
+        let mut block = Block::bordered().title(title).border_style(self.theme.border(focused));
+        if chat.is_editing_tool_input() {
+            block = block.title_bottom(" Enter approve · Esc back ");
+        } else if chat.selected.is_some() {
+            block = block.title_bottom(" ↑↓ select · ←→ branch · y copy · 1-9 copy code · d delete · e edit · r regenerate · p pin · Esc back ");
+        } else if chat.editing.is_some() {
+            block = block.title_bottom(" Enter resend · Esc cancel ");
+        }
+        match self.app_state.borrow().vim {
+            Some(VimMode::Normal) => block = block.title_bottom(Line::styled(" NORMAL ", self.theme.status.bold()).right_aligned()),
+            Some(VimMode::Insert) => block = block.title_bottom(Line::styled(" INSERT ", self.theme.status.bold()).right_aligned()),
+            None => {}
+        }
+        drop(chat);
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        // The editor draws its own cursor
+        set_cursor_position(&self.app_state, None);
+        let searching = self.chat.borrow().search.as_ref().is_some_and(|search| search.editing);
+        let editing = self.chat.borrow().user_input || self.chat.borrow().is_editing_tool_input();
+        if focused && editing && self.chat.borrow().selected.is_none() && !searching {
+            self.chat_input.borrow().textarea.render(inner, buf);
+        } else {
+            let mut textarea = self.chat_input.borrow().textarea.clone();
+            textarea.set_cursor_style(Style::default());
+            textarea.render(inner, buf);
         }
     }
 }
 
-fn user_message_lines(parts: &[UserMessagePart], width: u16) -> Vec<Line> {
-    let text = parts.iter().map(|UserMessagePart { content }| {
-        match content {
-            UserMessageContent::Text { text } => "> ".to_owned() + text,
+/// The alternatives of the message and whether it is pinned, if there is anything to show
+fn message_header(chat: &Chat, index: usize) -> Option<String> {
+    let (alternative, alternatives) = chat.alternatives(index);
+    let mut header = vec![];
+    if alternatives > 1 {
+        header.push(format!("‹ {}/{alternatives} ›", alternative + 1));
+    }
+    if chat.is_pinned(index) {
+        header.push("pinned".to_string());
+    }
+    (!header.is_empty()).then(|| header.join(" · "))
+}
+
+fn user_message_lines(parts: &[UserMessagePart], width: u16, theme: &Theme) -> (Vec<Line<'static>>, Vec<LineSource>) {
+    let mut lines = vec![];
+    let mut sources = vec![];
+    // The offset of the text of each part in the text of the message
+    let mut offset = 0;
+    for (index, UserMessagePart { content }) in parts.iter().enumerate() {
+        // Attached files follow the text which was typed, and are only named
+        let (part_lines, part_sources) = match content {
+            _ if index > 0 && let Some(label) = attachment_label(content) => without_sources(into_formatted_lines(&format!("> [attached] {label}"), width, &theme.hint)),
+            UserMessageContent::Text { text } => plain_lines(text, "> ", width, theme.user, theme),
+            UserMessageContent::Image { .. } | UserMessageContent::Document { .. } => (vec![], vec![]),
+            UserMessageContent::ToolResult { content, is_error: false, .. } => without_sources(into_formatted_lines(&("> [tool result] ".to_owned() + content), width, &theme.tool)),
+            UserMessageContent::ToolResult { content, is_error: true, .. } => without_sources(into_formatted_lines(&("> [tool error] ".to_owned() + content), width, &theme.error)),
+        };
+        lines.extend(part_lines);
+        sources.extend(part_sources.into_iter().map(|source| offset_source(source, offset)));
+        if let UserMessageContent::Text { text } = content {
+            offset += text.len() + 1;
         }
-    }).collect::<Vec<_>>().join("\n");
-    into_formatted_lines(&text, width, &Style::default().italic())
+    }
+    (lines, sources)
 }
 
-fn assistant_message_lines(parts: &[AssistantMessagePart], width: u16) -> Vec<Line> {
-    let text = parts.iter().map(|AssistantMessagePart { content, complete }| {
-        match content {
-            AssistantMessageContent::Text { text } => text.clone() + if *complete { "" } else { " ..." },
+fn assistant_message_lines(parts: &[AssistantMessagePart], width: u16, theme: &Theme) -> (Vec<Line<'static>>, Vec<LineSource>) {
+    let mut lines = vec![];
+    let mut sources = vec![];
+    // Code blocks are numbered throughout the message
+    let mut code_blocks = 0;
+    // The offset of the text of each part in the text of the message
+    let mut offset = 0;
+    for AssistantMessagePart { content, complete } in parts {
+        let (mut part_lines, part_sources) = match content {
+            AssistantMessageContent::Text { text } => markdown_lines(text, width, theme, &mut code_blocks),
+            AssistantMessageContent::ToolUse { name, input, .. } if *complete => without_sources(into_formatted_lines(&format!("[tool call] {name} {input}"), width, &theme.tool)),
+            AssistantMessageContent::ToolUse { name, .. } => without_sources(into_formatted_lines(&format!("[tool call] {name} ..."), width, &theme.tool)),
+        };
+        sources.extend(part_sources.into_iter().map(|source| offset_source(source, offset)));
+        if let AssistantMessageContent::Text { text } = content {
+            offset += text.len() + 1;
+            if !*complete {
+                match part_lines.last_mut() {
+                    Some(line) => line.push_span(Span::styled(" ...", theme.assistant)),
+                    None => {
+                        part_lines.push(Line::styled("...", theme.assistant));
+                        sources.push(LineSource::default());
+                    }
+                }
+            }
         }
-    }).collect::<Vec<_>>().join("\n");
-    into_formatted_lines(&text, width, &Style::default())
+        lines.extend(part_lines);
+    }
+    (lines, sources)
+}
+
+/// Lines which do not show the text of the message
+fn without_sources(lines: Vec<Line<'static>>) -> (Vec<Line<'static>>, Vec<LineSource>) {
+    let sources = vec![LineSource::default(); lines.len()];
+    (lines, sources)
+}
+
+/// The source of a line of a part, whose text follows the text of the parts before it
+fn offset_source(mut source: LineSource, offset: usize) -> LineSource {
+    for piece in &mut source.pieces {
+        piece.range = piece.range.start + offset..piece.range.end + offset;
+    }
+    source
+}
+
+/// A description of the error and its cause
+fn error_lines(error: &ChatError, width: u16, theme: &Theme) -> Vec<Line<'static>> {
+    let text = match error {
+        ChatError::Llm(LlmError::ConnectionError) => "Cannot connect to the model provider".to_string(),
+        ChatError::Llm(LlmError::UnexpectedResponse) => "The model provider sent an unexpected or incomplete response".to_string(),
+        ChatError::Llm(LlmError::StepLimitReached) => "The model kept calling tools until the step limit was reached".to_string(),
+        ChatError::Llm(LlmError::InvalidStructuredOutput { reason }) => format!("The response does not match the requested format: {reason}"),
+        ChatError::Llm(LlmError::ApiError { status: Some(status), error_type, message }) => format!("The model provider returned {status} ({error_type}): {message}"),
+        ChatError::Llm(LlmError::ApiError { status: None, error_type, message }) => format!("The model provider failed ({error_type}): {message}"),
+        ChatError::Session(reason) => format!("Session error: {reason}"),
+        ChatError::Editor(reason) => format!("Editor error: {reason}"),
+        ChatError::Command(reason) | ChatError::Message(reason) | ChatError::Attachment(reason) => reason.clone(),
+        ChatError::ToolInput(reason) => format!("Invalid tool input: {reason}")
+    };
+    into_formatted_lines(&text, width, &theme.error)
 }
 
 fn into_formatted_lines<S>(text: &str, width: u16, style: &S) -> Vec<Line<'static>>
@@ -91,3 +509,104 @@ fn into_formatted_lines<S>(text: &str, width: u16, style: &S) -> Vec<Line<'stati
         .map(|line| Line::styled(line, style.clone()))
         .collect()
 }
+
+/// Apply the styles to the columns of the line
+fn highlight_columns(line: Line<'static>, columns: &[(Range<usize>, Style)]) -> Line<'static> {
+    // The byte offset of the text of the line at each column
+    let mut offsets = vec![];
+    let mut offset = 0;
+    for span in &line.spans {
+        for char in span.content.chars() {
+            offsets.extend(std::iter::repeat_n(offset, display_width(char.encode_utf8(&mut [0; 4]))));
+            offset += char.len_utf8();
+        }
+    }
+    offsets.push(offset);
+    let ranges: Vec<(Range<usize>, Style)> = columns.iter()
+        .map(|(columns, style)| {
+            let offset_at = |column: usize| offsets.get(column).copied().unwrap_or(offset);
+            (offset_at(columns.start)..offset_at(columns.end), *style)
+        })
+        .collect();
+    highlight_ranges(line, &ranges)
+}
+
+/// Apply the styles to the byte ranges of the text of the line
+fn highlight_ranges(line: Line<'static>, ranges: &[(Range<usize>, Style)]) -> Line<'static> {
+    if ranges.is_empty() {
+        return line;
+    }
+    let mut spans = vec![];
+    let mut offset = 0;
+    for span in line.spans {
+        let content = span.content.as_ref();
+        let end = offset + content.len();
+        let mut position = offset;
+        for (range, style) in ranges.iter().filter(|(range, _)| range.start < end && range.end > offset) {
+            let start = range.start.max(offset);
+            let stop = range.end.min(end);
+            if start > position {
+                spans.push(Span::styled(content[position - offset..start - offset].to_string(), span.style));
+            }
+            spans.push(Span::styled(content[start - offset..stop - offset].to_string(), span.style.patch(*style)));
+            position = stop;
+        }
+        if position < end {
+            spans.push(Span::styled(content[position - offset..].to_string(), span.style));
+        }
+        offset = end;
+    }
+    Line { spans, style: line.style, alignment: line.alignment }
+}
+
+/// A token count in a compact form, e.g. 12.3k
+fn format_tokens(tokens: u64) -> String {
+    match tokens {
+        0..1_000 => tokens.to_string(),
+        1_000..1_000_000 => format!("{:.1}k", tokens as f64 / 1_000.0),
+        _ => format!("{:.1}M", tokens as f64 / 1_000_000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use lliminal::llm::{AssistantMessageContent, AssistantMessagePart, Message, UserMessageContent, UserMessagePart};
+
+    use super::{LineCache, Theme};
+
+    fn messages() -> Vec<Message> {
+        vec![
+            Message::User { parts: vec![UserMessagePart { content: UserMessageContent::Text { text: "Hello, how are you today?".to_string() } }] },
+            Message::Assistant { parts: vec![AssistantMessagePart { complete: true, content: AssistantMessageContent::Text { text: "Fine, thanks".to_string() } }] }
+        ]
+    }
+
+    #[test]
+    fn wraps_only_messages_whose_version_changed() {
+        let mut cache = LineCache::default();
+        cache.update(&messages(), &[1, 2], 40, &Theme::default());
+        let sources = cache.sources(1);
+
+        cache.update(&messages(), &[1, 3], 40, &Theme::default());
+
+        assert!(Arc::ptr_eq(&cache.sources(1), &sources));
+        assert!(!cache.lines(3).is_empty());
+        // The lines of versions which are gone are forgotten
+        assert!(cache.lines(2).is_empty());
+    }
+
+    #[test]
+    fn wraps_all_messages_again_when_the_width_changes() {
+        let mut cache = LineCache::default();
+        cache.update(&messages(), &[1, 2], 40, &Theme::default());
+        let (lines, sources) = (cache.lines(1).len(), cache.sources(1));
+
+        cache.update(&messages(), &[1, 2], 12, &Theme::default());
+
+        assert!(!Arc::ptr_eq(&cache.sources(1), &sources));
+        assert!(cache.lines(1).len() > lines);
+        assert_eq!(cache.sources(1).len(), cache.lines(1).len());
+    }
+}
@@ -0,0 +1,111 @@
+use std::sync::LazyLock;
+
+use ratatui::{style::{Color, Modifier, Style}, text::Span};
+use syntect::{easy::HighlightLines, highlighting::{FontStyle, Theme, ThemeSet}, parsing::SyntaxSet, util::LinesWithEndings};
+
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+
+static THEMES: LazyLock<ThemeSet> = LazyLock::new(ThemeSet::load_defaults);
+
+/// Highlight code by the language of its fence with the syntect theme, one list of spans per line
+///
+/// Returns `None` if the language or the theme is unknown. Incomplete code is highlighted as far as it goes,
+/// so a code block can be highlighted again whenever more of it has been received.
+pub fn highlight_code(code: &str, language: &str, theme: &str) -> Option<Vec<Vec<Span<'static>>>> {
+    // Fences may carry more than the language, e.g. "rust,ignore"
+    let token = language.split([',', ' ', '{']).next().unwrap_or_default();
+    let syntax = SYNTAXES.find_syntax_by_token(token)?;
+    let theme: &Theme = THEMES.themes.get(theme)?;
+    let mut highlighter = HighlightLines::new(syntax, theme);
+    let mut lines = vec![];
+    for line in LinesWithEndings::from(code) {
+        let ranges = highlighter.highlight_line(line, &SYNTAXES).ok()?;
+        lines.push(ranges.into_iter()
+            .map(|(style, text)| Span::styled(text.trim_end_matches('\n').to_string(), span_style(style)))
+            .filter(|span| !span.content.is_empty())
+            .collect());
+    }
+    Some(lines)
+}
+
+/// Whether a syntect theme with the name is built in
+pub fn has_theme(name: &str) -> bool {
+    THEMES.themes.contains_key(name)
+}
+
+/// The names of the syntect themes
+pub fn theme_names() -> Vec<&'static str> {
+    THEMES.themes.keys().map(String::as_str).collect()
+}
+
+/// The foreground and font style of a syntect style; the background is left to the code block
+fn span_style(style: syntect::highlighting::Style) -> Style {
+    let mut modifier = Modifier::empty();
+    if style.font_style.contains(FontStyle::BOLD) {
+        modifier |= Modifier::BOLD;
+    }
+    if style.font_style.contains(FontStyle::ITALIC) {
+        modifier |= Modifier::ITALIC;
+    }
+    if style.font_style.contains(FontStyle::UNDERLINE) {
+        modifier |= Modifier::UNDERLINED;
+    }
+    Style::default()
+        .fg(Color::Rgb(style.foreground.r, style.foreground.g, style.foreground.b))
+        .add_modifier(modifier)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use ratatui::{style::Style, text::Span};
+
+    use super::highlight_code;
+
+    const THEME: &str = "base16-ocean.dark";
+
+    fn styles(line: &[Span<'static>]) -> Vec<Style> {
+        line.iter().map(|span| span.style).collect()
+    }
+
+    #[test]
+    fn colors_code_of_known_languages() {
+        let lines = highlight_code("fn main() {\n    let answer = 42;\n}\n", "rust", THEME).unwrap();
+
+        assert_eq!(lines.len(), 3);
+        let text: String = lines[1].iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(text, "    let answer = 42;");
+        let colors: HashSet<_> = lines.iter().flatten().filter_map(|span| span.style.fg).collect();
+        assert!(colors.len() > 1);
+    }
+
+    #[test]
+    fn finds_the_language_in_the_fence_info() {
+        let code = "let answer = 42;\n";
+        let lines = highlight_code(code, "rust", THEME);
+
+        assert!(lines.is_some());
+        assert_eq!(highlight_code(code, "rust,ignore", THEME), lines);
+        assert_eq!(highlight_code(code, "rust {.numberLines}", THEME), lines);
+    }
+
+    #[test]
+    fn does_not_highlight_with_unknown_languages_or_themes() {
+        assert_eq!(highlight_code("answer", "klingon", THEME), None);
+        assert_eq!(highlight_code("let answer = 42;", "rust", "unknown"), None);
+    }
+
+    #[test]
+    fn highlights_incomplete_code_like_the_start_of_the_complete_code() {
+        let complete = "fn main() {\n    let text = \"answer\";\n}\n";
+        let incomplete = "fn main() {\n    let text = \"ans";
+
+        let complete_lines = highlight_code(complete, "rust", THEME).unwrap();
+        let incomplete_lines = highlight_code(incomplete, "rust", THEME).unwrap();
+
+        assert_eq!(incomplete_lines.len(), 2);
+        assert_eq!(incomplete_lines[0], complete_lines[0]);
+        assert_eq!(styles(&incomplete_lines[1]), styles(&complete_lines[1])[..incomplete_lines[1].len()]);
+    }
+}
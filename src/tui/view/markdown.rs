@@ -0,0 +1,648 @@
+use std::ops::Range;
+
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, HeadingLevel, Tag, TagEnd};
+use ratatui::{style::{Style, Stylize}, text::{Line, Span}};
+use textwrap::core::display_width;
+
+use crate::tui::viewmodel::{markdown_parser, LineSource, SourcePiece};
+
+use super::{highlight::highlight_code, Theme};
+
+/// Render markdown into styled lines which fit into `width` columns
+///
+/// Code blocks and tables are not wrapped, so they keep their layout; long lines are cut off.
+/// Code blocks are numbered so that they can be copied; `code_blocks` is the number of code blocks
+/// before the text and is increased by the code blocks within it. Returns the lines together with
+/// the parts of the text which they show.
+pub fn markdown_lines(text: &str, width: u16, theme: &Theme, code_blocks: &mut usize) -> (Vec<Line<'static>>, Vec<LineSource>) {
+    let mut renderer = Renderer::new(text, usize::from(width), theme);
+    renderer.code_blocks = *code_blocks;
+    for (event, range) in markdown_parser(text).into_offset_iter() {
+        renderer.handle(event, range);
+    }
+    renderer.flush();
+    *code_blocks = renderer.code_blocks;
+    (renderer.lines, renderer.sources)
+}
+
+/// Wrap plain text into lines which fit into `width` columns, starting with the prefix
+///
+/// Returns the lines together with the parts of the text which they show.
+pub fn plain_lines(text: &str, prefix: &str, width: u16, style: Style, theme: &Theme) -> (Vec<Line<'static>>, Vec<LineSource>) {
+    let mut renderer = Renderer::new(text, usize::from(width), theme);
+    renderer.styles = vec![style];
+    let indent = Span::raw(" ".repeat(display_width(prefix)));
+    renderer.prefixes.push(Prefix { first: Some(Span::styled(prefix.to_string(), style)), rest: indent });
+    let mut offset = 0;
+    for line in text.split('\n') {
+        if line.is_empty() {
+            renderer.push_line(vec![]);
+        } else {
+            let source = Source { range: offset..offset + line.len(), verbatim: true };
+            renderer.spans.push((Span::styled(line.to_string(), style), Some(source)));
+            renderer.flush();
+        }
+        offset += line.len() + 1;
+    }
+    (renderer.lines, renderer.sources)
+}
+
+/// The part of the markdown which a span shows
+#[derive(Clone, Debug)]
+struct Source {
+    range: Range<usize>,
+    /// The span shows the text as it is written, e.g. not an escaped character
+    verbatim: bool
+}
+
+/// A span and the part of the markdown which it shows, if any
+type SourceSpan = (Span<'static>, Option<Source>);
+
+/// The indentation of the lines of a list item or block quote
+struct Prefix {
+    /// The prefix of the first line, until it has been rendered
+    first: Option<Span<'static>>,
+    rest: Span<'static>
+}
+
+struct CodeBlock {
+    /// The language given on the fence
+    language: Option<String>,
+    text: String,
+    /// The offset of each piece of the text and its source
+    sources: Vec<(usize, Source)>
+}
+
+struct Table {
+    alignments: Vec<Alignment>,
+    rows: Vec<Vec<Vec<SourceSpan>>>,
+    header_rows: usize
+}
+
+struct Renderer<'a> {
+    /// The markdown which is rendered
+    text: &'a str,
+    width: usize,
+    theme: &'a Theme,
+    lines: Vec<Line<'static>>,
+    /// The parts of the text which each line shows
+    sources: Vec<LineSource>,
+    /// The inline content of the current block and the parts of the text which it shows
+    spans: Vec<SourceSpan>,
+    styles: Vec<Style>,
+    prefixes: Vec<Prefix>,
+    /// The next number of each enclosing list, or `None` for bullet lists
+    lists: Vec<Option<u64>>,
+    /// The destination of each enclosing link, and the number of spans before it
+    links: Vec<(String, usize)>,
+    code_block: Option<CodeBlock>,
+    /// The number of code blocks so far
+    code_blocks: usize,
+    table: Option<Table>,
+    /// Whether the next block is separated by an empty line
+    separate: bool
+}
+
+impl<'a> Renderer<'a> {
+    fn new(text: &'a str, width: usize, theme: &'a Theme) -> Self {
+        Self {
+            text,
+            width,
+            theme,
+            lines: vec![],
+            sources: vec![],
+            spans: vec![],
+            styles: vec![theme.assistant],
+            prefixes: vec![],
+            lists: vec![],
+            links: vec![],
+            code_block: None,
+            code_blocks: 0,
+            table: None,
+            separate: false
+        }
+    }
+
+    /// Handle an event of the parser for the range of the text
+    fn handle(&mut self, event: Event, range: Range<usize>) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => {
+                let source = self.source(&text, range);
+                match &mut self.code_block {
+                    Some(code_block) => {
+                        code_block.sources.push((code_block.text.len(), source));
+                        code_block.text.push_str(&text);
+                    },
+                    None => self.spans.push((Span::styled(text.to_string(), self.style()), Some(source)))
+                }
+            },
+            Event::Code(code) => {
+                let source = self.source(&code, range);
+                self.spans.push((Span::styled(code.to_string(), self.style().patch(self.theme.code)), Some(source)));
+            },
+            Event::InlineMath(text) | Event::DisplayMath(text) | Event::Html(text) | Event::InlineHtml(text) | Event::FootnoteReference(text) => {
+                let source = self.source(&text, range);
+                self.spans.push((Span::styled(text.to_string(), self.style()), Some(source)));
+            },
+            Event::SoftBreak => self.spans.push((Span::styled(" ", self.style()), Some(Source { range, verbatim: false }))),
+            Event::HardBreak => self.flush(),
+            Event::Rule => {
+                self.start_block();
+                let width = self.width.saturating_sub(self.prefix_width());
+                self.push_line(vec![(Span::styled("─".repeat(width), self.theme.hint), None)]);
+                self.separate = true;
+            },
+            Event::TaskListMarker(checked) => self.push_text(if checked { "[x] " } else { "[ ] " })
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph | Tag::HtmlBlock | Tag::MetadataBlock(_) => self.start_block(),
+            Tag::Heading { level, .. } => {
+                self.start_block();
+                self.push_style(heading_style(level, self.theme));
+            },
+            Tag::BlockQuote(_) => {
+                self.start_block();
+                let bar = Span::styled("│ ", self.theme.hint);
+                self.prefixes.push(Prefix { first: None, rest: bar });
+                self.push_style(self.theme.quote);
+            },
+            Tag::CodeBlock(kind) => {
+                self.start_block();
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) if !info.is_empty() => Some(info.to_string()),
+                    _ => None
+                };
+                self.code_block = Some(CodeBlock { language, text: String::new(), sources: vec![] });
+            },
+            Tag::List(start) => {
+                // Nested lists start directly below their item
+                if self.lists.is_empty() {
+                    self.start_block();
+                } else {
+                    self.flush();
+                }
+                self.lists.push(start);
+            },
+            Tag::Item => {
+                self.flush();
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    },
+                    _ => "• ".to_string()
+                };
+                let indent = " ".repeat(display_width(&marker));
+                self.prefixes.push(Prefix { first: Some(Span::styled(marker, self.theme.list_marker)), rest: Span::raw(indent) });
+                self.separate = false;
+            },
+            Tag::Table(alignments) => {
+                self.start_block();
+                self.table = Some(Table { alignments, rows: vec![], header_rows: 0 });
+            },
+            Tag::TableHead | Tag::TableRow => {
+                if let Some(table) = &mut self.table {
+                    table.rows.push(vec![]);
+                }
+            },
+            Tag::TableCell => self.spans.clear(),
+            Tag::Emphasis => self.push_style(Style::default().italic()),
+            Tag::Strong => self.push_style(Style::default().bold()),
+            Tag::Strikethrough => self.push_style(Style::default().crossed_out()),
+            Tag::Link { dest_url, .. } => {
+                self.links.push((dest_url.to_string(), self.spans.len()));
+                self.push_style(self.theme.link);
+            },
+            Tag::Image { dest_url, .. } => {
+                self.links.push((dest_url.to_string(), self.spans.len()));
+                self.push_text("[image: ");
+                self.push_style(self.theme.link.not_underlined());
+            },
+            Tag::FootnoteDefinition(label) => {
+                self.start_block();
+                self.push_text(&format!("[{label}]: "));
+            },
+            Tag::DefinitionList | Tag::DefinitionListTitle | Tag::DefinitionListDefinition => self.start_block(),
+            Tag::Superscript | Tag::Subscript => self.push_style(Style::default())
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph | TagEnd::HtmlBlock | TagEnd::MetadataBlock(_) | TagEnd::FootnoteDefinition
+                | TagEnd::DefinitionList | TagEnd::DefinitionListTitle | TagEnd::DefinitionListDefinition => self.end_block(),
+            TagEnd::Heading(_) => {
+                self.end_block();
+                self.styles.pop();
+            },
+            TagEnd::BlockQuote(_) => {
+                self.end_block();
+                self.prefixes.pop();
+                self.styles.pop();
+            },
+            TagEnd::CodeBlock => {
+                if let Some(code_block) = self.code_block.take() {
+                    self.push_code_block(&code_block);
+                }
+                self.separate = true;
+            },
+            TagEnd::List(_) => {
+                self.end_block();
+                self.lists.pop();
+            },
+            TagEnd::Item => {
+                self.flush();
+                self.prefixes.pop();
+            },
+            TagEnd::Table => {
+                if let Some(table) = self.table.take() {
+                    self.push_table(table);
+                }
+                self.separate = true;
+            },
+            TagEnd::TableHead => {
+                if let Some(table) = &mut self.table {
+                    table.header_rows = table.rows.len();
+                }
+            },
+            TagEnd::TableRow => {},
+            TagEnd::TableCell => {
+                let cell = std::mem::take(&mut self.spans);
+                if let Some(row) = self.table.as_mut().and_then(|table| table.rows.last_mut()) {
+                    row.push(cell);
+                }
+            },
+            TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough | TagEnd::Superscript | TagEnd::Subscript => {
+                self.styles.pop();
+            },
+            TagEnd::Link => {
+                self.styles.pop();
+                if let Some((destination, start)) = self.links.pop() {
+                    let text: String = self.spans[start.min(self.spans.len())..].iter().map(|(span, _)| span.content.as_ref()).collect();
+                    if text != destination && !destination.is_empty() {
+                        self.spans.push((Span::styled(format!(" ({destination})"), self.theme.hint), None));
+                    }
+                }
+            },
+            TagEnd::Image => {
+                self.styles.pop();
+                self.links.pop();
+                self.push_text("]");
+            }
+        }
+    }
+
+    fn start_block(&mut self) {
+        self.flush();
+        if self.separate && !self.lines.is_empty() {
+            let prefix: Vec<Span<'static>> = self.prefixes.iter().map(|prefix| prefix.rest.clone()).collect();
+            self.lines.push(Line::from(prefix));
+            self.sources.push(LineSource::default());
+        }
+        self.separate = false;
+    }
+
+    fn end_block(&mut self) {
+        self.flush();
+        self.separate = true;
+    }
+
+    fn push_style(&mut self, style: Style) {
+        self.styles.push(self.style().patch(style));
+    }
+
+    fn style(&self) -> Style {
+        self.styles.last().copied().unwrap_or_default()
+    }
+
+    /// Push text which is not part of the markdown, e.g. a marker
+    fn push_text(&mut self, text: &str) {
+        self.spans.push((Span::styled(text.to_string(), self.style()), None));
+    }
+
+    /// The source of text which the parser found in the range of the markdown
+    ///
+    /// The text is shown as it is written if it occurs in the range, which contains the delimiters of code spans.
+    fn source(&self, text: &str, range: Range<usize>) -> Source {
+        match self.text.get(range.clone()).and_then(|source| source.find(text)) {
+            Some(index) if !text.is_empty() => Source { range: range.start + index..range.start + index + text.len(), verbatim: true },
+            _ => Source { range, verbatim: false }
+        }
+    }
+
+    /// The prefix of the next line
+    fn take_prefix(&mut self) -> Vec<Span<'static>> {
+        self.prefixes.iter_mut().map(|prefix| prefix.first.take().unwrap_or_else(|| prefix.rest.clone())).collect()
+    }
+
+    fn prefix_width(&self) -> usize {
+        self.prefixes.iter().map(|prefix| prefix.rest.width()).sum()
+    }
+
+    fn push_line(&mut self, spans: Vec<SourceSpan>) {
+        let mut line = self.take_prefix();
+        let mut column: usize = line.iter().map(Span::width).sum();
+        let mut pieces = vec![];
+        for (span, source) in spans {
+            let width = span.width();
+            if let Some(Source { range, verbatim }) = source {
+                pieces.push(SourcePiece { column, width, range, verbatim });
+            }
+            column += width;
+            line.push(span);
+        }
+        self.lines.push(Line::from(line));
+        self.sources.push(LineSource { pieces });
+    }
+
+    /// Wrap the inline content of the current block into lines
+    fn flush(&mut self) {
+        if self.spans.is_empty() {
+            return;
+        }
+        let spans = std::mem::take(&mut self.spans);
+        let available = self.width.saturating_sub(self.prefix_width()).max(1);
+        let mut line = vec![];
+        let mut line_width = 0;
+        for (span, source) in spans {
+            // The source of the text from the offset on
+            let source_from = |offset: usize, text: &str| source.as_ref().map(|source| match source.verbatim {
+                true => Source { range: source.range.start + offset..source.range.start + offset + text.len(), verbatim: true },
+                false => source.clone()
+            });
+            let mut offset = 0;
+            for word in split_words(&span.content) {
+                let is_space = word.trim().is_empty();
+                let mut word_width = display_width(word);
+                let mut word_offset = offset;
+                offset += word.len();
+                if line_width + word_width > available && line_width > 0 {
+                    self.push_line(std::mem::take(&mut line));
+                    line_width = 0;
+                    if is_space {
+                        continue;
+                    }
+                }
+                // Break words which are longer than a line
+                let mut word = word;
+                while word_width > available {
+                    let (head, tail) = split_at_width(word, available);
+                    self.push_line(vec![(Span::styled(head.to_string(), span.style), source_from(word_offset, head))]);
+                    word = tail;
+                    word_offset += head.len();
+                    word_width = display_width(word);
+                }
+                if !word.is_empty() {
+                    line.push((Span::styled(word.to_string(), span.style), source_from(word_offset, word)));
+                    line_width += word_width;
+                }
+            }
+        }
+        if !line.is_empty() {
+            self.push_line(line);
+        }
+    }
+
+    fn push_code_block(&mut self, code_block: &CodeBlock) {
+        let width = self.width.saturating_sub(self.prefix_width());
+        let code = code_block.text.trim_end_matches('\n');
+        let code_lines = code_block.language.as_deref()
+            .and_then(|language| highlight_code(code, language, &self.theme.syntax))
+            .filter(|lines| !lines.is_empty())
+            .unwrap_or_else(|| code.split('\n').map(|line| vec![Span::styled(line.to_string(), self.theme.assistant)]).collect());
+        self.code_blocks += 1;
+        let label = format!("[{}] {}", self.code_blocks, code_block.language.as_deref().unwrap_or_default());
+        let label_line = vec![(Span::styled(label, self.theme.hint), None)];
+        let mut line_start = 0;
+        let code_lines = code_lines.into_iter().map(|spans| {
+            let length = spans.iter().map(|span| span.content.len()).sum::<usize>();
+            let source = code_block.source(line_start..line_start + length);
+            line_start += length + 1;
+            let mut span_start = 0;
+            spans.into_iter().map(|span| {
+                let span_source = source.as_ref().map(|source| match source.verbatim {
+                    true => Source { range: source.range.start + span_start..source.range.start + span_start + span.content.len(), verbatim: true },
+                    false => source.clone()
+                });
+                span_start += span.content.len();
+                (span, span_source)
+            }).collect()
+        });
+        for mut spans in std::iter::once(label_line).chain(code_lines) {
+            let padding = width.saturating_sub(spans.iter().map(|(span, _)| span.width()).sum());
+            spans.push((Span::raw(" ".repeat(padding)), None));
+            self.push_line(spans.into_iter().map(|(span, source)| (span.patch_style(self.theme.code_block), source)).collect());
+        }
+    }
+
+    fn push_table(&mut self, table: Table) {
+        let columns = table.rows.iter().map(Vec::len).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns).map(|column| {
+            table.rows.iter().filter_map(|row| row.get(column)).map(|cell| cell_width(cell)).max().unwrap_or(0)
+        }).collect();
+        let separator = self.theme.hint;
+        for (index, row) in table.rows.into_iter().enumerate() {
+            let mut spans = vec![];
+            for (column, width) in widths.iter().enumerate() {
+                if column > 0 {
+                    spans.push((Span::styled(" │ ", separator), None));
+                }
+                let cell = row.get(column).cloned().unwrap_or_default();
+                let padding = width - cell_width(&cell);
+                let (left, right) = match table.alignments.get(column) {
+                    Some(Alignment::Right) => (padding, 0),
+                    Some(Alignment::Center) => (padding / 2, padding - padding / 2),
+                    _ => (0, padding)
+                };
+                spans.push((Span::raw(" ".repeat(left)), None));
+                if index < table.header_rows {
+                    spans.extend(cell.into_iter().map(|(span, source)| (span.bold(), source)));
+                } else {
+                    spans.extend(cell);
+                }
+                spans.push((Span::raw(" ".repeat(right)), None));
+            }
+            self.push_line(spans);
+            if index + 1 == table.header_rows {
+                let rule = widths.iter().map(|width| "─".repeat(*width)).collect::<Vec<_>>().join("─┼─");
+                self.push_line(vec![(Span::styled(rule, separator), None)]);
+            }
+        }
+    }
+}
+
+impl CodeBlock {
+    /// The source of the range of the code, if it shows text of the markdown
+    fn source(&self, range: Range<usize>) -> Option<Source> {
+        let index = self.sources.iter().rposition(|(start, _)| *start <= range.start)?;
+        let (start, source) = &self.sources[index];
+        let end = self.sources.get(index + 1).map_or(self.text.len(), |(end, _)| *end);
+        match source.verbatim && range.end <= end {
+            true => Some(Source { range: source.range.start + range.start - start..source.range.start + range.end - start, verbatim: true }),
+            false => Some(Source { range: source.range.clone(), verbatim: false })
+        }
+    }
+}
+
+fn heading_style(level: HeadingLevel, theme: &Theme) -> Style {
+    match level {
+        HeadingLevel::H1 => theme.heading.underlined(),
+        HeadingLevel::H2 => theme.heading,
+        _ => Style::default().bold()
+    }
+}
+
+fn cell_width(cell: &[SourceSpan]) -> usize {
+    cell.iter().map(|(span, _)| span.width()).sum()
+}
+
+/// Split text into words and runs of whitespace
+fn split_words(text: &str) -> Vec<&str> {
+    let mut words = vec![];
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((_, char)) = chars.next() {
+        if let Some((index, next)) = chars.peek() && char.is_whitespace() != next.is_whitespace() {
+            words.push(&text[start..*index]);
+            start = *index;
+        }
+    }
+    if start < text.len() {
+        words.push(&text[start..]);
+    }
+    words
+}
+
+/// Split text after at most `width` columns, but after at least one character
+fn split_at_width(text: &str, width: usize) -> (&str, &str) {
+    let mut end = 0;
+    for (index, char) in text.char_indices() {
+        let next = index + char.len_utf8();
+        if display_width(&text[..next]) > width && end > 0 {
+            break;
+        }
+        end = next;
+    }
+    text.split_at(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use lliminal::llm::{AssistantMessageContent, AssistantMessagePart, Message};
+    use ratatui::{style::Stylize, text::Line};
+
+    use crate::tui::viewmodel::code_blocks;
+
+    use ratatui::style::Style;
+
+    use super::{markdown_lines, plain_lines, Theme};
+
+    fn render(text: &str, width: u16) -> Vec<Line<'static>> {
+        markdown_lines(text, width, &Theme::default(), &mut 0).0
+    }
+
+    /// The text of the lines, without trailing padding
+    fn texts(lines: &[Line]) -> Vec<String> {
+        lines.iter()
+            .map(|line| line.spans.iter().map(|span| span.content.as_ref()).collect::<String>().trim_end().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn renders_headings_as_styled_blocks() {
+        let theme = Theme::default();
+
+        let lines = render("# Title\n## Section\nText", 40);
+
+        assert_eq!(texts(&lines), vec!["Title", "", "Section", "", "Text"]);
+        assert_eq!(lines[0].spans[0].style, theme.assistant.patch(theme.heading.underlined()));
+        assert_eq!(lines[2].spans[0].style, theme.assistant.patch(theme.heading));
+        assert_eq!(lines[4].spans[0].style, theme.assistant);
+    }
+
+    #[test]
+    fn renders_bullet_numbered_and_nested_lists() {
+        let lines = render("- one\n- two\n  - nested\n\n3. three\n4. four", 40);
+
+        assert_eq!(texts(&lines), vec!["• one", "• two", "  • nested", "", "3. three", "4. four"]);
+    }
+
+    #[test]
+    fn renders_numbered_code_blocks_padded_to_the_width() {
+        let mut code_blocks = 1;
+
+        let (lines, _) = markdown_lines("Before\n\n```\nlet x = 1;\n\n```\nAfter", 20, &Theme::default(), &mut code_blocks);
+
+        assert_eq!(texts(&lines), vec!["Before", "", "[2]", "let x = 1;", "", "After"]);
+        assert_eq!(code_blocks, 2);
+        assert!(lines[2..4].iter().all(|line| line.width() == 20));
+        assert_eq!(lines[3].spans[0].style, Theme::default().assistant.patch(Theme::default().code_block));
+    }
+
+    #[test]
+    fn does_not_wrap_code_blocks() {
+        let lines = render("```\nfn a_very_long_function_name() {}\n```", 10);
+
+        assert_eq!(texts(&lines), vec!["[1]", "fn a_very_long_function_name() {}"]);
+    }
+
+    #[test]
+    fn wraps_paragraphs_at_word_boundaries() {
+        let lines = render("aaa bbb ccc", 7);
+
+        assert_eq!(texts(&lines), vec!["aaa bbb", "ccc"]);
+    }
+
+    #[test]
+    fn breaks_words_longer_than_a_line() {
+        let lines = render("abcdefghij", 4);
+
+        assert_eq!(texts(&lines), vec!["abcd", "efgh", "ij"]);
+    }
+
+    #[test]
+    fn indents_wrapped_list_items() {
+        let lines = render("- aaa bbb\n\n> ccc ddd", 7);
+
+        assert_eq!(texts(&lines), vec!["• aaa", "  bbb", "", "│ ccc", "│ ddd"]);
+    }
+
+    #[test]
+    fn numbers_the_code_blocks_which_are_copied() {
+        let text = "| a | b |\n|---|---|\n| ~~c~~ | `d` |\n\n- [x] done\n\n  ```rust\n  let a = 1;\n  ```\n\n~~~\nplain\n~~~\n\n    indented\n";
+        let message = Message::Assistant { parts: vec![AssistantMessagePart { complete: true, content: AssistantMessageContent::Text { text: text.to_string() } }] };
+        let mut rendered = 0;
+
+        markdown_lines(text, 40, &Theme::default(), &mut rendered);
+
+        assert_eq!(code_blocks(&message), vec!["let a = 1;\n", "plain\n", "indented\n"]);
+        assert_eq!(rendered, 3);
+    }
+
+    #[test]
+    fn maps_the_lines_to_the_text_which_they_show() {
+        let text = "**Hello** wörld `code`\n\n- item";
+        let (lines, sources) = markdown_lines(text, 40, &Theme::default(), &mut 0);
+
+        assert_eq!(texts(&lines), vec!["Hello wörld code", "", "• item"]);
+        let ranges = |line: usize| sources[line].pieces.iter().map(|piece| (piece.column, &text[piece.range.clone()])).collect::<Vec<_>>();
+        assert_eq!(ranges(0), vec![(0, "Hello"), (5, " "), (6, "wörld"), (11, " "), (12, "code")]);
+        assert!(sources[1].pieces.is_empty());
+        assert_eq!(ranges(2), vec![(2, "item")]);
+    }
+
+    #[test]
+    fn maps_wrapped_plain_text_after_the_prefix() {
+        let (lines, sources) = plain_lines("aaa bbb", "> ", 6, Style::default(), &Theme::default());
+
+        assert_eq!(texts(&lines), vec!["> aaa", "  bbb"]);
+        assert_eq!(sources[0].pieces[0].column, 2);
+        assert_eq!(sources[0].pieces[0].range, 0..3);
+        assert_eq!(sources[1].pieces.last().map(|piece| piece.range.clone()), Some(4..7));
+    }
+}
@@ -1,3 +1,24 @@
 mod chat;
+mod highlight;
+mod markdown;
+mod sidebar;
+mod theme;
+
+use ratatui::layout::Position;
+use tokio::sync::watch;
 
 pub use chat::ChatWidget;
+pub use sidebar::SidebarWidget;
+pub use theme::{StyleConfig, Theme, ThemeElement, ThemeName};
+
+use super::viewmodel::AppState;
+
+/// Show the cursor at the position after rendering, or hide it
+///
+/// The position is a result of rendering, so the receivers are not notified, which would render again.
+fn set_cursor_position(app_state: &watch::Sender<AppState>, position: Option<Position>) {
+    app_state.send_if_modified(|state| {
+        state.cursor_position = position;
+        false
+    });
+}
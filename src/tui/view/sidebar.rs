@@ -0,0 +1,87 @@
+use ratatui::{buffer::Buffer, layout::{Constraint, Layout, Position, Rect}, style::{Style, Stylize}, text::Line, widgets::{Block, Paragraph, Widget}};
+use tokio::sync::watch;
+
+use crate::tui::viewmodel::{AppState, Chat, Focus, Sidebar, SidebarMode};
+
+use super::{set_cursor_position, Theme};
+
+pub struct SidebarWidget {
+    pub app_state: watch::Sender<AppState>,
+    pub sidebar: watch::Receiver<Sidebar>,
+    pub chat: watch::Receiver<Chat>,
+    pub theme: Theme
+}
+
+impl Widget for &SidebarWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let focused = self.app_state.borrow().focus == Focus::Sidebar;
+        let block = Block::bordered().title("Sessions").border_style(self.theme.border(focused));
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let [prompt_area, list_area] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(1),
+        ]).areas(inner);
+        self.render_prompt(prompt_area, buf, focused);
+        self.render_sessions(list_area, buf);
+    }
+}
+
+impl SidebarWidget {
+    /// The filter, the new title or the delete confirmation
+    fn render_prompt(&self, area: Rect, buf: &mut Buffer, focused: bool) {
+        let sidebar = self.sidebar.borrow();
+        let (label, input) = match &sidebar.mode {
+            SidebarMode::Browse if sidebar.filter.value().is_empty() => {
+                Line::styled("/ filter  n new  r rename  d delete", self.theme.hint).render(area, buf);
+                return;
+            },
+            SidebarMode::Browse | SidebarMode::Filter => ("/", &sidebar.filter),
+            SidebarMode::Rename(input) => ("Title: ", input),
+            SidebarMode::ConfirmDelete => {
+                Line::styled("Delete session? (y/n)", self.theme.error).render(area, buf);
+                return;
+            }
+        };
+        let [label_area, input_area] = Layout::horizontal([
+            Constraint::Length(u16::try_from(label.len()).expect("Overflow for sidebar label")),
+            Constraint::Min(1),
+        ]).areas(area);
+        let scroll = input.visual_scroll(input_area.width as usize);
+        Line::raw(label).render(label_area, buf);
+        Paragraph::new(input.value())
+            .scroll((0, u16::try_from(scroll).expect("Overflow for sidebar scroll position")))
+            .render(input_area, buf);
+        if focused && !matches!(sidebar.mode, SidebarMode::Browse) {
+            let cursor = u16::try_from(input.visual_cursor().saturating_sub(scroll)).expect("Overflow for sidebar scroll position");
+            set_cursor_position(&self.app_state, Some(Position::from((input_area.x + cursor, input_area.y))));
+        }
+    }
+
+    fn render_sessions(&self, area: Rect, buf: &mut Buffer) {
+        let sidebar = self.sidebar.borrow();
+        let active_id = self.chat.borrow().id.clone();
+        let sessions = sidebar.filtered_sessions();
+        if sessions.is_empty() {
+            Line::styled("No sessions", self.theme.hint).render(area, buf);
+            return;
+        }
+        // Each session takes two lines; keep the selected one visible
+        let visible = usize::from(area.height / 2).max(1);
+        let offset = (sidebar.selected + 1).saturating_sub(visible);
+        let mut y = area.y;
+        for (index, session) in sessions.iter().enumerate().skip(offset).take(visible) {
+            let mut title_style = if session.id == active_id { Style::default().bold() } else { Style::default() };
+            if index == sidebar.selected {
+                title_style = title_style.patch(self.theme.selected);
+            }
+            let details = format!("{} · {}", session.updated_at.format("%Y-%m-%d %H:%M"), session.model);
+            Line::styled(session.title.clone(), title_style).render(Rect { x: area.x, y, width: area.width, height: 1 }, buf);
+            if y + 1 < area.y + area.height {
+                Line::styled(details, self.theme.hint).render(Rect { x: area.x, y: y + 1, width: area.width, height: 1 }, buf);
+            }
+            y += 2;
+        }
+    }
+}
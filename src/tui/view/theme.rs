@@ -0,0 +1,285 @@
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use serde::Deserialize;
+
+use super::highlight;
+
+/// The styles of the elements of the TUI
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Theme {
+    /// Messages written by the user
+    pub user: Style,
+    /// Responses of the model
+    pub assistant: Style,
+    /// Tool calls and their results
+    pub tool: Style,
+    pub error: Style,
+    /// The border of notices
+    pub notice: Style,
+    /// Secondary text such as hints, labels and rules
+    pub hint: Style,
+    /// The border of blocks which are not focused
+    pub border: Style,
+    pub focused_border: Style,
+    /// Selected messages and sessions
+    pub selected: Style,
+    /// Inline code
+    pub code: Style,
+    /// The background of code blocks
+    pub code_block: Style,
+    pub heading: Style,
+    pub link: Style,
+    pub quote: Style,
+    pub list_marker: Style,
+    pub status: Style,
+    pub spinner: Style,
+    pub search_match: Style,
+    pub current_match: Style,
+    /// The syntect theme which colors code blocks
+    pub syntax: String
+}
+
+/// The built-in themes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemeName {
+    #[default]
+    Dark,
+    Light,
+    HighContrast
+}
+
+/// An element of the TUI whose style can be configured
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemeElement {
+    User,
+    Assistant,
+    Tool,
+    Error,
+    Notice,
+    Hint,
+    Border,
+    FocusedBorder,
+    Selected,
+    Code,
+    CodeBlock,
+    Heading,
+    Link,
+    Quote,
+    ListMarker,
+    Status,
+    Spinner,
+    SearchMatch,
+    CurrentMatch
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    pub fn builtin(name: ThemeName) -> Self {
+        match name {
+            ThemeName::Dark => Self::dark(),
+            ThemeName::Light => Self::light(),
+            ThemeName::HighContrast => Self::high_contrast()
+        }
+    }
+
+    /// For terminals with a dark background
+    pub fn dark() -> Self {
+        Self {
+            user: Style::new().italic(),
+            assistant: Style::new(),
+            tool: Style::new().fg(Color::Gray),
+            error: Style::new().fg(Color::Red),
+            notice: Style::new().dim(),
+            hint: Style::new().dim(),
+            border: Style::new().dim(),
+            focused_border: Style::new().bold(),
+            selected: Style::new().bg(Color::Indexed(238)),
+            code: Style::new().fg(Color::Yellow).bg(Color::Indexed(236)),
+            code_block: Style::new().bg(Color::Indexed(236)),
+            heading: Style::new().fg(Color::Cyan).bold(),
+            link: Style::new().fg(Color::Blue).underlined(),
+            quote: Style::new().italic(),
+            list_marker: Style::new().fg(Color::Cyan),
+            status: Style::new().dim(),
+            spinner: Style::new().fg(Color::Cyan),
+            search_match: Style::new().fg(Color::Black).bg(Color::Yellow),
+            current_match: Style::new().fg(Color::Black).bg(Color::LightRed),
+            syntax: "base16-ocean.dark".to_string()
+        }
+    }
+
+    /// For terminals with a light background
+    pub fn light() -> Self {
+        Self {
+            user: Style::new().fg(Color::Blue).italic(),
+            assistant: Style::new(),
+            tool: Style::new().fg(Color::DarkGray),
+            error: Style::new().fg(Color::Red),
+            notice: Style::new().fg(Color::DarkGray),
+            hint: Style::new().fg(Color::DarkGray),
+            border: Style::new().fg(Color::DarkGray),
+            focused_border: Style::new().fg(Color::Black).bold(),
+            selected: Style::new().bg(Color::Indexed(253)),
+            code: Style::new().fg(Color::Indexed(124)).bg(Color::Indexed(255)),
+            code_block: Style::new().bg(Color::Indexed(255)),
+            heading: Style::new().fg(Color::Blue).bold(),
+            link: Style::new().fg(Color::Blue).underlined(),
+            quote: Style::new().fg(Color::DarkGray).italic(),
+            list_marker: Style::new().fg(Color::Blue),
+            status: Style::new().fg(Color::DarkGray),
+            spinner: Style::new().fg(Color::Blue),
+            search_match: Style::new().fg(Color::Black).bg(Color::LightYellow),
+            current_match: Style::new().fg(Color::White).bg(Color::Red),
+            syntax: "InspiredGitHub".to_string()
+        }
+    }
+
+    /// Bright colors on black, without dimmed text
+    pub fn high_contrast() -> Self {
+        Self {
+            user: Style::new().fg(Color::White).bold(),
+            assistant: Style::new().fg(Color::White),
+            tool: Style::new().fg(Color::LightCyan),
+            error: Style::new().fg(Color::LightRed).bold(),
+            notice: Style::new().fg(Color::White),
+            hint: Style::new().fg(Color::White),
+            border: Style::new().fg(Color::White),
+            focused_border: Style::new().fg(Color::LightYellow).bold(),
+            selected: Style::new().fg(Color::Black).bg(Color::White),
+            code: Style::new().fg(Color::LightYellow).bg(Color::Black),
+            code_block: Style::new().bg(Color::Black),
+            heading: Style::new().fg(Color::LightYellow).bold().underlined(),
+            link: Style::new().fg(Color::LightCyan).underlined(),
+            quote: Style::new().fg(Color::White).italic(),
+            list_marker: Style::new().fg(Color::LightYellow),
+            status: Style::new().fg(Color::White),
+            spinner: Style::new().fg(Color::LightYellow),
+            search_match: Style::new().fg(Color::Black).bg(Color::LightYellow),
+            current_match: Style::new().fg(Color::Black).bg(Color::LightGreen),
+            syntax: "base16-eighties.dark".to_string()
+        }
+    }
+
+    pub fn style_mut(&mut self, element: ThemeElement) -> &mut Style {
+        match element {
+            ThemeElement::User => &mut self.user,
+            ThemeElement::Assistant => &mut self.assistant,
+            ThemeElement::Tool => &mut self.tool,
+            ThemeElement::Error => &mut self.error,
+            ThemeElement::Notice => &mut self.notice,
+            ThemeElement::Hint => &mut self.hint,
+            ThemeElement::Border => &mut self.border,
+            ThemeElement::FocusedBorder => &mut self.focused_border,
+            ThemeElement::Selected => &mut self.selected,
+            ThemeElement::Code => &mut self.code,
+            ThemeElement::CodeBlock => &mut self.code_block,
+            ThemeElement::Heading => &mut self.heading,
+            ThemeElement::Link => &mut self.link,
+            ThemeElement::Quote => &mut self.quote,
+            ThemeElement::ListMarker => &mut self.list_marker,
+            ThemeElement::Status => &mut self.status,
+            ThemeElement::Spinner => &mut self.spinner,
+            ThemeElement::SearchMatch => &mut self.search_match,
+            ThemeElement::CurrentMatch => &mut self.current_match
+        }
+    }
+
+    /// Color code blocks with another syntect theme; fails if there is no such theme
+    pub fn set_syntax(&mut self, syntax: &str) -> Result<(), String> {
+        if !highlight::has_theme(syntax) {
+            return Err(format!("Unknown syntax theme {syntax}, expected one of: {}", highlight::theme_names().join(", ")));
+        }
+        self.syntax = syntax.to_string();
+        Ok(())
+    }
+
+    /// The style of the border of a block
+    pub fn border(&self, focused: bool) -> Style {
+        if focused { self.focused_border } else { self.border }
+    }
+}
+
+/// Changes to the style of an element; anything which is not given is kept
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct StyleConfig {
+    /// A color name such as "light-blue", an index such as "238", or a hex value such as "#ff8800"
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub underlined: Option<bool>,
+    pub dim: Option<bool>,
+    pub reversed: Option<bool>
+}
+
+impl StyleConfig {
+    pub fn apply(&self, mut style: Style) -> Style {
+        if let Some(fg) = self.fg {
+            style = style.fg(fg);
+        }
+        if let Some(bg) = self.bg {
+            style = style.bg(bg);
+        }
+        let modifiers = [
+            (self.bold, Modifier::BOLD),
+            (self.italic, Modifier::ITALIC),
+            (self.underlined, Modifier::UNDERLINED),
+            (self.dim, Modifier::DIM),
+            (self.reversed, Modifier::REVERSED)
+        ];
+        for (enabled, modifier) in modifiers {
+            style = match enabled {
+                Some(true) => style.add_modifier(modifier),
+                Some(false) => style.remove_modifier(modifier),
+                None => style
+            };
+        }
+        style
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::style::{Color, Modifier, Style};
+
+    use super::{StyleConfig, Theme, ThemeName};
+
+    #[test]
+    fn applies_only_the_given_changes() {
+        let style = Style::new().fg(Color::Red).bg(Color::Black).add_modifier(Modifier::BOLD | Modifier::ITALIC);
+        let config = StyleConfig { fg: Some(Color::Blue), italic: Some(false), underlined: Some(true), ..StyleConfig::default() };
+
+        let applied = config.apply(style);
+
+        assert_eq!(applied, Style::new().fg(Color::Blue).bg(Color::Black).add_modifier(Modifier::BOLD | Modifier::UNDERLINED).remove_modifier(Modifier::ITALIC));
+        assert_eq!(StyleConfig::default().apply(style), style);
+    }
+
+    #[test]
+    fn reads_colors_by_name_index_and_hex_value() {
+        let config: StyleConfig = toml::from_str("fg = \"light-blue\"\nbg = \"#ff8800\"\ndim = true").unwrap();
+
+        assert_eq!(config.fg, Some(Color::LightBlue));
+        assert_eq!(config.bg, Some(Color::Rgb(0xff, 0x88, 0x00)));
+        assert_eq!(config.dim, Some(true));
+        let config: StyleConfig = toml::from_str("fg = \"238\"").unwrap();
+        assert_eq!(config.fg, Some(Color::Indexed(238)));
+    }
+
+    #[test]
+    fn changes_the_syntax_theme_only_to_known_ones() {
+        let mut theme = Theme::builtin(ThemeName::Light);
+
+        assert!(theme.set_syntax("InspiredGitHub").is_ok());
+        assert_eq!(theme.syntax, "InspiredGitHub");
+        assert!(theme.set_syntax("unknown").unwrap_err().contains("InspiredGitHub"));
+        assert_eq!(theme.syntax, "InspiredGitHub");
+    }
+}
@@ -1,12 +1,41 @@
 use ratatui::layout::Position;
 
+use super::ChatLayout;
+
 pub struct AppState {
     pub running: bool,
-    pub cursor_position: Option<Position>
+    pub cursor_position: Option<Position>,
+    pub focus: Focus,
+    /// The input is to be edited in an external editor
+    pub external_editor: bool,
+    /// Text which the terminal is to copy to its clipboard; only the app writes to the terminal
+    pub clipboard: Option<String>,
+    /// The number of animation frames shown while requests were running
+    pub tick: usize,
+    /// How the chat is rendered, to find the targets of mouse events and scroll
+    pub chat_layout: ChatLayout,
+    /// The mode of the input, if vim mode is enabled
+    pub vim: Option<VimMode>
+}
+
+/// Whether keys move around the input or are typed into it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VimMode {
+    Normal,
+    #[default]
+    Insert
+}
+
+/// The part of the UI which receives key events
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Focus {
+    #[default]
+    Chat,
+    Sidebar
 }
 
 impl Default for AppState {
     fn default() -> Self {
-        Self { running: true, cursor_position: None }
+        Self { running: true, cursor_position: None, focus: Focus::default(), external_editor: false, clipboard: None, tick: 0, chat_layout: ChatLayout::default(), vim: None }
     }
 }
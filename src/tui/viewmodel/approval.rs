@@ -0,0 +1,47 @@
+use serde_json::Value;
+use tui_input::Input;
+
+/// A tool call which waits for the decision of the user
+#[derive(Clone, Debug)]
+pub struct ToolApprovalRequest {
+    pub name: String,
+    /// The input which the model requested
+    pub input: Value,
+    pub mode: ApprovalMode
+}
+
+#[derive(Clone, Debug)]
+pub enum ApprovalMode {
+    /// The user chooses what happens with the call
+    Choose,
+    /// The reason for denying the call is being typed
+    Deny(Input),
+    /// The input of the call is being edited in the editor of the chat
+    Edit
+}
+
+impl ToolApprovalRequest {
+    pub fn new(name: &str, input: Value) -> Self {
+        Self { name: name.to_string(), input, mode: ApprovalMode::Choose }
+    }
+
+    /// The input as indented JSON
+    pub fn pretty_input(&self) -> String {
+        serde_json::to_string_pretty(&self.input).unwrap_or_else(|_| self.input.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{ApprovalMode, ToolApprovalRequest};
+
+    #[test]
+    fn starts_with_choosing_and_shows_the_input_indented() {
+        let request = ToolApprovalRequest::new("files__read", json!({ "path": "a.txt", "lines": [1, 2] }));
+
+        assert!(matches!(request.mode, ApprovalMode::Choose));
+        assert_eq!(request.pretty_input(), "{\n  \"lines\": [\n    1,\n    2\n  ],\n  \"path\": \"a.txt\"\n}");
+    }
+}
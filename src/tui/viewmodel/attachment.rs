@@ -0,0 +1,101 @@
+use lliminal::llm::{UserMessageContent, UserMessagePart};
+
+/// Characters which end a sentence rather than a mentioned path
+const TRAILING_PUNCTUATION: &[char] = &['.', ',', ';', ':', '!', '?', ')', '"', '\''];
+
+/// The paths of the files mentioned as `@path` in the input, without duplicates
+pub fn mentioned_paths(text: &str) -> Vec<&str> {
+    let mut paths = vec![];
+    for word in text.split_whitespace() {
+        let Some(path) = word.strip_prefix('@') else {
+            continue;
+        };
+        let path = match path.trim_end_matches(TRAILING_PUNCTUATION) {
+            "" => path,
+            trimmed => trimmed
+        };
+        if !path.is_empty() && !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+/// The path which is being mentioned at the end of `text`, e.g. the text before the cursor
+pub fn mention_at_end(text: &str) -> Option<&str> {
+    let word = &text[text.rfind(char::is_whitespace).map_or(0, |index| index + 1)..];
+    word.strip_prefix('@')
+}
+
+/// A text file as a part of a message, with its path as header
+pub fn text_file_part(path: &str, contents: &str) -> UserMessagePart {
+    UserMessagePart {
+        content: UserMessageContent::Text { text: format!("<file path=\"{path}\">\n{}\n</file>", contents.trim_end_matches('\n')) }
+    }
+}
+
+/// The path and the contents of a text file which is attached to a message
+pub fn attached_text_file(text: &str) -> Option<(&str, &str)> {
+    let (header, contents) = text.strip_suffix("\n</file>")?.split_once('\n')?;
+    let path = header.strip_prefix("<file path=\"")?.strip_suffix("\">")?;
+    Some((path, contents))
+}
+
+/// A short description of a file which is attached to a message; `None` for other parts
+pub fn attachment_label(content: &UserMessageContent) -> Option<String> {
+    match content {
+        UserMessageContent::Text { text } => {
+            let (path, contents) = attached_text_file(text)?;
+            let lines = contents.lines().count();
+            Some(format!("{path} ({lines} {})", if lines == 1 { "line" } else { "lines" }))
+        },
+        UserMessageContent::Image { media_type, data } | UserMessageContent::Document { media_type, data } => {
+            // Four characters of base64 encode three bytes
+            Some(format!("{media_type} ({})", format_size(data.len() / 4 * 3)))
+        },
+        UserMessageContent::ToolResult { .. } => None
+    }
+}
+
+pub fn format_size(bytes: usize) -> String {
+    match bytes {
+        0..1_000 => format!("{bytes} B"),
+        1_000..1_000_000 => format!("{:.1} kB", bytes as f64 / 1_000.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1_000_000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lliminal::llm::UserMessageContent;
+
+    use super::{attached_text_file, attachment_label, mention_at_end, mentioned_paths, text_file_part};
+
+    #[test]
+    fn finds_mentioned_paths_without_trailing_punctuation() {
+        let paths = mentioned_paths("See @src/main.rs, and @notes.txt. Compare (@src/main.rs) with @a@b and mail@example.com @ @.");
+
+        assert_eq!(paths, vec!["src/main.rs", "notes.txt", "a@b", "."]);
+    }
+
+    #[test]
+    fn finds_the_mention_which_is_being_typed() {
+        assert_eq!(mention_at_end("Look at @src/ma"), Some("src/ma"));
+        assert_eq!(mention_at_end("Look at @"), Some(""));
+        assert_eq!(mention_at_end("@src/main.rs and"), None);
+        assert_eq!(mention_at_end("Look at @src/main.rs "), None);
+    }
+
+    #[test]
+    fn reads_the_path_and_contents_of_attached_text_files() {
+        let part = text_file_part("notes.txt", "first\nsecond\n\n");
+        let UserMessageContent::Text { text } = &part.content else {
+            panic!("Text files are attached as text");
+        };
+
+        assert_eq!(attached_text_file(text), Some(("notes.txt", "first\nsecond")));
+        assert_eq!(attachment_label(&part.content), Some("notes.txt (2 lines)".to_string()));
+        assert_eq!(attached_text_file("<file path=\"notes.txt\">\nno end"), None);
+        assert_eq!(attached_text_file("Some text\n</file>"), None);
+    }
+}
@@ -1,45 +1,645 @@
-use lliminal::llm::{LlmError, Message, UserMessageContent, UserMessagePart};
+use std::{collections::BTreeSet, sync::atomic::{AtomicU64, Ordering}, time::{Duration, Instant}};
+
+use chrono::{DateTime, Utc};
+use lliminal::llm::{AssistantMessageContent, LlmError, Message, SystemPrompt, Usage, UserMessageContent, UserMessagePart};
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+
+use super::{ApprovalMode, ChatLayout, MessageTree, Search, TextCell, TextPosition, ToolApprovalRequest};
+
+/// The next version of the content of a message, unique across all chats
+static NEXT_VERSION: AtomicU64 = AtomicU64::new(0);
 
 #[derive(Clone, Debug)]
 pub struct Chat {
-    pub messages: Vec<Message>,
+    /// The identifier of the session in which the chat is saved
+    pub id: String,
+    /// The title of the session; derived from the first message if not set
+    pub title: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub model: String,
+    pub system: Vec<SystemPrompt>,
+    /// All messages, including the inactive branches
+    tree: MessageTree,
+    /// The messages of the active branch
+    messages: Vec<Message>,
+    /// The version of each message, which changes whenever its content changes
+    versions: Vec<u64>,
+    /// The index of the active alternative and the number of alternatives of each message
+    alternatives: Vec<(usize, usize)>,
+    /// Whether each message is pinned, so that it is always sent to the model
+    pinned: Vec<bool>,
     pub user_input: bool,
+    /// The number of lines scrolled up from the bottom
     pub scroll: usize,
-    pub error: Option<LlmError>
+    /// The number of lines of all messages when the chat was scrolled up
+    ///
+    /// Lines which are added afterwards are below the view, which keeps its place instead of
+    /// following the response; `None` while the view follows the bottom.
+    pub scroll_lines: Option<usize>,
+    pub error: Option<ChatError>,
+    /// Information for the user, e.g. the result of a slash command
+    pub notice: Option<String>,
+    /// The input whose large attachments await confirmation; it is sent if it is submitted again
+    pub confirm_input: Option<String>,
+    /// The index of the message which is selected for an action
+    pub selected: Option<usize>,
+    /// The index of the user message which is being edited; it is replaced on submit
+    pub editing: Option<usize>,
+    /// The text which is being selected with the mouse
+    pub mouse_selection: Option<MouseSelection>,
+    pub search: Option<Search>,
+    /// The tool call which waits for the approval of the user
+    pub approval: Option<ToolApprovalRequest>,
+    /// The tokens used by all requests of the chat
+    pub usage: Usage,
+    /// The tokens used by the current or last request
+    pub turn_usage: Usage,
+    /// When the current or last request was started
+    pub started_at: Option<Instant>,
+    /// When the last request was finished; `None` while it is running
+    pub finished_at: Option<Instant>
+}
+
+/// Text which is selected with the mouse
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MouseSelection {
+    /// The cell where the mouse was pressed
+    pub anchor: TextCell,
+    /// The cell which the mouse was dragged to; `None` until it is dragged
+    pub head: Option<TextCell>
+}
+
+impl MouseSelection {
+    /// The start and the end of the selected text, once the mouse has been dragged
+    pub fn range(&self) -> Option<(TextPosition, TextPosition)> {
+        let head = self.head?;
+        if head.start() < self.anchor.start() {
+            Some((head.start(), self.anchor.end()))
+        } else {
+            Some((self.anchor.start(), head.end()))
+        }
+    }
+}
+
+/// An error which is shown in the chat
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChatError {
+    Llm(LlmError),
+    Session(String),
+    /// The external editor could not be used
+    Editor(String),
+    /// A slash command is invalid or failed
+    Command(String),
+    /// A message action is not possible or failed
+    Message(String),
+    /// A file mentioned in the input cannot be attached
+    Attachment(String),
+    /// The edited input of a tool call is not valid JSON
+    ToolInput(String)
 }
 
-impl Default for Chat {
-    fn default() -> Self {
+impl Chat {
+    pub fn new(model: &str) -> Self {
+        let created_at = Utc::now();
         Self {
+            id: created_at.format("%Y%m%d-%H%M%S-%3f").to_string(),
+            title: None,
+            created_at,
+            model: model.to_string(),
+            system: vec![],
+            tree: MessageTree::default(),
             messages: vec![],
+            versions: vec![],
+            alternatives: vec![],
+            pinned: vec![],
             user_input: true,
             scroll: 0,
-            error: None
+            scroll_lines: None,
+            error: None,
+            notice: None,
+            confirm_input: None,
+            selected: None,
+            editing: None,
+            mouse_selection: None,
+            search: None,
+            approval: None,
+            usage: Usage::default(),
+            turn_usage: Usage::default(),
+            started_at: None,
+            finished_at: None
         }
     }
-}
 
-impl Chat {
-    pub fn submit_user_input(&mut self, input: &str) {
-        self.messages.push(
-            Message::User { parts: vec![
-                UserMessagePart {
-                    content: UserMessageContent::Text { text: input.to_string() }
-                }
-            ]}
-        );
+    /// The messages of the active branch, which are shown and sent to the model
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+
+    /// The version of each message; a message whose content changed has a new version
+    pub fn versions(&self) -> &[u64] {
+        &self.versions
+    }
+
+    pub fn tree(&self) -> &MessageTree {
+        &self.tree
+    }
+
+    pub fn set_tree(&mut self, tree: MessageTree) {
+        self.tree = tree;
+        self.sync_messages();
+    }
+
+    /// Submit the input followed by the attached files; an edited message is added as an alternative to the original one
+    pub fn submit_user_input(&mut self, input: &str, attachments: Vec<UserMessagePart>) {
+        if let Some(index) = self.editing.take() {
+            self.tree.branch(index);
+        }
+        let mut parts = vec![UserMessagePart { content: UserMessageContent::Text { text: input.to_string() } }];
+        parts.extend(attachments);
+        self.tree.push(Message::User { parts });
+        self.sync_messages();
+        self.scroll_to_bottom();
         self.user_input = false;
+        self.error = None;
+        self.notice = None;
+        self.confirm_input = None;
+    }
+
+    /// Replace the messages from `start` on with the response which is being received
+    pub fn update_response(&mut self, start: usize, messages: &[Message]) {
+        self.tree.replace_from(start, messages);
+        self.sync_messages_from(start);
+    }
+
+    /// Start timing a request and counting its tokens
+    pub fn start_turn(&mut self) {
+        self.turn_usage = Usage::default();
+        self.started_at = Some(Instant::now());
+        self.finished_at = None;
+    }
+
+    /// Update the tokens used by the current request
+    pub fn record_usage(&mut self, turn_usage: Usage) {
+        self.usage = self.usage + turn_usage - self.turn_usage;
+        self.turn_usage = turn_usage;
+    }
+
+    /// The time the current request is running, or the last request took
+    pub fn elapsed(&self) -> Option<Duration> {
+        let started_at = self.started_at?;
+        Some(self.finished_at.unwrap_or_else(Instant::now) - started_at)
     }
 
     pub fn wait_for_user(&mut self) {
         self.user_input = true;
+        self.finish_turn();
+    }
+
+    /// Whether the user is editing the input of a tool call while the response waits for it
+    pub fn is_editing_tool_input(&self) -> bool {
+        matches!(self.approval, Some(ToolApprovalRequest { mode: ApprovalMode::Edit, .. }))
+    }
+
+    /// Show the error and hand control back to the user
+    pub fn fail(&mut self, error: ChatError) {
+        self.error = Some(error);
+        self.user_input = true;
+        self.finish_turn();
+    }
+
+    fn finish_turn(&mut self) {
+        if self.started_at.is_some() && self.finished_at.is_none() {
+            self.finished_at = Some(Instant::now());
+        }
+    }
+
+    /// Whether the last request failed and can be sent again
+    pub fn can_retry(&self) -> bool {
+        self.user_input && matches!(self.error, Some(ChatError::Llm(_))) && !self.messages.is_empty()
+    }
+
+    /// Remove the incomplete response of the failed request, so that it can be sent again
+    pub fn prepare_retry(&mut self) {
+        while let Some(Message::Assistant { .. }) = self.messages.last() {
+            self.tree.truncate(self.messages.len() - 1);
+            self.sync_messages();
+        }
+        self.user_input = false;
+        self.error = None;
+    }
+
+    /// The number of lines scrolled up from the bottom, given `total` lines of messages and the
+    /// `height` of the view
+    pub fn scroll_position(&self, total: usize, height: usize) -> usize {
+        (self.scroll + self.lines_below(total)).min(total.saturating_sub(height))
+    }
+
+    /// The number of lines which were added below the view since it was scrolled up
+    pub fn lines_below(&self, total: usize) -> usize {
+        self.scroll_lines.map_or(0, |lines| total.saturating_sub(lines))
+    }
+
+    pub fn scroll_up(&mut self, amount: usize, layout: &ChatLayout) {
+        let position = self.scroll_position(layout.total_lines, usize::from(layout.messages.height));
+        self.set_scroll(position.saturating_add(amount), layout);
+    }
+
+    pub fn scroll_down(&mut self, amount: usize, layout: &ChatLayout) {
+        let position = self.scroll_position(layout.total_lines, usize::from(layout.messages.height));
+        self.set_scroll(position.saturating_sub(amount), layout);
+    }
+
+    pub fn scroll_to_top(&mut self, layout: &ChatLayout) {
+        self.set_scroll(usize::MAX, layout);
+    }
+
+    /// Scroll to the bottom, which the view then follows
+    pub fn scroll_to_bottom(&mut self) {
+        self.scroll = 0;
+        self.scroll_lines = None;
+    }
+
+    /// Scroll to a position within the messages; the view follows the bottom once it reaches it
+    fn set_scroll(&mut self, scroll: usize, layout: &ChatLayout) {
+        self.scroll = scroll.min(layout.total_lines.saturating_sub(usize::from(layout.messages.height)));
+        self.scroll_lines = (self.scroll > 0).then_some(layout.total_lines);
+    }
+
+    /// Start a search, or edit the query of the current one
+    pub fn start_search(&mut self) {
+        self.selected = None;
+        match &mut self.search {
+            Some(search) => search.editing = true,
+            None => self.search = Some(Search::new())
+        }
+    }
+
+    /// Move to the next or previous search match and scroll it into view
+    pub fn move_to_match(&mut self, layout: &ChatLayout, forward: bool) {
+        if let Some(search) = &mut self.search {
+            let count = search.matches(&self.messages).len();
+            if forward {
+                search.next(count);
+            } else {
+                search.previous(count);
+            }
+        }
+        self.scroll_to_match(layout);
+    }
+
+    /// Scroll the current search match into view, centering it if it is not visible
+    ///
+    /// The matches are found in the current messages, so only the lines of the layout are used.
+    pub fn scroll_to_match(&mut self, layout: &ChatLayout) {
+        let Some(search) = &self.search else {
+            return;
+        };
+        let matches = search.matches(&self.messages);
+        let height = usize::from(layout.messages.height);
+        let position = self.scroll_position(layout.total_lines, height);
+        let current = search.current_match(matches.len()).and_then(|current| matches.get(current));
+        if let Some(line) = current.and_then(|found| layout.line_below(found.message, &found.range))
+            && (line < position || line >= position + height) {
+            self.set_scroll(line.saturating_sub(height / 2), layout);
+        }
+    }
+
+    /// Select the first message, if messages are selected
+    pub fn select_first(&mut self) {
+        self.selected = self.selected.map(|_| 0);
+    }
+
+    pub fn select_last(&mut self) {
+        self.selected = self.messages.len().checked_sub(1);
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.map(|index| index.saturating_sub(1));
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = self.selected.map(|index| (index + 1).min(self.messages.len().saturating_sub(1)));
+    }
+
+    pub fn selected_message(&self) -> Option<&Message> {
+        self.selected.and_then(|index| self.messages.get(index))
     }
 
-    pub fn scroll_up(&mut self, amount: usize) {
-        self.scroll = self.scroll.saturating_add(amount);
+    /// Start selecting text with the mouse at the cell
+    pub fn start_mouse_selection(&mut self, cell: TextCell) {
+        self.mouse_selection = Some(MouseSelection { anchor: cell, head: None });
     }
 
-    pub fn scroll_down(&mut self, amount: usize) {
-        self.scroll = self.scroll.saturating_sub(amount);
+    pub fn extend_mouse_selection(&mut self, cell: TextCell) {
+        if let Some(selection) = &mut self.mouse_selection {
+            selection.head = Some(cell);
+        }
+    }
+
+    /// Finish selecting text with the mouse; returns the selected text
+    ///
+    /// A click on a message, without dragging the mouse, selects it for an action instead.
+    pub fn finish_mouse_selection(&mut self) -> Option<String> {
+        let selection = self.mouse_selection.take()?;
+        let Some((start, end)) = selection.range() else {
+            self.selected = Some(selection.anchor.message).filter(|index| *index < self.messages.len());
+            return None;
+        };
+        let texts: Vec<String> = (start.message..=end.message).filter_map(|index| {
+            let text = self.messages.get(index)?.text();
+            let from = if index == start.message { start.offset } else { 0 };
+            let to = if index == end.message { end.offset.min(text.len()) } else { text.len() };
+            text.get(from..to).filter(|text| !text.is_empty()).map(str::to_string)
+        }).collect();
+        (!texts.is_empty()).then(|| texts.join("\n\n"))
+    }
+
+    /// Whether the message is highlighted, because it is selected with the keyboard
+    pub fn is_highlighted(&self, index: usize) -> bool {
+        self.selected == Some(index)
+    }
+
+    /// The last response which contains text
+    pub fn last_response(&self) -> Option<&Message> {
+        self.messages.iter().rev().find(|message| matches!(message, Message::Assistant { .. }) && !message.text().is_empty())
+    }
+
+    /// Delete the message, together with the tool calls or results which belong to it
+    pub fn delete_message(&mut self, index: usize) {
+        if index >= self.messages.len() {
+            return;
+        }
+        let mut range = index..index + 1;
+        if index > 0 && has_tool_results(self.messages.get(index)) && has_tool_calls(self.messages.get(index - 1)) {
+            range.start -= 1;
+        }
+        if has_tool_calls(self.messages.get(range.start)) && has_tool_results(self.messages.get(range.start + 1)) {
+            range.end = range.end.max(range.start + 2);
+        }
+        for index in range.rev() {
+            self.tree.remove(index);
+        }
+        self.sync_messages();
+        self.selected = match self.messages.len() {
+            0 => None,
+            len => self.selected.map(|selected| selected.min(len - 1))
+        };
+    }
+
+    /// Start editing a message of the user; returns its text
+    pub fn start_edit(&mut self, index: usize) -> Option<String> {
+        match self.messages.get(index)? {
+            Message::User { parts } if !has_tool_results(self.messages.get(index)) && !parts.is_empty() => {
+                self.editing = Some(index);
+                self.selected = None;
+                // The files are attached again from the mentions in the text
+                match &parts[0].content {
+                    UserMessageContent::Text { text } if parts.len() > 1 => Some(text.clone()),
+                    _ => Some(self.messages[index].text())
+                }
+            },
+            _ => None
+        }
+    }
+
+    /// Branch off before the response, so that a new one is requested as an alternative
+    pub fn prepare_regenerate(&mut self, index: usize) -> bool {
+        if !matches!(self.messages.get(index), Some(Message::Assistant { .. })) {
+            return false;
+        }
+        // Tool results before the response would be sent without their tool calls
+        let start = self.messages[..index].iter()
+            .rposition(|message| matches!(message, Message::User { .. }) && !has_tool_results(Some(message)))
+            .map_or(0, |user| user + 1);
+        if start == 0 {
+            return false;
+        }
+        self.tree.branch(start);
+        self.sync_messages();
+        self.selected = None;
+        self.user_input = false;
+        self.error = None;
+        true
+    }
+
+    /// Prepare sending the last request again: the failed request is retried, while a complete
+    /// response is kept as an alternative of the new one
+    pub fn prepare_resend(&mut self) -> bool {
+        if self.can_retry() {
+            self.prepare_retry();
+            return true;
+        }
+        match self.messages.last() {
+            Some(Message::Assistant { .. }) => self.prepare_regenerate(self.messages.len() - 1),
+            Some(Message::User { .. }) => {
+                self.prepare_retry();
+                true
+            },
+            None => false
+        }
+    }
+
+    /// The index of the active alternative of the message and the number of alternatives
+    pub fn alternatives(&self, index: usize) -> (usize, usize) {
+        self.alternatives.get(index).copied().unwrap_or_default()
+    }
+
+    pub fn is_pinned(&self, index: usize) -> bool {
+        self.pinned.get(index).copied().unwrap_or_default()
+    }
+
+    /// The indices of the pinned messages, which are kept when the conversation exceeds the context window
+    pub fn pinned_messages(&self) -> BTreeSet<usize> {
+        self.pinned.iter().enumerate().filter(|(_, pinned)| **pinned).map(|(index, _)| index).collect()
+    }
+
+    /// Pin the selected message, or unpin it if it is pinned
+    pub fn toggle_pinned(&mut self) {
+        if let Some(index) = self.selected {
+            self.tree.toggle_pinned(index);
+            self.pinned = self.tree.path_pinned();
+        }
+    }
+
+    /// Switch the selected message to its next or previous alternative
+    pub fn cycle_alternative(&mut self, forward: bool) {
+        let Some(index) = self.selected else {
+            return;
+        };
+        self.tree.cycle(index, forward);
+        self.sync_messages();
+        self.selected = Some(index.min(self.messages.len().saturating_sub(1)));
+    }
+
+    fn sync_messages(&mut self) {
+        self.sync_messages_from(0);
+    }
+
+    /// Update the messages from `start` on, keeping the versions of those which did not change
+    fn sync_messages_from(&mut self, start: usize) {
+        let start = start.min(self.messages.len());
+        let messages = self.tree.messages_from(start);
+        let versions: Vec<u64> = messages.iter().enumerate().map(|(offset, message)| {
+            match (self.messages.get(start + offset), self.versions.get(start + offset)) {
+                (Some(previous), Some(version)) if previous == message => *version,
+                _ => NEXT_VERSION.fetch_add(1, Ordering::Relaxed)
+            }
+        }).collect();
+        self.messages.truncate(start);
+        self.messages.extend(messages);
+        self.versions.truncate(start);
+        self.versions.extend(versions);
+        self.alternatives = self.tree.path_alternatives();
+        self.pinned = self.tree.path_pinned();
+    }
+}
+
+/// A parser of the markdown in messages, with the extensions which are rendered
+pub fn markdown_parser(text: &str) -> Parser<'_> {
+    Parser::new_ext(text, Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS)
+}
+
+/// The code blocks in the text of a response, in the order in which they are numbered
+pub fn code_blocks(message: &Message) -> Vec<String> {
+    let Message::Assistant { parts } = message else {
+        return vec![];
+    };
+    let mut code_blocks = vec![];
+    for part in parts {
+        let AssistantMessageContent::Text { text } = &part.content else {
+            continue;
+        };
+        let mut code_block: Option<String> = None;
+        for event in markdown_parser(text) {
+            match event {
+                Event::Start(Tag::CodeBlock(_)) => code_block = Some(String::new()),
+                Event::Text(text) => if let Some(code_block) = &mut code_block {
+                    code_block.push_str(&text);
+                },
+                Event::End(TagEnd::CodeBlock) => code_blocks.extend(code_block.take()),
+                _ => {}
+            }
+        }
+    }
+    code_blocks
+}
+
+fn has_tool_calls(message: Option<&Message>) -> bool {
+    matches!(message, Some(Message::Assistant { parts }) if parts.iter().any(|part| matches!(part.content, AssistantMessageContent::ToolUse { .. })))
+}
+
+fn has_tool_results(message: Option<&Message>) -> bool {
+    matches!(message, Some(Message::User { parts }) if parts.iter().any(|part| matches!(part.content, UserMessageContent::ToolResult { .. })))
+}
+
+#[cfg(test)]
+mod tests {
+    use lliminal::llm::{AssistantMessageContent, AssistantMessagePart, LlmError, Message};
+    use ratatui::layout::Rect;
+
+    use super::{Chat, ChatError, ChatLayout};
+
+    fn response(text: &str, complete: bool) -> Message {
+        Message::Assistant { parts: vec![AssistantMessagePart { complete, content: AssistantMessageContent::Text { text: text.to_string() } }] }
+    }
+
+    fn layout(total_lines: usize, height: u16) -> ChatLayout {
+        ChatLayout { messages: Rect::new(0, 0, 80, height), total_lines, ..ChatLayout::default() }
+    }
+
+    #[test]
+    fn retries_truncated_response_without_the_incomplete_part() {
+        let mut chat = Chat::new("model");
+        chat.submit_user_input("Hello", vec![]);
+        chat.update_response(1, &[response("Hi, how", false)]);
+
+        chat.fail(ChatError::Llm(LlmError::UnexpectedResponse));
+
+        assert!(chat.user_input);
+        assert!(chat.can_retry());
+        chat.prepare_retry();
+        assert_eq!(chat.messages().len(), 1);
+        assert_eq!(chat.error, None);
+        assert!(!chat.user_input);
+    }
+
+    #[test]
+    fn resends_a_complete_response_as_an_alternative() {
+        let mut chat = Chat::new("model");
+        assert!(!chat.prepare_resend());
+        chat.submit_user_input("Hello", vec![]);
+        chat.update_response(1, &[response("Hi", true)]);
+        chat.wait_for_user();
+
+        assert!(!chat.can_retry());
+        assert!(chat.prepare_resend());
+        assert_eq!(chat.messages().len(), 1);
+        assert!(!chat.user_input);
+        chat.update_response(1, &[response("Hello there", true)]);
+        assert_eq!(chat.alternatives(1), (1, 2));
+        chat.selected = Some(1);
+        chat.cycle_alternative(false);
+        assert_eq!(chat.messages()[1].text(), "Hi");
+    }
+
+    #[test]
+    fn keeps_the_versions_of_messages_which_did_not_change() {
+        let mut chat = Chat::new("model");
+        chat.submit_user_input("Hello", vec![]);
+        chat.update_response(1, &[response("Hi", false)]);
+        let versions = chat.versions().to_vec();
+
+        chat.update_response(1, &[response("Hi", false)]);
+        assert_eq!(chat.versions(), versions);
+
+        chat.update_response(1, &[response("Hi there", true)]);
+        assert_eq!(chat.versions()[0], versions[0]);
+        assert!(chat.versions()[1] > versions[1]);
+
+        chat.delete_message(1);
+        assert_eq!(chat.versions(), &versions[..1]);
+        chat.update_response(1, &[response("Hi", false)]);
+        // A version is never used again, even for the same content
+        assert!(!versions.contains(&chat.versions()[1]));
+    }
+
+    #[test]
+    fn clamps_scrolling_to_the_messages() {
+        let mut chat = Chat::new("model");
+
+        chat.scroll_up(30, &layout(50, 10));
+        assert_eq!(chat.scroll_position(50, 10), 30);
+        chat.scroll_up(30, &layout(50, 10));
+        assert_eq!(chat.scroll_position(50, 10), 40);
+        chat.scroll_down(100, &layout(50, 10));
+        assert_eq!(chat.scroll_position(50, 10), 0);
+        assert_eq!(chat.scroll_lines, None);
+
+        chat.scroll_to_top(&layout(50, 10));
+        assert_eq!(chat.scroll_position(50, 10), 40);
+        // Messages which fit into the view cannot be scrolled
+        chat.scroll_up(5, &layout(8, 10));
+        assert_eq!(chat.scroll_position(8, 10), 0);
+    }
+
+    #[test]
+    fn follows_new_content_only_at_the_bottom() {
+        let mut chat = Chat::new("model");
+        assert_eq!(chat.scroll_position(60, 10), 0);
+        assert_eq!(chat.lines_below(60), 0);
+
+        chat.scroll_up(5, &layout(50, 10));
+        // The view stays on the same lines while more are added below
+        assert_eq!(chat.scroll_position(60, 10), 15);
+        assert_eq!(chat.lines_below(60), 10);
+        assert_eq!(chat.lines_below(40), 0);
+
+        chat.scroll_down(20, &layout(60, 10));
+        assert_eq!(chat.scroll_position(70, 10), 0);
+        assert_eq!(chat.lines_below(70), 0);
+
+        chat.scroll_up(5, &layout(70, 10));
+        chat.submit_user_input("Hello", vec![]);
+        assert_eq!(chat.scroll_position(80, 10), 0);
     }
 }
@@ -0,0 +1,198 @@
+use ratatui::style::Style;
+use tui_textarea::{CursorMove, Input, Key, TextArea};
+
+/// The hint shown while the editor is empty
+const PLACEHOLDER: &str = "Enter to send, Alt+Enter for a new line, @path to attach a file, Ctrl+G to open $EDITOR";
+
+/// The multi-line editor for the next user message
+#[derive(Clone, Debug)]
+pub struct Editor {
+    pub textarea: TextArea<'static>
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self::new("")
+    }
+}
+
+impl Editor {
+    /// An editor containing `text` with the cursor at its end
+    pub fn new(text: &str) -> Self {
+        let mut textarea = TextArea::from(text.split('\n'));
+        textarea.set_cursor_line_style(Style::default());
+        textarea.set_placeholder_text(PLACEHOLDER);
+        textarea.move_cursor(CursorMove::Bottom);
+        textarea.move_cursor(CursorMove::End);
+        Self { textarea }
+    }
+
+    pub fn text(&self) -> String {
+        self.textarea.lines().join("\n")
+    }
+
+    /// Whether there is nothing but whitespace to send
+    pub fn is_blank(&self) -> bool {
+        self.textarea.lines().iter().all(|line| line.trim().is_empty())
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.textarea.lines().len()
+    }
+
+    /// Handle a key; Enter with any modifier and Ctrl+J insert a new line
+    pub fn input(&mut self, input: Input) {
+        match input {
+            Input { key: Key::Char('j'), ctrl: true, alt: false, .. } => self.textarea.insert_newline(),
+            Input { key: Key::Char('z'), ctrl: true, alt: false, .. } => { self.textarea.undo(); },
+            input => { self.textarea.input(input); }
+        }
+    }
+
+    pub fn move_cursor(&mut self, movement: CursorMove) {
+        self.textarea.move_cursor(movement);
+    }
+
+    /// Delete the character under the cursor
+    pub fn delete_char(&mut self) {
+        self.textarea.delete_next_char();
+    }
+
+    /// Delete the line of the cursor, including its line break
+    pub fn delete_line(&mut self) {
+        let (row, _) = self.textarea.cursor();
+        let line_count = self.line_count();
+        self.textarea.move_cursor(CursorMove::Head);
+        if !self.textarea.lines()[row].is_empty() {
+            self.textarea.delete_line_by_end();
+        }
+        if row + 1 < line_count {
+            self.textarea.delete_next_char();
+        } else if row > 0 {
+            self.textarea.delete_char();
+            self.textarea.move_cursor(CursorMove::Head);
+        }
+    }
+
+    /// Insert an empty line below or above the cursor and move to it
+    pub fn open_line(&mut self, below: bool) {
+        if below {
+            self.textarea.move_cursor(CursorMove::End);
+            self.textarea.insert_newline();
+        } else {
+            self.textarea.move_cursor(CursorMove::Head);
+            self.textarea.insert_newline();
+            self.textarea.move_cursor(CursorMove::Up);
+        }
+    }
+
+    pub fn insert_newline(&mut self) {
+        self.textarea.insert_newline();
+    }
+
+    pub fn undo(&mut self) {
+        self.textarea.undo();
+    }
+
+    pub fn redo(&mut self) {
+        self.textarea.redo();
+    }
+
+    /// The line of the cursor up to the cursor
+    pub fn line_before_cursor(&self) -> String {
+        let (row, column) = self.textarea.cursor();
+        self.textarea.lines()[row].chars().take(column).collect()
+    }
+
+    pub fn insert(&mut self, text: &str) {
+        self.textarea.insert_str(text);
+    }
+
+    /// Insert pasted text at the cursor
+    pub fn paste(&mut self, text: &str) {
+        self.textarea.insert_str(text.replace("\r\n", "\n").replace('\r', "\n"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tui_textarea::{CursorMove, Input, Key};
+
+    use super::Editor;
+
+    /// An editor with the cursor at the start of the text
+    fn editor(text: &str) -> Editor {
+        let mut editor = Editor::new(text);
+        editor.move_cursor(CursorMove::Top);
+        editor.move_cursor(CursorMove::Head);
+        editor
+    }
+
+    #[test]
+    fn inserts_new_lines_with_enter_and_ctrl_j() {
+        let mut editor = Editor::new("ab");
+        editor.move_cursor(CursorMove::Back);
+
+        editor.input(Input { key: Key::Char('j'), ctrl: true, alt: false, shift: false });
+        assert_eq!(editor.text(), "a\nb");
+        editor.input(Input { key: Key::Enter, ctrl: false, alt: true, shift: false });
+        assert_eq!(editor.text(), "a\n\nb");
+        editor.insert_newline();
+        assert_eq!(editor.line_count(), 4);
+        assert_eq!(editor.textarea.cursor(), (3, 0));
+    }
+
+    #[test]
+    fn deletes_the_first_and_the_last_line() {
+        let mut editor = editor("first\nsecond\nthird");
+        editor.delete_line();
+        assert_eq!(editor.text(), "second\nthird");
+        assert_eq!(editor.textarea.cursor(), (0, 0));
+
+        editor.move_cursor(CursorMove::Bottom);
+        editor.delete_line();
+        assert_eq!(editor.text(), "second");
+        assert_eq!(editor.textarea.cursor(), (0, 0));
+
+        editor.delete_line();
+        assert_eq!(editor.text(), "");
+        assert!(editor.is_blank());
+    }
+
+    #[test]
+    fn opens_lines_above_the_first_and_below_the_last_line() {
+        let mut editor = editor("first\nlast");
+        editor.open_line(false);
+        assert_eq!(editor.text(), "\nfirst\nlast");
+        assert_eq!(editor.textarea.cursor(), (0, 0));
+
+        editor.move_cursor(CursorMove::Bottom);
+        editor.open_line(true);
+        assert_eq!(editor.text(), "\nfirst\nlast\n");
+        assert_eq!(editor.textarea.cursor(), (3, 0));
+    }
+
+    #[test]
+    fn undoes_and_redoes_edits() {
+        let mut editor = Editor::new("Hello");
+        editor.insert(" world");
+
+        editor.undo();
+        assert_eq!(editor.text(), "Hello");
+        editor.redo();
+        assert_eq!(editor.text(), "Hello world");
+        editor.input(Input { key: Key::Char('z'), ctrl: true, alt: false, shift: false });
+        assert_eq!(editor.text(), "Hello");
+    }
+
+    #[test]
+    fn takes_the_line_up_to_the_cursor() {
+        let mut editor = Editor::new("first\nsee @src/ma");
+        assert_eq!(editor.line_before_cursor(), "see @src/ma");
+
+        editor.move_cursor(CursorMove::WordBack);
+        assert_eq!(editor.line_before_cursor(), "see @src/");
+        editor.move_cursor(CursorMove::Up);
+        assert_eq!(editor.line_before_cursor(), "first");
+    }
+}
@@ -0,0 +1,294 @@
+use std::{ops::Range, sync::Arc};
+
+use lliminal::llm::Message;
+use ratatui::layout::{Position, Rect};
+use textwrap::core::display_width;
+
+use super::SearchMatch;
+
+/// How the chat is laid out on the screen, before it is rendered
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChatLayout {
+    pub messages: Rect,
+    /// The error or notice between the messages and the input; empty if there is none
+    pub banner: Rect,
+    pub input: Rect,
+    pub status: Rect,
+    /// The rendered lines of each message
+    pub message_lines: Vec<MessageLines>,
+    /// The message and its line in each row of the message area, from the top
+    pub message_rows: Vec<Option<(usize, usize)>>,
+    /// The number of lines which are scrolled up from the bottom
+    pub scroll: usize,
+    /// The number of lines of all messages
+    pub total_lines: usize,
+    /// The matches of the search, from the top
+    pub search_matches: Vec<SearchMatch>
+}
+
+/// The lines of a message as they are rendered
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MessageLines {
+    /// The version of the content which was rendered
+    pub version: u64,
+    /// A header with the alternatives of the message is shown above its content
+    pub header: bool,
+    /// Where the text of each line of the content comes from
+    pub sources: Arc<[LineSource]>
+}
+
+/// The pieces of a rendered line which show the text of its message
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LineSource {
+    pub pieces: Vec<SourcePiece>
+}
+
+/// A piece of a rendered line and the text of the message which it shows
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourcePiece {
+    /// The first column of the piece in the line
+    pub column: usize,
+    pub width: usize,
+    /// The byte range of the text of the message
+    pub range: Range<usize>,
+    /// The piece shows the text as it is, one character after the other; otherwise, it stands for the whole range,
+    /// e.g. for an escaped character or a line break
+    pub verbatim: bool
+}
+
+/// A position in the text of a message
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TextPosition {
+    pub message: usize,
+    /// The byte offset in the text of the message
+    pub offset: usize
+}
+
+/// The text of a message under a cell of the screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextCell {
+    pub message: usize,
+    /// The byte offset before the character in the cell
+    pub start: usize,
+    /// The byte offset after the character; the same as `start` if the cell does not show the text
+    pub end: usize
+}
+
+impl ChatLayout {
+    /// The index of the message at the position, if any
+    pub fn message_at(&self, position: Position) -> Option<usize> {
+        if !self.messages.contains(position) {
+            return None;
+        }
+        self.message_rows.get(usize::from(position.y - self.messages.y)).copied().flatten().map(|(index, _)| index)
+    }
+
+    /// The text of the message at the position; positions above or below the message area are moved into it
+    pub fn text_cell_at(&self, position: Position, messages: &[Message]) -> Option<TextCell> {
+        let row = position.y.clamp(self.messages.top(), self.messages.bottom().checked_sub(1)?) - self.messages.y;
+        let (message, line) = self.message_rows.get(usize::from(row)).copied().flatten()?;
+        let text = messages.get(message)?.text();
+        let column = usize::from(position.x.saturating_sub(self.messages.x));
+        let (start, end) = self.message_lines.get(message)?.offsets_at(line, column, &text);
+        Some(TextCell { message, start, end })
+    }
+
+    /// The line which shows the start of the text in the byte range of the message, counted from the bottom of all
+    /// messages
+    pub fn line_below(&self, message: usize, range: &Range<usize>) -> Option<usize> {
+        let lines = self.message_lines.get(message)?;
+        let below: usize = self.message_lines[message + 1..].iter().map(MessageLines::height).sum();
+        Some(below + lines.height().checked_sub(lines.line_of(range) + 1)?)
+    }
+}
+
+impl MessageLines {
+    /// The number of lines, including the header
+    pub fn height(&self) -> usize {
+        self.sources.len() + usize::from(self.header)
+    }
+
+    /// The source of the line, counted from the header; `None` for the header
+    pub fn source(&self, line: usize) -> Option<&LineSource> {
+        line.checked_sub(usize::from(self.header)).and_then(|line| self.sources.get(line))
+    }
+
+    /// The line which shows the start of the text in the byte range, counted from the header
+    ///
+    /// Text which is not shown, e.g. markup, is on the line of the text after it.
+    fn line_of(&self, range: &Range<usize>) -> usize {
+        let line = self.sources.iter()
+            .position(|source| source.pieces.iter().any(|piece| piece.range.end > range.start))
+            .unwrap_or(self.sources.len().saturating_sub(1));
+        line + usize::from(self.header)
+    }
+
+    /// The byte offsets before and after the text at the column of the line
+    fn offsets_at(&self, line: usize, column: usize, text: &str) -> (usize, usize) {
+        if let Some(source) = self.source(line) {
+            match source.pieces.iter().find(|piece| column < piece.column + piece.width) {
+                Some(piece) if column < piece.column => return (piece.range.start, piece.range.start),
+                Some(piece) => return piece.offsets_at(column - piece.column, text),
+                None => if let Some(piece) = source.pieces.last() {
+                    return (piece.range.end, piece.range.end);
+                }
+            }
+        }
+        // Lines which do not show any text, e.g. empty lines, are at the end of the text above them
+        let content_line = line.saturating_sub(usize::from(self.header)).min(self.sources.len());
+        let offset = self.sources[..content_line].iter().rev()
+            .find_map(|source| source.pieces.last())
+            .map(|piece| piece.range.end)
+            .or_else(|| self.sources[content_line..].iter().find_map(|source| source.pieces.first()).map(|piece| piece.range.start))
+            .unwrap_or_default();
+        (offset, offset)
+    }
+}
+
+impl LineSource {
+    /// The columns of the line which show the text in the byte range
+    pub fn columns(&self, range: &Range<usize>, text: &str) -> Vec<Range<usize>> {
+        self.pieces.iter()
+            .filter(|piece| piece.range.start < range.end && range.start < piece.range.end)
+            .map(|piece| {
+                if !piece.verbatim {
+                    return piece.column..piece.column + piece.width;
+                }
+                let mut column = piece.column;
+                let mut columns: Option<Range<usize>> = None;
+                for (index, char) in text.get(piece.range.clone()).unwrap_or_default().char_indices() {
+                    let offset = piece.range.start + index;
+                    if offset >= range.end {
+                        break;
+                    }
+                    let width = char_width(char);
+                    if offset >= range.start {
+                        columns = Some(columns.map_or(column..column + width, |columns| columns.start..column + width));
+                    }
+                    column += width;
+                }
+                columns.unwrap_or_default()
+            })
+            .filter(|columns| !columns.is_empty())
+            .collect()
+    }
+}
+
+impl SourcePiece {
+    /// The byte offsets before and after the character at the column of the piece
+    fn offsets_at(&self, column: usize, text: &str) -> (usize, usize) {
+        if !self.verbatim {
+            return (self.range.start, self.range.end);
+        }
+        let mut position = 0;
+        for (index, char) in text.get(self.range.clone()).unwrap_or_default().char_indices() {
+            position += char_width(char);
+            if position > column {
+                let start = self.range.start + index;
+                return (start, start + char.len_utf8());
+            }
+        }
+        (self.range.end, self.range.end)
+    }
+}
+
+impl TextCell {
+    pub fn start(&self) -> TextPosition {
+        TextPosition { message: self.message, offset: self.start }
+    }
+
+    pub fn end(&self) -> TextPosition {
+        TextPosition { message: self.message, offset: self.end }
+    }
+}
+
+fn char_width(char: char) -> usize {
+    display_width(char.encode_utf8(&mut [0; 4]))
+}
+
+#[cfg(test)]
+mod tests {
+    use lliminal::llm::{AssistantMessageContent, AssistantMessagePart, Message};
+    use ratatui::layout::{Position, Rect};
+
+    use super::{ChatLayout, LineSource, MessageLines, SourcePiece, TextCell};
+
+    fn message(text: &str) -> Message {
+        Message::Assistant { parts: vec![AssistantMessagePart { complete: true, content: AssistantMessageContent::Text { text: text.to_string() } }] }
+    }
+
+    fn piece(column: usize, width: usize, start: usize, end: usize, verbatim: bool) -> SourcePiece {
+        SourcePiece { column, width, range: start..end, verbatim }
+    }
+
+    /// The layout of "**Hello** wörld\n\n- item", rendered as "Hello wörld", "", "• item" below a header
+    fn layout() -> (ChatLayout, Vec<Message>) {
+        let sources = vec![
+            LineSource { pieces: vec![piece(0, 5, 2, 7, true), piece(5, 1, 9, 10, true), piece(6, 5, 10, 16, true)] },
+            LineSource::default(),
+            LineSource { pieces: vec![piece(2, 4, 20, 24, true)] },
+        ];
+        let layout = ChatLayout {
+            messages: Rect::new(0, 1, 20, 5),
+            message_lines: vec![MessageLines { version: 0, header: true, sources: sources.into() }],
+            message_rows: vec![None, Some((0, 0)), Some((0, 1)), Some((0, 2)), Some((0, 3))],
+            ..ChatLayout::default()
+        };
+        (layout, vec![message("**Hello** wörld\n\n- item")])
+    }
+
+    #[test]
+    fn maps_cells_to_characters_of_the_text() {
+        let (layout, messages) = layout();
+        let cell = |x, y| layout.text_cell_at(Position::new(x, y), &messages);
+
+        assert_eq!(cell(0, 3), Some(TextCell { message: 0, start: 2, end: 3 }));
+        assert_eq!(cell(8, 3), Some(TextCell { message: 0, start: 13, end: 14 }));
+        assert_eq!(cell(15, 3), Some(TextCell { message: 0, start: 16, end: 16 }));
+        assert_eq!(cell(0, 5), Some(TextCell { message: 0, start: 20, end: 20 }));
+        assert_eq!(cell(3, 5), Some(TextCell { message: 0, start: 21, end: 22 }));
+    }
+
+    #[test]
+    fn maps_lines_without_text_to_the_text_around_them() {
+        let (layout, messages) = layout();
+        let cell = |x, y| layout.text_cell_at(Position::new(x, y), &messages);
+
+        assert_eq!(cell(4, 2), Some(TextCell { message: 0, start: 2, end: 2 }));
+        assert_eq!(cell(4, 4), Some(TextCell { message: 0, start: 16, end: 16 }));
+        assert_eq!(cell(4, 1), None);
+    }
+
+    #[test]
+    fn moves_positions_outside_of_the_messages_into_them() {
+        let (layout, messages) = layout();
+
+        assert_eq!(layout.text_cell_at(Position::new(30, 9), &messages), Some(TextCell { message: 0, start: 24, end: 24 }));
+        assert_eq!(layout.message_at(Position::new(0, 9)), None);
+        assert_eq!(layout.message_at(Position::new(0, 2)), Some(0));
+    }
+
+    #[test]
+    fn finds_the_columns_of_text() {
+        let (layout, messages) = layout();
+        let text = messages[0].text();
+        let source = layout.message_lines[0].source(1).unwrap();
+
+        assert_eq!(source.columns(&(0..4), &text), vec![0..2]);
+        assert_eq!(source.columns(&(8..14), &text), vec![5..6, 6..9]);
+        assert_eq!(source.columns(&(16..20), &text), Vec::<std::ops::Range<usize>>::new());
+        let escaped = LineSource { pieces: vec![piece(0, 1, 0, 5, false)] };
+        assert_eq!(escaped.columns(&(2..3), "&amp;"), vec![0..1]);
+    }
+
+    #[test]
+    fn finds_the_lines_of_text_from_the_bottom() {
+        let (layout, _) = layout();
+
+        assert_eq!(layout.line_below(0, &(10..16)), Some(2));
+        // The markup before the text is on the line of the text
+        assert_eq!(layout.line_below(0, &(0..2)), Some(2));
+        assert_eq!(layout.line_below(0, &(17..22)), Some(0));
+        assert_eq!(layout.line_below(1, &(0..1)), None);
+    }
+}
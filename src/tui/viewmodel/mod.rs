@@ -1,5 +1,23 @@
 mod app_state;
+mod approval;
+mod attachment;
 mod chat;
+mod editor;
+mod layout;
+mod pricing;
+mod search;
+mod sidebar;
+mod slash;
+mod tree;
 
-pub use app_state::AppState;
-pub use chat::Chat;
+pub use app_state::{AppState, Focus, VimMode};
+pub use approval::{ApprovalMode, ToolApprovalRequest};
+pub use attachment::{attached_text_file, attachment_label, format_size, mention_at_end, mentioned_paths, text_file_part};
+pub use chat::{code_blocks, markdown_parser, Chat, ChatError};
+pub use editor::Editor;
+pub use layout::{ChatLayout, LineSource, MessageLines, SourcePiece, TextCell, TextPosition};
+pub use pricing::estimated_cost;
+pub use search::{Search, SearchMatch};
+pub use sidebar::{SessionEntry, Sidebar, SidebarMode};
+pub use slash::{complete, SlashCommand, SlashCommandKind, SlashInput, SLASH_COMMANDS};
+pub use tree::MessageTree;
@@ -0,0 +1,69 @@
+use lliminal::llm::Usage;
+
+/// The price of a model in US dollars per million tokens
+struct Price {
+    input: f64,
+    output: f64
+}
+
+/// Prices of the models by the prefix of their names
+const PRICES: &[(&str, Price)] = &[
+    ("claude-opus-4", Price { input: 15.0, output: 75.0 }),
+    ("claude-sonnet-4", Price { input: 3.0, output: 15.0 }),
+    ("claude-3-7-sonnet", Price { input: 3.0, output: 15.0 }),
+    ("claude-3-5-sonnet", Price { input: 3.0, output: 15.0 }),
+    ("claude-3-5-haiku", Price { input: 0.8, output: 4.0 }),
+    ("claude-3-opus", Price { input: 15.0, output: 75.0 }),
+    ("claude-3-haiku", Price { input: 0.25, output: 1.25 }),
+];
+
+/// Writing to the prompt cache costs more than regular input tokens
+const CACHE_WRITE_FACTOR: f64 = 1.25;
+
+/// Reading from the prompt cache costs less than regular input tokens
+const CACHE_READ_FACTOR: f64 = 0.1;
+
+/// The estimated cost of the usage in US dollars; `None` for models with an unknown price
+pub fn estimated_cost(model: &str, usage: &Usage) -> Option<f64> {
+    let (_, price) = PRICES.iter().find(|(prefix, _)| model.starts_with(prefix))?;
+    let input = usage.input_tokens as f64
+        + usage.cache_creation_input_tokens as f64 * CACHE_WRITE_FACTOR
+        + usage.cache_read_input_tokens as f64 * CACHE_READ_FACTOR;
+    Some((input * price.input + usage.output_tokens as f64 * price.output) / 1_000_000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use lliminal::llm::Usage;
+
+    use super::estimated_cost;
+
+    fn assert_cost(model: &str, usage: Usage, expected: f64) {
+        let cost = estimated_cost(model, &usage).unwrap();
+        assert!((cost - expected).abs() < 1e-9, "{model} costs {cost} instead of {expected}");
+    }
+
+    #[test]
+    fn prices_input_and_output_tokens() {
+        let usage = Usage { input_tokens: 1_000_000, output_tokens: 1_000_000, ..Usage::default() };
+
+        assert_cost("claude-sonnet-4-20250514", usage, 18.0);
+        assert_cost("claude-opus-4-20250514", usage, 90.0);
+        assert_cost("claude-3-5-haiku-latest", usage, 4.8);
+        assert_cost("claude-3-haiku-20240307", usage, 1.5);
+    }
+
+    #[test]
+    fn prices_cache_writes_higher_and_reads_lower() {
+        let usage = Usage { cache_creation_input_tokens: 1_000_000, cache_read_input_tokens: 1_000_000, ..Usage::default() };
+
+        assert_cost("claude-sonnet-4-0", usage, 3.0 * 1.25 + 3.0 * 0.1);
+    }
+
+    #[test]
+    fn has_no_price_for_unknown_models() {
+        assert_eq!(estimated_cost("gpt-4o", &Usage::default()), None);
+        assert_eq!(estimated_cost("claude-2.1", &Usage { input_tokens: 1, ..Usage::default() }), None);
+        assert_eq!(estimated_cost("claude-3-5-haiku-latest", &Usage::default()), Some(0.0));
+    }
+}
@@ -0,0 +1,169 @@
+use std::ops::Range;
+
+use lliminal::llm::Message;
+use regex::{Regex, RegexBuilder};
+use tui_input::Input;
+
+/// A search for text in the messages of a chat
+#[derive(Clone, Debug)]
+pub struct Search {
+    query: Input,
+    /// The query is a regular expression instead of plain text
+    regex: bool,
+    /// The query is being edited; otherwise, the matches are navigated
+    pub editing: bool,
+    /// The index of the current match; the last match if it is not set
+    pub current: Option<usize>,
+    /// The compiled query, compiled whenever the query changes
+    pattern: Result<Option<Regex>, String>
+}
+
+/// A match of a search in the text of a message
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub message: usize,
+    /// The byte range of the match in the text of the message
+    pub range: Range<usize>
+}
+
+impl Search {
+    pub fn new() -> Self {
+        Self { query: Input::default(), regex: false, editing: true, current: None, pattern: Ok(None) }
+    }
+
+    pub fn query(&self) -> &Input {
+        &self.query
+    }
+
+    pub fn is_regex(&self) -> bool {
+        self.regex
+    }
+
+    /// Edit the query, which starts over at the last match
+    pub fn edit_query(&mut self, edit: impl FnOnce(&mut Input)) {
+        edit(&mut self.query);
+        self.compile();
+    }
+
+    /// Switch between a regular expression and plain text, which starts over at the last match
+    pub fn toggle_regex(&mut self) {
+        self.regex = !self.regex;
+        self.compile();
+    }
+
+    /// The pattern of the query, or `None` if the query is empty
+    ///
+    /// The case is ignored unless the query contains capitals. Fails if the query is an invalid
+    /// regular expression.
+    pub fn pattern(&self) -> Result<Option<&Regex>, &str> {
+        self.pattern.as_ref().map(Option::as_ref).map_err(String::as_str)
+    }
+
+    fn compile(&mut self) {
+        self.current = None;
+        let query = self.query.value();
+        self.pattern = if query.is_empty() {
+            Ok(None)
+        } else {
+            let pattern = if self.regex { query.to_string() } else { regex::escape(query) };
+            RegexBuilder::new(&pattern)
+                .case_insensitive(!query.chars().any(char::is_uppercase))
+                .build()
+                .map(Some)
+                .map_err(|err| err.to_string())
+        };
+    }
+
+    /// The matches in the text of the messages, from the top; empty matches are skipped
+    pub fn matches(&self, messages: &[Message]) -> Vec<SearchMatch> {
+        let Ok(Some(pattern)) = self.pattern() else {
+            return vec![];
+        };
+        messages.iter().enumerate()
+            .flat_map(|(message, content)| {
+                let text = content.text();
+                pattern.find_iter(&text)
+                    .filter(|found| !found.is_empty())
+                    .map(|found| SearchMatch { message, range: found.range() })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// The index of the current match among `count` matches
+    pub fn current_match(&self, count: usize) -> Option<usize> {
+        match self.current {
+            _ if count == 0 => None,
+            Some(current) => Some(current.min(count - 1)),
+            None => Some(count - 1)
+        }
+    }
+
+    pub fn next(&mut self, count: usize) {
+        if let Some(current) = self.current_match(count) {
+            self.current = Some((current + 1) % count);
+        }
+    }
+
+    pub fn previous(&mut self, count: usize) {
+        if let Some(current) = self.current_match(count) {
+            self.current = Some((current + count - 1) % count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lliminal::llm::{AssistantMessageContent, AssistantMessagePart, Message, UserMessageContent, UserMessagePart};
+    use tui_input::{Input, InputRequest};
+
+    use super::{Search, SearchMatch};
+
+    fn search(query: &str) -> Search {
+        let mut search = Search::new();
+        search.edit_query(|input| *input = Input::new(query.to_string()));
+        search
+    }
+
+    fn messages() -> Vec<Message> {
+        vec![
+            Message::User { parts: vec![UserMessagePart { content: UserMessageContent::Text { text: "Find a.b".to_string() } }] },
+            Message::Assistant { parts: vec![AssistantMessagePart { complete: true, content: AssistantMessageContent::Text { text: "**A** find, and AxB".to_string() } }] }
+        ]
+    }
+
+    #[test]
+    fn finds_matches_in_the_text_of_the_messages() {
+        let matches = search("find").matches(&messages());
+
+        assert_eq!(matches, vec![SearchMatch { message: 0, range: 0..4 }, SearchMatch { message: 1, range: 6..10 }]);
+        assert_eq!(search("Find").matches(&messages()), vec![SearchMatch { message: 0, range: 0..4 }]);
+    }
+
+    #[test]
+    fn escapes_plain_text_unless_the_query_is_a_regex() {
+        let mut search = search("a.b");
+
+        assert_eq!(search.matches(&messages()), vec![SearchMatch { message: 0, range: 5..8 }]);
+        search.toggle_regex();
+        assert_eq!(search.matches(&messages()).len(), 2);
+        search.edit_query(|input| { input.handle(InputRequest::InsertChar('(')); });
+        assert!(search.pattern().is_err());
+        assert!(search.matches(&messages()).is_empty());
+    }
+
+    #[test]
+    fn moves_between_matches_and_starts_over_when_the_query_changes() {
+        let mut search = search("a");
+
+        assert_eq!(search.current_match(3), Some(2));
+        search.next(3);
+        assert_eq!(search.current_match(3), Some(0));
+        search.previous(3);
+        search.previous(3);
+        assert_eq!(search.current_match(3), Some(1));
+        search.edit_query(|input| { input.handle(InputRequest::InsertChar('b')); });
+        assert_eq!(search.current, None);
+        assert_eq!(search.current_match(0), None);
+    }
+}
@@ -0,0 +1,122 @@
+use chrono::{DateTime, Utc};
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use tui_input::Input;
+
+/// The list of saved sessions next to the chat
+#[derive(Clone, Debug, Default)]
+pub struct Sidebar {
+    pub visible: bool,
+    pub sessions: Vec<SessionEntry>,
+    pub filter: Input,
+    pub mode: SidebarMode,
+    /// The index of the selected session within the filtered sessions
+    pub selected: usize
+}
+
+#[derive(Clone, Debug, Default)]
+pub enum SidebarMode {
+    #[default]
+    Browse,
+    /// The filter is being edited
+    Filter,
+    /// The selected session is being renamed
+    Rename(Input),
+    /// The deletion of the selected session awaits confirmation
+    ConfirmDelete
+}
+
+#[derive(Clone, Debug)]
+pub struct SessionEntry {
+    pub id: String,
+    pub title: String,
+    pub model: String,
+    pub updated_at: DateTime<Utc>
+}
+
+impl Sidebar {
+    /// The sessions matching the filter, best matches first
+    pub fn filtered_sessions(&self) -> Vec<&SessionEntry> {
+        if self.filter.value().is_empty() {
+            return self.sessions.iter().collect();
+        }
+        let matcher = SkimMatcherV2::default();
+        let mut matches: Vec<(i64, &SessionEntry)> = self.sessions.iter()
+            .filter_map(|session| matcher.fuzzy_match(&session.title, self.filter.value()).map(|score| (score, session)))
+            .collect();
+        matches.sort_by_key(|(score, _)| -score);
+        matches.into_iter().map(|(_, session)| session).collect()
+    }
+
+    pub fn selected_session(&self) -> Option<&SessionEntry> {
+        self.filtered_sessions().get(self.selected).copied()
+    }
+
+    pub fn select_next(&mut self) {
+        let count = self.filtered_sessions().len();
+        self.selected = (self.selected + 1).min(count.saturating_sub(1));
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// Keep the selection within the filtered sessions
+    pub fn clamp_selection(&mut self) {
+        self.selected = self.selected.min(self.filtered_sessions().len().saturating_sub(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use tui_input::Input;
+
+    use super::{SessionEntry, Sidebar};
+
+    fn sidebar(titles: &[&str]) -> Sidebar {
+        let sessions = titles.iter().enumerate().map(|(index, title)| SessionEntry {
+            id: index.to_string(),
+            title: title.to_string(),
+            model: "model".to_string(),
+            updated_at: Utc::now()
+        }).collect();
+        Sidebar { sessions, ..Sidebar::default() }
+    }
+
+    fn titles(sidebar: &Sidebar) -> Vec<&str> {
+        sidebar.filtered_sessions().iter().map(|session| session.title.as_str()).collect()
+    }
+
+    #[test]
+    fn shows_the_best_matches_of_the_filter_first() {
+        let mut sidebar = sidebar(&["The real issue of pay", "Rust lifetimes", "Trip to Rome"]);
+        assert_eq!(titles(&sidebar), ["The real issue of pay", "Rust lifetimes", "Trip to Rome"]);
+
+        sidebar.filter = Input::new("trip".to_string());
+        assert_eq!(titles(&sidebar), ["Trip to Rome", "The real issue of pay"]);
+        sidebar.filter = Input::new("rome".to_string());
+        assert_eq!(titles(&sidebar), ["Trip to Rome"]);
+    }
+
+    #[test]
+    fn keeps_the_selection_within_the_filtered_sessions() {
+        let mut sidebar = sidebar(&["Rust lifetimes", "Trip to Rome", "Rust"]);
+        sidebar.select_next();
+        sidebar.select_next();
+        sidebar.select_next();
+        assert_eq!(sidebar.selected, 2);
+
+        sidebar.filter = Input::new("rome".to_string());
+        sidebar.clamp_selection();
+        assert_eq!(sidebar.selected, 0);
+        assert_eq!(sidebar.selected_session().unwrap().title, "Trip to Rome");
+        sidebar.select_previous();
+        assert_eq!(sidebar.selected, 0);
+
+        sidebar.filter = Input::new("nothing".to_string());
+        sidebar.clamp_selection();
+        sidebar.select_next();
+        assert_eq!(sidebar.selected, 0);
+        assert!(sidebar.selected_session().is_none());
+    }
+}
@@ -0,0 +1,161 @@
+/// A command which is entered in the input, starting with a slash
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SlashCommand {
+    pub kind: SlashCommandKind,
+    pub name: &'static str,
+    /// The arguments, in brackets if they are optional
+    pub usage: &'static str,
+    pub description: &'static str
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlashCommandKind {
+    Model,
+    System,
+    Clear,
+    New,
+    Save,
+    Load,
+    Delete,
+    Export,
+    Retry,
+    Copy,
+    Help
+}
+
+/// All slash commands, in the order in which they are listed
+pub const SLASH_COMMANDS: &[SlashCommand] = &[
+    SlashCommand { kind: SlashCommandKind::Model, name: "model", usage: "[name]", description: "Show or change the model of the chat" },
+    SlashCommand { kind: SlashCommandKind::System, name: "system", usage: "[prompt]", description: "Set the system prompt, or remove it if no prompt is given" },
+    SlashCommand { kind: SlashCommandKind::Clear, name: "clear", usage: "", description: "Continue in an empty chat with the same model and system prompt; the current chat stays saved" },
+    SlashCommand { kind: SlashCommandKind::New, name: "new", usage: "", description: "Start a new chat" },
+    SlashCommand { kind: SlashCommandKind::Save, name: "save", usage: "[title]", description: "Save the chat, optionally under a new title" },
+    SlashCommand { kind: SlashCommandKind::Load, name: "load", usage: "<session>", description: "Open a saved chat by its identifier or title" },
+    SlashCommand { kind: SlashCommandKind::Delete, name: "delete", usage: "", description: "Delete the chat and its saved session, after confirming it" },
+    SlashCommand { kind: SlashCommandKind::Export, name: "export", usage: "<path>", description: "Export the chat as Markdown, HTML or JSON, depending on the extension" },
+    SlashCommand { kind: SlashCommandKind::Retry, name: "retry", usage: "", description: "Send the last request again" },
+    SlashCommand { kind: SlashCommandKind::Copy, name: "copy", usage: "[code block]", description: "Copy the last response, or one of its numbered code blocks" },
+    SlashCommand { kind: SlashCommandKind::Help, name: "help", usage: "[command]", description: "List the commands; start a message with // to send a literal slash" },
+];
+
+/// A slash command as entered in the input
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SlashInput<'a> {
+    /// The name as typed, without the slash
+    pub name: &'a str,
+    /// The arguments, trimmed; `None` while the name is still being typed
+    pub arguments: Option<&'a str>
+}
+
+impl SlashCommand {
+    pub fn find(name: &str) -> Option<&'static SlashCommand> {
+        SLASH_COMMANDS.iter().find(|command| command.name == name)
+    }
+
+    /// The commands whose name starts with `prefix`
+    pub fn matching(prefix: &str) -> Vec<&'static SlashCommand> {
+        SLASH_COMMANDS.iter().filter(|command| command.name.starts_with(prefix)).collect()
+    }
+
+    /// A one-line help text
+    pub fn help(&self) -> String {
+        let usage = if self.usage.is_empty() { String::new() } else { format!(" {}", self.usage) };
+        format!("/{}{usage}  {}", self.name, self.description)
+    }
+
+    /// Whether the arguments satisfy the usage of the command
+    pub fn accepts(&self, arguments: &str) -> bool {
+        match self.usage.chars().next() {
+            None => arguments.is_empty(),
+            Some('<') => !arguments.is_empty(),
+            _ => true
+        }
+    }
+}
+
+impl<'a> SlashInput<'a> {
+    /// Parse input which starts with a single slash
+    pub fn parse(input: &'a str) -> Option<Self> {
+        let command = input.strip_prefix('/').filter(|command| !command.starts_with('/'))?;
+        match command.split_once(char::is_whitespace) {
+            Some((name, arguments)) => Some(Self { name, arguments: Some(arguments.trim()) }),
+            None => Some(Self { name: command, arguments: None })
+        }
+    }
+}
+
+/// Complete `input` to the longest common prefix of the candidates which start with it
+pub fn complete<'a>(input: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let mut matches = candidates.into_iter().filter(|candidate| candidate.starts_with(input));
+    let first = matches.next()?;
+    let common = matches.fold(first, |common, candidate| {
+        let length = common.char_indices()
+            .zip(candidate.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map_or(0, |((index, char), _)| index + char.len_utf8());
+        &common[..length]
+    });
+    Some(common.to_string()).filter(|common| common.len() > input.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{complete, SlashCommand, SlashCommandKind, SlashInput};
+
+    #[test]
+    fn parses_name_while_it_is_typed() {
+        assert_eq!(SlashInput::parse("/mo"), Some(SlashInput { name: "mo", arguments: None }));
+        assert_eq!(SlashInput::parse("/"), Some(SlashInput { name: "", arguments: None }));
+    }
+
+    #[test]
+    fn parses_trimmed_arguments() {
+        assert_eq!(SlashInput::parse("/model  claude-opus-4-1 "), Some(SlashInput { name: "model", arguments: Some("claude-opus-4-1") }));
+        assert_eq!(SlashInput::parse("/save "), Some(SlashInput { name: "save", arguments: Some("") }));
+        assert_eq!(SlashInput::parse("/system Be\nbrief"), Some(SlashInput { name: "system", arguments: Some("Be\nbrief") }));
+    }
+
+    #[test]
+    fn does_not_parse_messages_or_double_slashes() {
+        assert_eq!(SlashInput::parse("Hello /model"), None);
+        assert_eq!(SlashInput::parse("//model"), None);
+    }
+
+    #[test]
+    fn checks_arguments_against_usage() {
+        let find = |name| SlashCommand::find(name).unwrap();
+
+        assert!(find("clear").accepts(""));
+        assert!(!find("clear").accepts("all"));
+        assert!(find("load").accepts("Notes"));
+        assert!(!find("load").accepts(""));
+        assert!(find("save").accepts(""));
+        assert_eq!(find("delete").kind, SlashCommandKind::Delete);
+    }
+
+    #[test]
+    fn lists_matching_commands() {
+        let names: Vec<&str> = SlashCommand::matching("s").iter().map(|command| command.name).collect();
+
+        assert_eq!(names, vec!["system", "save"]);
+    }
+
+    #[test]
+    fn completes_to_common_prefix() {
+        assert_eq!(complete("cl", ["claude-opus-4-0", "claude-opus-4-1", "gpt"]), Some("claude-opus-4-".to_string()));
+        assert_eq!(complete("h", ["help "]), Some("help ".to_string()));
+    }
+
+    #[test]
+    fn does_not_complete_without_progress() {
+        assert_eq!(complete("claude-opus-4-", ["claude-opus-4-0", "claude-opus-4-1"]), None);
+        assert_eq!(complete("x", ["model", "system"]), None);
+        assert_eq!(complete("help", ["help"]), None);
+    }
+
+    #[test]
+    fn completes_multibyte_characters() {
+        assert_eq!(complete("N", ["Notizen über A", "Notizen über B"]), Some("Notizen über ".to_string()));
+    }
+}
@@ -0,0 +1,355 @@
+use std::collections::BTreeMap;
+
+use lliminal::llm::Message;
+use serde::{Deserialize, Serialize};
+
+/// The messages of a chat, with alternative branches where messages were edited or regenerated
+///
+/// Every message can have several follow-ups, of which one is active. The active path starts
+/// with the active first message and follows the active follow-ups; it is the conversation which
+/// is shown and sent to the model. Positions always refer to the active path.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageTree {
+    nodes: BTreeMap<usize, MessageNode>,
+    /// The alternative first messages
+    roots: Vec<usize>,
+    active_root: Option<usize>,
+    next_id: usize
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct MessageNode {
+    message: Message,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    active_child: Option<usize>,
+    /// Whether the message is always kept when the conversation is shortened to fit the context window
+    #[serde(default)]
+    pinned: bool
+}
+
+impl From<Vec<Message>> for MessageTree {
+    fn from(messages: Vec<Message>) -> Self {
+        let mut tree = Self::default();
+        for message in messages {
+            tree.push(message);
+        }
+        tree
+    }
+}
+
+impl MessageTree {
+    /// The node identifiers of the active path
+    fn path(&self) -> Vec<usize> {
+        let mut path = vec![];
+        let mut current = self.active_root;
+        while let Some(id) = current {
+            path.push(id);
+            current = self.nodes[&id].active_child;
+        }
+        path
+    }
+
+    /// The messages of the active path
+    pub fn messages(&self) -> Vec<Message> {
+        self.messages_from(0)
+    }
+
+    /// The messages of the active path from `position` on
+    pub fn messages_from(&self, position: usize) -> Vec<Message> {
+        self.path().iter().skip(position).map(|id| self.nodes[id].message.clone()).collect()
+    }
+
+    /// Whether there are no messages, not even in inactive branches
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Append a message to the active path
+    pub fn push(&mut self, message: Message) {
+        let parent = self.path().last().copied();
+        let id = self.next_id;
+        self.next_id += 1;
+        self.nodes.insert(id, MessageNode { message, parent, children: vec![], active_child: None, pinned: false });
+        self.children_mut(parent).push(id);
+        self.set_active(parent, Some(id));
+    }
+
+    /// Replace the messages of the active path from `position` on; any further messages are removed
+    pub fn replace_from(&mut self, position: usize, messages: &[Message]) {
+        let path = self.path();
+        for (offset, message) in messages.iter().enumerate() {
+            match path.get(position + offset) {
+                Some(id) => self.nodes.get_mut(id).expect("Path contains unknown node").message = message.clone(),
+                None => self.push(message.clone())
+            }
+        }
+        if let Some(id) = path.get(position + messages.len()) {
+            self.remove_subtree(*id);
+        }
+    }
+
+    /// End the active path before `position`, keeping the rest as an inactive branch
+    ///
+    /// The next pushed message becomes an alternative to the message at `position`.
+    pub fn branch(&mut self, position: usize) {
+        let path = self.path();
+        if position < path.len() {
+            self.set_active(parent_at(&path, position), None);
+        }
+    }
+
+    /// Remove the message at `position` and everything after it
+    pub fn truncate(&mut self, position: usize) {
+        if let Some(id) = self.path().get(position) {
+            self.remove_subtree(*id);
+        }
+    }
+
+    /// Remove the message at `position`, keeping its follow-ups
+    pub fn remove(&mut self, position: usize) {
+        let Some(id) = self.path().get(position).copied() else {
+            return;
+        };
+        let node = self.nodes.remove(&id).expect("Path contains unknown node");
+        for child in &node.children {
+            self.nodes.get_mut(child).expect("Node has unknown child").parent = node.parent;
+        }
+        let siblings = self.children_mut(node.parent);
+        if let Some(index) = siblings.iter().position(|sibling| *sibling == id) {
+            siblings.splice(index..=index, node.children.iter().copied());
+        }
+        if self.active(node.parent) == Some(id) {
+            self.set_active(node.parent, node.active_child);
+        }
+    }
+
+    /// The index of the active alternative at `position` and the number of alternatives
+    pub fn alternatives(&self, position: usize) -> (usize, usize) {
+        let path = self.path();
+        let alternatives = self.children(parent_at(&path, position));
+        let index = path.get(position)
+            .and_then(|id| alternatives.iter().position(|alternative| alternative == id))
+            .unwrap_or_default();
+        (index, alternatives.len())
+    }
+
+    /// The index of the active alternative and the number of alternatives at each position of the active path
+    pub fn path_alternatives(&self) -> Vec<(usize, usize)> {
+        let path = self.path();
+        path.iter().enumerate().map(|(position, id)| {
+            let alternatives = self.children(parent_at(&path, position));
+            let index = alternatives.iter().position(|alternative| alternative == id).unwrap_or_default();
+            (index, alternatives.len())
+        }).collect()
+    }
+
+    /// Whether each message of the active path is pinned
+    pub fn path_pinned(&self) -> Vec<bool> {
+        self.path().iter().map(|id| self.nodes[id].pinned).collect()
+    }
+
+    /// Pin the message at `position`, or unpin it if it is pinned
+    pub fn toggle_pinned(&mut self, position: usize) {
+        if let Some(id) = self.path().get(position) {
+            let node = self.nodes.get_mut(id).expect("Path contains unknown node");
+            node.pinned = !node.pinned;
+        }
+    }
+
+    /// Activate the next or previous alternative at `position`
+    pub fn cycle(&mut self, position: usize, forward: bool) {
+        let (index, count) = self.alternatives(position);
+        if count < 2 {
+            return;
+        }
+        let next = if forward { (index + 1) % count } else { (index + count - 1) % count };
+        let parent = parent_at(&self.path(), position);
+        let alternative = self.children(parent)[next];
+        self.set_active(parent, Some(alternative));
+    }
+
+    fn remove_subtree(&mut self, id: usize) {
+        let Some(node) = self.nodes.remove(&id) else {
+            return;
+        };
+        self.children_mut(node.parent).retain(|child| *child != id);
+        if self.active(node.parent) == Some(id) {
+            self.set_active(node.parent, None);
+        }
+        let mut descendants = node.children;
+        while let Some(descendant) = descendants.pop() {
+            if let Some(node) = self.nodes.remove(&descendant) {
+                descendants.extend(node.children);
+            }
+        }
+    }
+
+    fn children(&self, parent: Option<usize>) -> &[usize] {
+        match parent {
+            Some(parent) => &self.nodes[&parent].children,
+            None => &self.roots
+        }
+    }
+
+    fn children_mut(&mut self, parent: Option<usize>) -> &mut Vec<usize> {
+        match parent {
+            Some(parent) => &mut self.nodes.get_mut(&parent).expect("Unknown parent node").children,
+            None => &mut self.roots
+        }
+    }
+
+    fn active(&self, parent: Option<usize>) -> Option<usize> {
+        match parent {
+            Some(parent) => self.nodes[&parent].active_child,
+            None => self.active_root
+        }
+    }
+
+    fn set_active(&mut self, parent: Option<usize>, child: Option<usize>) {
+        match parent {
+            Some(parent) => self.nodes.get_mut(&parent).expect("Unknown parent node").active_child = child,
+            None => self.active_root = child
+        }
+    }
+}
+
+/// The parent of the node at `position` of the path
+fn parent_at(path: &[usize], position: usize) -> Option<usize> {
+    position.checked_sub(1).and_then(|previous| path.get(previous).copied())
+}
+
+#[cfg(test)]
+mod tests {
+    use lliminal::llm::{Message, UserMessageContent, UserMessagePart};
+
+    use super::MessageTree;
+
+    fn message(text: &str) -> Message {
+        Message::User { parts: vec![UserMessagePart { content: UserMessageContent::Text { text: text.to_string() } }] }
+    }
+
+    fn texts(tree: &MessageTree) -> Vec<String> {
+        tree.messages().iter().map(Message::text).collect()
+    }
+
+    fn tree(texts: &[&str]) -> MessageTree {
+        MessageTree::from(texts.iter().map(|text| message(text)).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn branch_adds_alternative_and_keeps_old_branch() {
+        let mut tree = tree(&["a", "b", "c"]);
+
+        tree.branch(1);
+        tree.push(message("b2"));
+
+        assert_eq!(texts(&tree), vec!["a", "b2"]);
+        assert_eq!(tree.alternatives(1), (1, 2));
+        tree.cycle(1, false);
+        assert_eq!(texts(&tree), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn branch_at_first_message_adds_root() {
+        let mut tree = tree(&["a", "b"]);
+
+        tree.branch(0);
+        tree.push(message("a2"));
+
+        assert_eq!(texts(&tree), vec!["a2"]);
+        assert_eq!(tree.path_alternatives(), vec![(1, 2)]);
+    }
+
+    #[test]
+    fn cycle_wraps_around_alternatives() {
+        let mut tree = tree(&["a", "b"]);
+        tree.branch(1);
+        tree.push(message("b2"));
+        tree.branch(1);
+        tree.push(message("b3"));
+
+        tree.cycle(1, true);
+        assert_eq!(texts(&tree), vec!["a", "b"]);
+        tree.cycle(1, false);
+        assert_eq!(texts(&tree), vec!["a", "b3"]);
+        tree.cycle(0, true);
+        assert_eq!(texts(&tree), vec!["a", "b3"]);
+    }
+
+    #[test]
+    fn remove_keeps_follow_ups() {
+        let mut tree = tree(&["a", "b", "c"]);
+        tree.branch(2);
+        tree.push(message("c2"));
+
+        tree.remove(1);
+
+        assert_eq!(texts(&tree), vec!["a", "c2"]);
+        assert_eq!(tree.path_alternatives(), vec![(0, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn remove_last_alternative_activates_its_parent_path_end() {
+        let mut tree = tree(&["a", "b"]);
+
+        tree.remove(1);
+        tree.remove(0);
+
+        assert!(tree.is_empty());
+        assert!(texts(&tree).is_empty());
+    }
+
+    #[test]
+    fn replace_from_updates_appends_and_removes() {
+        let mut tree = tree(&["a", "b", "c", "d"]);
+
+        tree.replace_from(1, &[message("b2"), message("c2")]);
+        assert_eq!(texts(&tree), vec!["a", "b2", "c2"]);
+
+        tree.replace_from(3, &[message("d2"), message("e2")]);
+        assert_eq!(texts(&tree), vec!["a", "b2", "c2", "d2", "e2"]);
+
+        tree.replace_from(1, &[]);
+        assert_eq!(texts(&tree), vec!["a"]);
+    }
+
+    #[test]
+    fn replace_from_keeps_other_branches() {
+        let mut tree = tree(&["a", "b"]);
+        tree.branch(1);
+        tree.push(message("b2"));
+
+        tree.replace_from(1, &[message("b3")]);
+
+        assert_eq!(texts(&tree), vec!["a", "b3"]);
+        tree.cycle(1, false);
+        assert_eq!(texts(&tree), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn path_alternatives_of_each_position() {
+        let mut tree = tree(&["a", "b", "c"]);
+        tree.branch(2);
+        tree.push(message("c2"));
+        tree.branch(2);
+        tree.push(message("c3"));
+
+        assert_eq!(tree.path_alternatives(), vec![(0, 1), (0, 1), (2, 3)]);
+    }
+
+    #[test]
+    fn pins_messages_of_the_active_path() {
+        let mut tree = tree(&["a", "b"]);
+
+        tree.toggle_pinned(1);
+        assert_eq!(tree.path_pinned(), vec![false, true]);
+        tree.branch(1);
+        tree.push(message("b2"));
+        assert_eq!(tree.path_pinned(), vec![false, false]);
+        tree.cycle(1, false);
+        assert_eq!(tree.path_pinned(), vec![false, true]);
+        tree.toggle_pinned(1);
+        assert_eq!(tree.path_pinned(), vec![false, false]);
+    }
+}